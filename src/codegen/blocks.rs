@@ -0,0 +1,81 @@
+//! CatWeb action IDs emitted by the compiler.
+//!
+//! These mirror the IDs CatWeb uses in exported site JSONs. Keep every ID used by the compiler in here,
+//! so a CatWeb update only needs to be reflected in one place.
+
+pub const LOG: &str = "0";
+pub const SET_VARIABLE: &str = "11";
+pub const INCREASE_VARIABLE: &str = "12";
+pub const SUBTRACT_VARIABLE: &str = "13";
+pub const MULTIPLY_VARIABLE: &str = "14";
+pub const DIVIDE_VARIABLE: &str = "15";
+pub const POWER_VARIABLE: &str = "40";
+pub const RUN_FUNCTION: &str = "87";
+/// Pauses the script for a number of seconds
+pub const WAIT: &str = "22";
+/// Comment, which does nothing when run
+pub const COMMENT: &str = "124";
+
+/// UI objects
+pub const SET_PROPERTY: &str = "31";
+pub const GET_PROPERTY: &str = "32";
+
+/// Tables. The table is always the first argument.
+pub const CREATE_TABLE: &str = "100";
+pub const SET_ENTRY: &str = "101";
+pub const GET_ENTRY: &str = "102";
+pub const TABLE_LENGTH: &str = "103";
+pub const INSERT_ENTRY: &str = "104";
+pub const REMOVE_ENTRY: &str = "105";
+
+/// Control flow. Every block opened by an `IF_*` or loop action is closed by `END`.
+pub const IF_EQUAL: &str = "18";
+pub const ITERATE_TABLE: &str = "106";
+pub const END: &str = "25";
+
+/// Actions whose following actions up to the matching `END` form their body
+pub const BLOCK_OPENERS: &[&str] = &[IF_EQUAL, ITERATE_TABLE];
+/// Actions which run their body repeatedly
+pub const LOOPS: &[&str] = &[ITERATE_TABLE];
+
+/// Code card IDs
+pub const FUNCTION_DECLARATION: &str = "6";
+/// Event card which runs when the website has loaded
+pub const WEBSITE_LOADED: &str = "0";
+
+/// Name of an action in CatWeb's editor, used in listings of scripts
+pub fn action_name(id: &str) -> Option<&'static str> {
+  let name = match id {
+    LOG => "log",
+    SET_VARIABLE => "set variable",
+    INCREASE_VARIABLE => "increase variable",
+    SUBTRACT_VARIABLE => "subtract from variable",
+    MULTIPLY_VARIABLE => "multiply variable",
+    DIVIDE_VARIABLE => "divide variable",
+    POWER_VARIABLE => "raise variable to power",
+    RUN_FUNCTION => "run function",
+    WAIT => "wait",
+    COMMENT => "comment",
+    SET_PROPERTY => "set property",
+    GET_PROPERTY => "get property",
+    CREATE_TABLE => "create table",
+    SET_ENTRY => "set entry",
+    GET_ENTRY => "get entry",
+    TABLE_LENGTH => "get table length",
+    INSERT_ENTRY => "insert entry",
+    REMOVE_ENTRY => "remove entry",
+    IF_EQUAL => "if equal",
+    ITERATE_TABLE => "iterate table",
+    END => "end",
+    _ => return None,
+  };
+  Some(name)
+}
+
+/// Name of an event in CatWeb's editor, used in listings of scripts
+pub fn event_name(id: &str) -> Option<&'static str> {
+  match id {
+    WEBSITE_LOADED => Some("website loaded"),
+    _ => None,
+  }
+}
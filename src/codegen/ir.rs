@@ -0,0 +1,306 @@
+//! Textual format of the lowered program, which is what `lync build --emit=ir` prints.
+//!
+//! ```text
+//! event 0 {
+//!   with {
+//!     greet("world")
+//!   } do {}
+//! }
+//!
+//! function greet(greet$name) {
+//!   console.log(`Hi {greet$name}`)
+//! }
+//! ```
+//!
+//! Calls are written `#id(arguments)` for CatWeb actions and `name(arguments)` for functions, followed by
+//! `-> variable` if they return a value. `with { ... }` in front of a statement or call lists its dependencies, and
+//! `do { ... }` the calls of a statement which doesn't consist of exactly one call. `comment "text"` lines in front of
+//! a statement or function are its comments. Arguments are literals (`"text"`),
+//! raw strings (`#"text"`), variables which are read (`name`) or written to (`&name`), templates (`` `Hi {name}` ``)
+//! and UI objects (`@"globalid"`). Strings are quoted like JSON strings, and names with characters other than letters,
+//! digits, `_`, `$`, `%` and `.` are quoted with single quotes. [parse_program] parses the format back.
+
+use std::fmt::{self, Display, Formatter};
+
+use pest::{Parser as _Parser, iterators::Pair, iterators::Pairs};
+use pest_derive::Parser as _Parser;
+
+use super::structures::*;
+
+const INDENT: &str = "  ";
+
+#[derive(_Parser)]
+#[grammar = "codegen/ir.pest"] // relative to src
+struct IrParser;
+
+/// Quotes text as a JSON string
+fn quote(text: &str) -> String {
+  serde_json::to_string(text).unwrap()
+}
+
+/// Name of a variable or function, which is quoted if it isn't plain
+struct Name<'a>(&'a str);
+
+impl Display for Name<'_> {
+  fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+    let is_plain = !self.0.is_empty()
+      && self.0.chars().all(|char| char.is_ascii_alphanumeric() || matches!(char, '_' | '$' | '%' | '.'));
+    match is_plain {
+      true => write!(f, "{}", self.0),
+      false => write!(f, "'{}'", self.0.replace('\\', "\\\\").replace('\'', "\\'")),
+    }
+  }
+}
+
+impl Display for Program {
+  fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+    for (index, item) in self.main_block.iter().enumerate() {
+      if index > 0 {
+        writeln!(f)?;
+      }
+      write!(f, "{}", item)?;
+    }
+    Ok(())
+  }
+}
+
+impl Display for Item {
+  fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+    let body = match self {
+      Item::FunctionDeclaration { name, parameters, body, comments, .. } => {
+        for comment in comments {
+          writeln!(f, "comment {}", quote(comment))?;
+        }
+        let parameters: Vec<String> = parameters.iter().map(|parameter| Name(&parameter.name).to_string()).collect();
+        writeln!(f, "function {}({}) {{", Name(name), parameters.join(", "))?;
+        body
+      },
+      Item::Event { name, body } => {
+        writeln!(f, "event {} {{", Name(name))?;
+        body
+      },
+    };
+    for statement in body {
+      write_statement(f, statement, 1)?;
+    }
+    writeln!(f, "}}")
+  }
+}
+
+impl Display for Statement {
+  fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+    write_statement(f, self, 0)
+  }
+}
+
+impl Display for Call {
+  fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+    write_call(f, self, 0)
+  }
+}
+
+impl Display for Argument {
+  fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+    match self {
+      Argument::RawString(text) => write!(f, "#{}", quote(text)),
+      Argument::Literal(literal) => write!(f, "{}", quote(&literal.value)),
+      Argument::Identifier(variable) => write!(f, "{}", Name(&variable.name)),
+      Argument::VariableName(variable) => write!(f, "&{}", Name(&variable.name)),
+      Argument::Template(parts) => {
+        write!(f, "`")?;
+        for part in parts {
+          match part {
+            TemplatePart::Text(text) => for char in text.chars() {
+              if matches!(char, '`' | '\\' | '{' | '}') {
+                write!(f, "\\")?;
+              }
+              write!(f, "{}", char)?;
+            },
+            TemplatePart::Variable(variable) => write!(f, "{{{}}}", Name(&variable.name))?,
+          }
+        }
+        write!(f, "`")
+      },
+      Argument::Object(globalid) => write!(f, "@{}", quote(globalid)),
+    }
+  }
+}
+
+/// Writes a statement on its own lines, indented `depth` levels
+fn write_statement(f: &mut Formatter, statement: &Statement, depth: usize) -> fmt::Result {
+  for comment in &statement.comments {
+    writeln!(f, "{}comment {}", INDENT.repeat(depth), quote(comment))?;
+  }
+  write!(f, "{}", INDENT.repeat(depth))?;
+  if !statement.dependencies.is_empty() {
+    write_block(f, "with", &statement.dependencies, depth)?;
+    write!(f, " ")?;
+  }
+  match statement.content.as_slice() {
+    [call] if call.dependencies().is_empty() => write_call(f, call, depth)?,
+    calls => write_block(f, "do", calls, depth)?,
+  }
+  writeln!(f)
+}
+
+/// Writes a call, starting at the current column, with its dependencies indented `depth + 1` levels
+fn write_call(f: &mut Formatter, call: &Call, depth: usize) -> fmt::Result {
+  if !call.dependencies().is_empty() {
+    write_block(f, "with", call.dependencies(), depth)?;
+    write!(f, " ")?;
+  }
+  let (arguments, return_var) = match call {
+    Call::FunctionCall { function_name, arguments, return_var, .. } => {
+      write!(f, "{}(", Name(&function_name.name))?;
+      (arguments, return_var)
+    },
+    Call::CWScriptBlockCall { block_id, arguments, return_var, .. } => {
+      write!(f, "#{}(", block_id.id)?;
+      (arguments, return_var)
+    },
+  };
+  for (index, argument) in arguments.iter().enumerate() {
+    if index > 0 {
+      write!(f, ", ")?;
+    }
+    write!(f, "{}", argument)?;
+  }
+  write!(f, ")")?;
+  if let Some(variable) = return_var {
+    write!(f, " -> {}", Name(&variable.name))?;
+  }
+  Ok(())
+}
+
+/// Writes `keyword {`, the calls on their own lines and the closing brace, without a trailing newline
+fn write_block(f: &mut Formatter, keyword: &str, calls: &[Call], depth: usize) -> fmt::Result {
+  if calls.is_empty() {
+    return write!(f, "{} {{}}", keyword);
+  }
+  writeln!(f, "{} {{", keyword)?;
+  for call in calls {
+    write!(f, "{}", INDENT.repeat(depth + 1))?;
+    write_call(f, call, depth + 1)?;
+    writeln!(f)?;
+  }
+  write!(f, "{}}}", INDENT.repeat(depth))
+}
+
+/// Parses a program from its textual format
+pub fn parse_program(input: &str) -> Result<Program, anyhow::Error> {
+  let program = IrParser::parse(Rule::program, input)?.next().expect("Program should match once");
+  let items = program.into_inner()
+    .filter(|pair| pair.as_rule() != Rule::EOI)
+    .map(parse_item)
+    .collect::<Result<Vec<Item>, anyhow::Error>>()?;
+  Ok(Program::new(items))
+}
+
+fn parse_item(input: Pair<Rule>) -> Result<Item, anyhow::Error> {
+  let rule = input.as_rule();
+  let mut input_iter = input.into_inner();
+  let comments = parse_comments(&mut input_iter)?;
+  let name = parse_name(input_iter.next().expect("Item should have a name"));
+  match rule {
+    Rule::Function => {
+      let parameters = input_iter.next().expect("Function should have parameters").into_inner()
+        .map(|pair| Variable { name: parse_name(pair) })
+        .collect();
+      let body = input_iter.map(parse_statement).collect::<Result<Vec<Statement>, anyhow::Error>>()?;
+      Ok(Item::FunctionDeclaration { name, parameters, body, position: Default::default(), comments })
+    },
+    Rule::Event => Ok(Item::Event { name, body: input_iter.map(parse_statement).collect::<Result<Vec<Statement>, anyhow::Error>>()? }),
+    rule => unreachable!("Expected item, found {:?}", rule),
+  }
+}
+
+fn parse_statement(input: Pair<Rule>) -> Result<Statement, anyhow::Error> {
+  let mut statement = Statement::new(vec![], vec![]);
+  let mut input_iter = input.into_inner();
+  statement.comments = parse_comments(&mut input_iter)?;
+  for pair in input_iter {
+    match pair.as_rule() {
+      Rule::Dependencies => statement.dependencies = parse_calls(pair)?,
+      Rule::Content => statement.content = parse_calls(pair)?,
+      Rule::Call => statement.content = vec![parse_call(pair)?],
+      rule => unreachable!("Expected part of a statement, found {:?}", rule),
+    }
+  }
+  Ok(statement)
+}
+
+fn parse_comments(input: &mut Pairs<Rule>) -> Result<Vec<String>, anyhow::Error> {
+  let mut comments = Vec::new();
+  while input.peek().is_some_and(|pair| pair.as_rule() == Rule::Comment) {
+    comments.push(parse_string(inner(input.next().expect("Comment should be present")))?);
+  }
+  Ok(comments)
+}
+
+fn parse_calls(input: Pair<Rule>) -> Result<Vec<Call>, anyhow::Error> {
+  input.into_inner().map(parse_call).collect()
+}
+
+fn parse_call(input: Pair<Rule>) -> Result<Call, anyhow::Error> {
+  let mut input_iter = input.into_inner();
+  let dependencies = match input_iter.peek().is_some_and(|pair| pair.as_rule() == Rule::Dependencies) {
+    true => parse_calls(input_iter.next().expect("Dependencies should be present"))?,
+    false => vec![],
+  };
+  let target = input_iter.next().expect("Call should have a target");
+  let arguments = input_iter.next().expect("Call should have arguments").into_inner()
+    .map(parse_argument)
+    .collect::<Result<Vec<Argument>, anyhow::Error>>()?;
+  let return_var = input_iter.next().map(|pair| Variable { name: parse_name(pair) });
+  Ok(match target.as_rule() {
+    Rule::BlockId => Call::CWScriptBlockCall {
+      dependencies,
+      block_id: CWScriptBlockID { id: target.as_str()[1..].to_string() },
+      arguments,
+      return_var,
+    },
+    _ => Call::FunctionCall { dependencies, function_name: Variable { name: parse_name(target) }, arguments, return_var },
+  })
+}
+
+fn parse_argument(input: Pair<Rule>) -> Result<Argument, anyhow::Error> {
+  Ok(match input.as_rule() {
+    Rule::RawString => Argument::RawString(parse_string(inner(input))?),
+    Rule::string => Argument::Literal(Literal { value: parse_string(input)? }),
+    Rule::Object => Argument::Object(parse_string(inner(input))?),
+    Rule::VariableName => Argument::VariableName(Variable { name: parse_name(inner(input)) }),
+    Rule::Template => Argument::Template(input.into_inner().map(|pair| match pair.as_rule() {
+      Rule::TemplateText => TemplatePart::Text(unescape(pair.as_str())),
+      _ => TemplatePart::Variable(Variable { name: parse_name(pair) }),
+    }).collect()),
+    _ => Argument::Identifier(Variable { name: parse_name(input) }),
+  })
+}
+
+fn inner(input: Pair<Rule>) -> Pair<Rule> {
+  input.into_inner().next().expect("Argument should have content")
+}
+
+fn parse_name(input: Pair<Rule>) -> String {
+  match input.as_rule() {
+    Rule::QuotedName => unescape(&input.as_str()[1..input.as_str().len() - 1]),
+    _ => input.as_str().to_string(),
+  }
+}
+
+fn parse_string(input: Pair<Rule>) -> Result<String, anyhow::Error> {
+  serde_json::from_str(input.as_str()).map_err(|err| anyhow::anyhow!("Invalid string {}: {}", input.as_str(), err))
+}
+
+/// Removes the backslashes which escape characters of templates and quoted names
+fn unescape(text: &str) -> String {
+  let mut unescaped = String::new();
+  let mut chars = text.chars();
+  while let Some(char) = chars.next() {
+    match char {
+      '\\' => unescaped.extend(chars.next()),
+      char => unescaped.push(char),
+    }
+  }
+  unescaped
+}
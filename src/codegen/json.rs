@@ -1,6 +1,8 @@
 
 use serde::{Deserialize, Serialize};
-use serde_json;
+
+/// Versions of CatWeb whose JSON format is generated
+pub const CATWEB_VERSIONS: &[&str] = &["2.15"];
 
 /// Data structures of CatWeb JSONs, used for code generation.
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
@@ -37,7 +39,20 @@ pub struct FunctionDeclaration {
     pub id: String,
     pub text: Vec<TextFieldValue>,
     pub actions: Vec<Action>,
+    /// Description shown on the card
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+  }
+
+impl FunctionDeclaration {
+  /// Name of the function, which is its first parameter field
+  pub fn name(&self) -> &str {
+    self.text.iter().find_map(|field| match field {
+      TextFieldValue::Parameter(parameter) => Some(parameter.value.as_str()),
+      _ => None,
+    }).unwrap_or_default()
   }
+}
 
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize, Default)]
 pub struct Event {
@@ -0,0 +1,71 @@
+use super::{blocks, json};
+use super::source_map::SourceMap;
+
+/// Lists the cards of a CatWeb script and their actions in a readable form. Bodies of blocks are indented, and lines
+/// end with the source location of the card or action if the source map has one.
+///
+/// ```text
+/// function greet(greet$user)  // src/main.lxs:1:10
+///   11 set variable: "greet$x", "1"  // src/main.lxs:2:3
+/// ```
+pub fn list_script(script: &json::Script, source_map: Option<&SourceMap>) -> String {
+  let mut listing = String::new();
+  // Lines of a card or action are followed by its source location
+  let mut push_line = |line: String, location: Option<(usize, Option<usize>)>| {
+    listing.push_str(&line);
+    let mapping = location.and_then(|(card, action)| source_map.and_then(|source_map| source_map.find(card, action)));
+    if let Some(mapping) = mapping {
+      listing.push_str(&format!("  // {}", mapping.location()));
+    }
+    listing.push('\n');
+  };
+  for (card_index, card) in script.content.iter().enumerate() {
+    let actions = match card {
+      json::CodeCard::FunctionDeclaration(func) => {
+        let parameters = func.variable_overrides.iter().map(|param| param.value.as_str()).collect::<Vec<&str>>();
+        push_line(format!("function {}({})", func.name(), parameters.join(", ")), Some((card_index, None)));
+        for line in func.description.lines() {
+          push_line(format!("  /// {}", line), None);
+        }
+        &func.actions
+      },
+      json::CodeCard::Event(event) => {
+        match blocks::event_name(&event.id) {
+          Some(name) => push_line(format!("event {} ({})", event.id, name), Some((card_index, None))),
+          None => push_line(format!("event {}", event.id), Some((card_index, None))),
+        }
+        &event.actions
+      },
+    };
+
+    let mut depth = 1;
+    for (action_index, action) in actions.iter().enumerate() {
+      if action.id == blocks::END && depth > 1 {
+        depth -= 1;
+      }
+      push_line(format!("{}{}", "  ".repeat(depth), list_action(action)), Some((card_index, Some(action_index))));
+      if blocks::BLOCK_OPENERS.contains(&action.id.as_str()) {
+        depth += 1;
+      }
+    }
+  }
+  listing
+}
+
+fn list_action(action: &json::Action) -> String {
+  let name = blocks::action_name(&action.id).map(|name| format!(" {}", name)).unwrap_or_default();
+  match action.text.is_empty() {
+    true => format!("{}{}", action.id, name),
+    false => format!("{}{}: {}", action.id, name, list_fields(&action.text)),
+  }
+}
+
+/// Lists text fields. Plain text is prefixed with `#` like raw strings, and UI objects with `@`.
+fn list_fields(fields: &[json::TextFieldValue]) -> String {
+  fields.iter().map(|field| match field {
+    json::TextFieldValue::PlainText(text) => format!("#{:?}", text),
+    json::TextFieldValue::Parameter(parameter) if parameter.t == "object" => format!("@{}", parameter.value),
+    json::TextFieldValue::Parameter(parameter) => format!("{:?}", parameter.value),
+    json::TextFieldValue::Tuple(tuple) => format!("({})", list_fields(&tuple.value)),
+  }).collect::<Vec<String>>().join(", ")
+}
@@ -1,26 +1,61 @@
-use std::vec;
-
-use serde::{Deserialize, Serialize};
-use serde_json::Result;
-
+pub mod blocks;
+pub mod ir;
 pub mod json;
+pub mod listing;
+pub mod site;
+pub mod source_map;
 pub mod structures;
 pub mod symbol_table;
+mod test;
 
 pub use structures::*;
-pub use symbol_table::*;
 
+/// Escapes braces in text, so CatWeb doesn't interpolate `{name}` in it as a variable
+pub fn escape_interpolation(text: &str) -> String {
+  text.replace('{', "\\{").replace('}', "\\}")
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct CWBlockScriptGenerator {
+  annotations: Option<Annotations>,
+}
+
+/// Source whose lines are shown in comments before the actions of the statements compiled from them
 #[derive(Debug, PartialEq, Clone)]
-pub struct CWBlockScriptGenerator {}
+struct Annotations {
+  lines: Vec<String>,
+  /// Functions compiled from the standard library rather than the source, which aren't annotated
+  stdlib_functions: Vec<String>,
+}
 
 impl CWBlockScriptGenerator {
   pub fn new() -> Self {
-    Self {}
+    Self { annotations: None }
   }
 
-  pub fn generate(&self, program: Program) -> String {
-    let json_program = json::Wrapper::Script(vec![self.generate_program(program)]);
-    format!("{}", serde_json::to_string(&json_program).unwrap())
+  /// Puts a comment action with the line of `source` each statement starts at in front of its actions, e.g.
+  /// `3: let sum = a + b;`, so the script can be followed in CatWeb's editor
+  pub fn with_annotations(mut self, source: &str, stdlib_functions: &[String]) -> Self {
+    self.annotations = Some(Annotations {
+      lines: source.lines().map(|line| line.trim().to_string()).collect(),
+      stdlib_functions: stdlib_functions.to_vec(),
+    });
+    self
+  }
+
+  /// Comments put in front of the actions of a statement of the item: its annotation, then its own comments
+  pub fn comments(&self, item: &Item, statement: &Statement) -> Vec<String> {
+    let is_annotated = match item {
+      Item::FunctionDeclaration { name, .. } => self.annotations.as_ref().is_some_and(|annotations| !annotations.stdlib_functions.contains(name)),
+      Item::Event { .. } => self.annotations.is_some(),
+    };
+    // The end of a block belongs to the statement which opened it, which is already annotated
+    let is_annotated = is_annotated && !matches!(statement.content.as_slice(), [Call::CWScriptBlockCall { block_id, .. }] if block_id.id == blocks::END);
+    let annotation = self.annotations.as_ref()
+      .filter(|_| is_annotated && statement.position.is_known())
+      .and_then(|annotations| annotations.lines.get(statement.position.line - 1))
+      .map(|line| format!("{}: {}", statement.position.line, line));
+    annotation.into_iter().chain(statement.comments.iter().cloned()).collect()
   }
 
   pub fn generate_program(&self, program: Program) -> json::Script {
@@ -32,12 +67,15 @@ impl CWBlockScriptGenerator {
   }
 
   pub fn generate_item(&self, item: Item) -> json::CodeCard {
+    let comments: Vec<Vec<String>> = match &item {
+      Item::FunctionDeclaration { body, .. } | Item::Event { body, .. } => body.iter().map(|statement| self.comments(&item, statement)).collect(),
+    };
     match item {
-      Item::FunctionDeclaration { name, parameters, body } => {
+      Item::FunctionDeclaration { name, parameters, body, comments: description, .. } => {
         json::CodeCard::FunctionDeclaration(
           json::FunctionDeclaration{
             // ID: Function declaration
-            id: "6".to_string(),
+            id: blocks::FUNCTION_DECLARATION.to_string(),
             text: vec![
               json::TextFieldValue::PlainText(Default::default()), // Argument padding
               json::TextFieldValue::Parameter(json::Parameter {    // Function name
@@ -48,86 +86,103 @@ impl CWBlockScriptGenerator {
             ],
             variable_overrides: parameters.into_iter()
               .map(|param| json::FunctionParameter { value: param.name }).collect(),
-            actions: body.into_iter()
-              .map(|statement| {
-                let content = statement.content.into_iter();
-                let depedencies = statement.dependencies.into_iter();
-                // Put dependency calls before content, since dependencies should execute first
-                depedencies.map(|call|
-                  self.generate_script_block(call)
-                ).chain(
-                  content.map(|call|
-                    self.generate_script_block(call)
-                  )
-                )
-              }).flatten().collect(),
+            actions: self.generate_actions(body, comments),
             globalid: Default::default(),
+            description: description.join("\n"),
           }
         )
       },
       Item::Event { name, body } => {
         json::CodeCard::Event(
           json::Event {
-            id: todo!(),
-            text: vec![], // TODO
-            actions: vec![], // TODO
+            id: name, // TODO: Map event names to CatWeb event IDs and their parameters
+            text: vec![],
+            actions: self.generate_actions(body, comments),
             ..Default::default()
           }
-        );
-        todo!()
+        )
       }
     }
   }
 
+  /// Generates the actions of a code card, with the comments of each statement in front of its actions. Dependencies
+  /// of a statement are put before its content, since they should execute first.
+  pub fn generate_actions(&self, body: Vec<Statement>, comments: Vec<Vec<String>>) -> Vec<json::Action> {
+    body.into_iter().zip(comments)
+      .flat_map(|(statement, comments)| {
+        comments.into_iter().map(|text| self.generate_comment(text))
+          .chain(statement.into_calls().into_iter().map(|call| self.generate_script_block(call)))
+          .collect::<Vec<json::Action>>()
+      })
+      .collect()
+  }
+
+  pub fn generate_comment(&self, text: String) -> json::Action {
+    json::Action {
+      id: blocks::COMMENT.to_string(),
+      text: vec![self.generate_argument(Argument::Literal(Literal { value: text }))],
+      ..Default::default()
+    }
+  }
+
+  pub fn generate_argument(&self, argument: Argument) -> json::TextFieldValue {
+    match argument {
+      Argument::Literal(lit) => json::TextFieldValue::Parameter( json::Parameter { 
+        value: escape_interpolation(&lit.value), 
+        t: "string".to_string(), // TODO: Check if "string" is the correct value for key "t"
+        ..Default::default()     // TODO: Check if key "l" are crucial for import
+      }),
+      // CatWeb substitutes `{name}` with the value of the variable
+      Argument::Identifier(var) => json::TextFieldValue::Parameter( json::Parameter { 
+        value: format!("{{{}}}", var.name),
+        t: "string".to_string(), // TODO: Check if "string" is the correct value for key "t"
+        ..Default::default()     // TODO: Check if key "l" are crucial for import
+      }),
+      Argument::VariableName(var) => json::TextFieldValue::Parameter( json::Parameter { 
+        value: var.name,
+        t: "string".to_string(), // TODO: Check if "string" is the correct value for key "t"
+        ..Default::default()     // TODO: Check if key "l" are crucial for import
+      }),
+      Argument::Template(parts) => json::TextFieldValue::Parameter( json::Parameter { 
+        value: parts.into_iter().map(|part| match part {
+          TemplatePart::Text(text) => escape_interpolation(&text),
+          TemplatePart::Variable(var) => format!("{{{}}}", var.name),
+        }).collect(),
+        t: "string".to_string(), // TODO: Check if "string" is the correct value for key "t"
+        ..Default::default()     // TODO: Check if key "l" are crucial for import
+      }),
+      Argument::Object(globalid) => json::TextFieldValue::Parameter( json::Parameter { 
+        value: globalid,
+        t: "object".to_string(),
+        ..Default::default()     // TODO: Check if key "l" are crucial for import
+      }),
+      Argument::RawString(rstr) => json::TextFieldValue::PlainText(rstr), // FIXME: Implement RawString
+    }
+  }
+
   pub fn generate_script_block(&self, call: Call) -> json::Action {
     match call {
-      Call::CWScriptBlockCall { block_id, arguments, return_var, dependencies } => {
+      Call::CWScriptBlockCall { block_id, arguments, return_var, .. } => {
         json::Action {
           id: block_id.id,
-          text: arguments.into_iter().map(|arg| match arg {
-            Argument::Literal(lit) => json::TextFieldValue::Parameter( json::Parameter { 
-              value: lit.value, 
-              t: "string".to_string(), // TODO: Check if "string" is the correct value for key "t"
-              ..Default::default()     // TODO: Check if key "l" are crucial for import
-            }),
-            Argument::Identifier(var) => json::TextFieldValue::Parameter( json::Parameter { 
-              value: var.name,
-              t: "string".to_string(), // TODO: Check if "string" is the correct value for key "t"
-              ..Default::default()     // TODO: Check if key "l" are crucial for import
-            }),
-            Argument::RawString(rstr) => json::TextFieldValue::PlainText(rstr), // FIXME: Implement RawString
-          }).chain(
+          text: arguments.into_iter()
+            .map(|arg| self.generate_argument(arg))
             // Return variable are provided to CatWeb as the last parameter of the script block
             // So we append it to the end of the arguments list if it's Some
-            //
-            // Chain return_var as the last element if it's Some
-            (|| -> Vec<json::TextFieldValue> {
-              if let Some(return_var) = return_var {
-                // Return a vector with the return_var as the only element if return_var is Some
-                vec![json::TextFieldValue::Parameter( json::Parameter {
-                  value: return_var.name,
-                  t: "string".to_string(), // TODO: Check if "string" is the correct value for key "t"
-                  ..Default::default()     // TODO: Check if key "l" are crucial for import
-                })]
-              } else {
-                // Else return_var is None, return empty vec
-                vec![]
-              }
-            })()
-          ).collect(),
+            .chain(return_var.map(|var| self.generate_argument(Argument::VariableName(var))))
+            .collect(),
           ..Default::default()
         }
       },
-      Call::FunctionCall { dependencies, function_name, arguments, return_var } => {
+      Call::FunctionCall { function_name, arguments, return_var, .. } => {
         // TODO: Replace with in-language implementation of call with inlining and export-as and to be put in symbol table for compiler to find, instead of hardcoding the implementation
         
         json::Action {
-          id: "87".to_string(),
+          id: blocks::RUN_FUNCTION.to_string(),
           text: vec![
-            json::TextFieldValue::PlainText("".to_string()), // Argument padding
+            self.generate_argument(Argument::VariableName(function_name)),
             json::TextFieldValue::Tuple( json::Tuple {
-              value: vec![
-              ],
+              value: arguments.into_iter().map(|arg| self.generate_argument(arg)).collect(),
               t: "tuple".to_string(),
             }),
             json::TextFieldValue::PlainText("".to_string()), // Argument padding
@@ -142,4 +197,4 @@ impl CWBlockScriptGenerator {
       },
     }
   }
-}
\ No newline at end of file
+}
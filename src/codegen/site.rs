@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+/// UI objects of linked CatWeb site JSONs, which scripts can reference by their alias.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct SiteData {
+  objects: HashMap<String, Vec<UIObject>>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct UIObject {
+  pub alias: String,
+  pub class: String,
+  pub globalid: String,
+}
+
+/// Classes of the UI objects scripts can reference
+pub const KNOWN_CLASSES: &[&str] = &[
+  "Frame", "TextLabel", "TextButton", "TextBox", "ImageLabel", "ImageButton", "ScrollingFrame",
+];
+
+/// Properties every UI object has
+const COMMON_PROPERTIES: &[&str] = &[
+  "visible", "position", "size", "rotation", "anchor_point", "z_index", "layout_order",
+  "background_color", "background_transparency",
+];
+const TEXT_PROPERTIES: &[&str] = &["text", "text_color", "text_size", "text_transparency", "font", "rich_text"];
+const TEXT_BOX_PROPERTIES: &[&str] = &["placeholder"];
+const IMAGE_PROPERTIES: &[&str] = &["image", "image_color", "image_transparency"];
+const SCROLLING_FRAME_PROPERTIES: &[&str] = &["canvas_size"];
+
+/// Returns the properties scripts can read and write on objects of the given class
+pub fn known_properties(class: &str) -> Vec<&'static str> {
+  let specific: &[&[&str]] = match class {
+    "TextLabel" | "TextButton" => &[TEXT_PROPERTIES],
+    "TextBox" => &[TEXT_PROPERTIES, TEXT_BOX_PROPERTIES],
+    "ImageLabel" | "ImageButton" => &[IMAGE_PROPERTIES],
+    "ScrollingFrame" => &[SCROLLING_FRAME_PROPERTIES],
+    _ => &[],
+  };
+  COMMON_PROPERTIES.iter().chain(specific.iter().flat_map(|properties| properties.iter())).copied().collect()
+}
+
+/// Returns the properties of all classes, for objects whose class is not known at compile time
+pub fn all_known_properties() -> Vec<&'static str> {
+  [COMMON_PROPERTIES, TEXT_PROPERTIES, TEXT_BOX_PROPERTIES, IMAGE_PROPERTIES, SCROLLING_FRAME_PROPERTIES].concat()
+}
+
+impl SiteData {
+  /// Loads the site JSONs linked by the program. Paths are relative to `base_path`.
+  pub fn load_links(paths: &[String], base_path: &Path) -> Result<Self, anyhow::Error> {
+    let mut site = SiteData::default();
+    site.add_links(paths, base_path)?;
+    Ok(site)
+  }
+
+  /// Adds the objects of more site JSONs, e.g. those linked by every script of a project
+  pub fn add_links(&mut self, paths: &[impl AsRef<Path>], base_path: &Path) -> Result<(), anyhow::Error> {
+    for path in paths {
+      let full_path = base_path.join(path);
+      let content = std::fs::read_to_string(&full_path)
+        .map_err(|err| anyhow::anyhow!("Failed to read linked site \"{}\": {}", full_path.display(), err))?;
+      self.add_json(&content)
+        .map_err(|err| anyhow::anyhow!("Failed to parse linked site \"{}\": {}", full_path.display(), err))?;
+    }
+    Ok(())
+  }
+
+  /// Adds every object with an alias in the site JSON
+  pub fn add_json(&mut self, content: &str) -> Result<(), anyhow::Error> {
+    let value: serde_json::Value = serde_json::from_str(content)?;
+    self.add_objects(&value);
+    Ok(())
+  }
+
+  fn add_objects(&mut self, value: &serde_json::Value) {
+    match value {
+      serde_json::Value::Array(values) => values.iter().for_each(|value| self.add_objects(value)),
+      serde_json::Value::Object(fields) => {
+        let field = |key: &str| fields.get(key).and_then(|value| value.as_str()).unwrap_or_default();
+        // Scripts are objects too, but cannot be referenced
+        if !field("alias").is_empty() && !field("globalid").is_empty() && !field("class").is_empty() && field("class") != "script" {
+          self.objects.entry(field("alias").to_string()).or_default().push(UIObject {
+            alias: field("alias").to_string(),
+            class: field("class").to_string(),
+            globalid: field("globalid").to_string(),
+          });
+        }
+        fields.values().for_each(|value| self.add_objects(value));
+      },
+      _ => {},
+    }
+  }
+
+  /// Aliases of all objects, which may each be used by several objects
+  pub fn aliases(&self) -> impl Iterator<Item = &String> {
+    self.objects.keys()
+  }
+
+  /// Looks up an object by its alias, which must be unique across the linked sites
+  pub fn lookup(&self, alias: &str) -> Result<Option<&UIObject>, anyhow::Error> {
+    match self.objects.get(alias).map(|objects| objects.as_slice()) {
+      None => Ok(None),
+      Some([object]) => Ok(Some(object)),
+      Some(objects) => Err(anyhow::anyhow!(
+        "Object name \"{}\" is ambiguous, it is used by {} objects in the linked sites", alias, objects.len()
+      )),
+    }
+  }
+}
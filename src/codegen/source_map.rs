@@ -0,0 +1,101 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::{json, CWBlockScriptGenerator, Call, Item, Program};
+
+/// File of the code compiled from the standard library
+pub const STDLIB_FILE: &str = "<stdlib>";
+
+/// Sidecar of a CatWeb JSON written by `lync build --source-map`, which maps its cards and actions to the source they
+/// were compiled from.
+///
+/// Cards and actions are identified by their indices, since the generated JSON doesn't give them unique globalids.
+///
+/// ```json
+/// {"mappings": [{"card": 1, "action": 0, "file": "src/main.lxs", "line": 2, "column": 3}]}
+/// ```
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SourceMap {
+  pub mappings: Vec<Mapping>,
+}
+
+/// Source location of a card, or of one of its actions if `action` is set
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Mapping {
+  /// Index of the card in the script
+  pub card: usize,
+  /// Index of the action in the card, counting the actions in blocks
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub action: Option<usize>,
+  pub file: String,
+  pub line: usize,
+  pub column: usize,
+}
+
+impl Mapping {
+  /// `file:line:column`
+  pub fn location(&self) -> String {
+    format!("{}:{}:{}", self.file, self.line, self.column)
+  }
+}
+
+impl SourceMap {
+  /// Maps the cards and actions `generator` generated `script` with from `program`. Functions in `stdlib_functions`
+  /// come from the standard library, and everything else from `file`. Code the compiler generated, like the call
+  /// stack, has no position and isn't mapped.
+  pub fn new(generator: &CWBlockScriptGenerator, program: &Program, script: &json::Script, file: &str, stdlib_functions: &[String]) -> Self {
+    let mut mappings = Vec::new();
+    for (card_index, (item, card)) in program.main_block.iter().zip(&script.content).enumerate() {
+      let (body, card_position, file) = match item {
+        Item::FunctionDeclaration { name, body, position, .. } if stdlib_functions.contains(name) => (body, Some(*position), STDLIB_FILE),
+        Item::FunctionDeclaration { body, position, .. } => (body, Some(*position), file),
+        Item::Event { body, .. } => (body, None, file),
+      };
+      let actions = match card {
+        json::CodeCard::FunctionDeclaration(func) => &func.actions,
+        json::CodeCard::Event(event) => &event.actions,
+      };
+      let mut map = |action: Option<usize>, position: crate::parser::Position| {
+        if position.is_known() {
+          mappings.push(Mapping {
+            card: card_index,
+            action,
+            file: file.to_string(),
+            line: position.line,
+            column: position.column,
+          });
+        }
+      };
+
+      if let Some(position) = card_position {
+        map(None, position);
+      }
+      // Statements are generated into consecutive actions, after their comments
+      let positions = body.iter().flat_map(|statement| {
+        let actions = generator.comments(item, statement).len() + count_calls(&statement.dependencies) + count_calls(&statement.content);
+        std::iter::repeat_n(statement.position, actions)
+      });
+      for (action_index, (position, _)) in positions.zip(actions).enumerate() {
+        map(Some(action_index), position);
+      }
+    }
+    Self { mappings }
+  }
+
+  /// Location of a card, or of one of its actions
+  pub fn find(&self, card: usize, action: Option<usize>) -> Option<&Mapping> {
+    self.mappings.iter().find(|mapping| mapping.card == card && mapping.action == action)
+  }
+
+  /// Path of the source map of a CatWeb JSON, e.g. `main.map.json` for `main.json`
+  pub fn path_for(json_path: &Path) -> PathBuf {
+    json_path.with_extension("map.json")
+  }
+}
+
+fn count_calls(calls: &[Call]) -> usize {
+  calls.iter().map(|call| 1 + count_calls(call.dependencies())).sum()
+}
@@ -1,5 +1,6 @@
-// TODO: Implement stringification
 /// This module defines the lowered data structures.
+use crate::parser::Position;
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct Program {
   pub main_block: Vec<Item>,
@@ -9,6 +10,22 @@ impl Program {
   pub fn new(main_block: Vec<Item>) -> Self {
     Self { main_block }
   }
+
+  /// Returns the program with unknown positions, which is what parsing its IR gives, since the IR has no positions
+  #[cfg(test)]
+  pub fn without_positions(mut self) -> Program {
+    for item in &mut self.main_block {
+      let body = match item {
+        Item::FunctionDeclaration { body, position, .. } => {
+          *position = Position::default();
+          body
+        },
+        Item::Event { body, .. } => body,
+      };
+      body.iter_mut().for_each(|statement| statement.position = Position::default());
+    }
+    self
+  }
 }
 
 #[derive(Debug, PartialEq, Clone, Default)]
@@ -21,6 +38,10 @@ pub struct Expression {
 pub struct Statement {
   pub dependencies: Vec<Call>,
   pub content: Vec<Call>,
+  /// Position of the source statement it was compiled from
+  pub position: Position,
+  /// Comments put in front of its actions, from `#[comment]` attributes and doc comments
+  pub comments: Vec<String>,
 }
 
 #[derive(Debug, PartialEq, Clone, Default)]
@@ -33,15 +54,32 @@ pub struct Variable {
   pub name: String
 }
 
-#[derive(Debug, PartialEq, Clone)]
-pub struct CWScriptBlockID {
-  pub id: String
+/// Prefix of compiler-generated temporaries before allocation.
+///
+/// `%` cannot appear in identifiers, so these never collide with user variables.
+pub const VIRTUAL_TEMPORARY_PREFIX: &str = "%";
+
+/// Prefix of temporaries after allocation. Identifiers starting with `__` are reserved for the compiler.
+pub const TEMPORARY_PREFIX: &str = "__t";
+
+impl Variable {
+  /// Creates an unallocated temporary, which is later assigned a reusable slot by the temporary allocator
+  pub fn virtual_temporary(index: usize) -> Self {
+    Self { name: format!("{}{}", VIRTUAL_TEMPORARY_PREFIX, index) }
+  }
+
+  pub fn temporary(slot: usize) -> Self {
+    Self { name: format!("{}{}", TEMPORARY_PREFIX, slot) }
+  }
+
+  pub fn is_virtual_temporary(&self) -> bool {
+    self.name.starts_with(VIRTUAL_TEMPORARY_PREFIX)
+  }
 }
 
 #[derive(Debug, PartialEq, Clone)]
-pub enum Identifier {
-  Name(Variable),
-  CWScriptBlockID(CWScriptBlockID),
+pub struct CWScriptBlockID {
+  pub id: String
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -50,6 +88,9 @@ pub enum Item {
     name: String,
     parameters: Vec<Variable>, // FIXME: Variables only
     body: Vec<Statement>,
+    position: Position,
+    /// Lines of the description of its card
+    comments: Vec<String>,
   },
   Event {
     name: String,
@@ -61,7 +102,33 @@ pub enum Item {
 pub enum Argument {
   RawString(String),
   Literal(Literal),
+  /// Reads the value of a variable
   Identifier(Variable),
+  /// Names a variable, for parameters which CatWeb writes to (e.g. the target of "set variable")
+  VariableName(Variable),
+  /// Text with variables interpolated by CatWeb
+  Template(Vec<TemplatePart>),
+  /// Reference to a UI object of a linked site, by its globalid
+  Object(String),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum TemplatePart {
+  Text(String),
+  Variable(Variable),
+}
+
+impl Argument {
+  pub fn variables_mut(&mut self) -> Vec<&mut Variable> {
+    match self {
+      Argument::Identifier(var) | Argument::VariableName(var) => vec![var],
+      Argument::Template(parts) => parts.iter_mut().filter_map(|part| match part {
+        TemplatePart::Variable(var) => Some(var),
+        TemplatePart::Text(_) => None,
+      }).collect(),
+      Argument::RawString(_) | Argument::Literal(_) | Argument::Object(_) => vec![],
+    }
+  }
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -78,4 +145,55 @@ pub enum Call {
     arguments: Vec<Argument>,
     return_var: Option<Variable>,
   }
-}
\ No newline at end of file
+}
+
+impl Call {
+  pub fn dependencies(&self) -> &[Call] {
+    match self {
+      Call::FunctionCall { dependencies, .. } | Call::CWScriptBlockCall { dependencies, .. } => dependencies,
+    }
+  }
+
+  pub fn dependencies_mut(&mut self) -> &mut Vec<Call> {
+    match self {
+      Call::FunctionCall { dependencies, .. } | Call::CWScriptBlockCall { dependencies, .. } => dependencies,
+    }
+  }
+
+  /// Variables referenced by the call itself, not including its dependencies
+  pub fn variables_mut(&mut self) -> Vec<&mut Variable> {
+    match self {
+      Call::FunctionCall { arguments, return_var, .. }
+      | Call::CWScriptBlockCall { arguments, return_var, .. } => {
+        arguments.iter_mut()
+          .flat_map(|arg| arg.variables_mut())
+          .chain(return_var.iter_mut())
+          .collect()
+      },
+    }
+  }
+
+  /// Moves the dependencies of this call (recursively) in front of it, in execution order
+  pub fn flatten(mut self) -> Vec<Call> {
+    let mut calls: Vec<Call> = std::mem::take(self.dependencies_mut()).into_iter()
+      .flat_map(|call| call.flatten())
+      .collect();
+    calls.push(self);
+    calls
+  }
+}
+
+impl Statement {
+  /// Creates a statement whose source position is unknown
+  pub fn new(dependencies: Vec<Call>, content: Vec<Call>) -> Self {
+    Self { dependencies, content, position: Position::default(), comments: Vec::new() }
+  }
+
+  /// Calls of the statement in execution order, with nested dependencies hoisted
+  pub fn into_calls(self) -> Vec<Call> {
+    self.dependencies.into_iter()
+      .chain(self.content)
+      .flat_map(|call| call.flatten())
+      .collect()
+  }
+}
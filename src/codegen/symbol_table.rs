@@ -1,44 +1,133 @@
-use std::{collections::{HashMap, HashSet}, hash::Hash};
+use std::collections::HashMap;
 
-#[derive(Debug, PartialEq, Clone)]
+/// Index of a symbol in the list of all symbols of a compilation
+pub type SymbolId = usize;
+
+/// Scope of the symbols declared by a module, function or block. Lookups walk outward through the `upper` scopes.
+#[derive(Debug, PartialEq, Clone, Default)]
 pub struct SymbolTable {
   pub upper: Option<Box<SymbolTable>>,
-  pub symbols: HashSet<SymbolRecord>,
+  pub symbols: HashMap<String, SymbolRecord>,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Hash)]
 pub struct SymbolRecord {
+  pub id: SymbolId,
   pub name: String,
   pub symbol_type: SymbolType,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
 pub enum SymbolType {
+  /// Parameter or local variable of a function
   Variable,
+  /// Variable declared with `global`, which is shared by all functions
+  Global,
+  /// Value declared with `const`, which is substituted where it is used
+  Constant,
   Function,
-  Event,
   UIObject,
+  /// First part of a dotted function name, e.g. `console` of `console.log`
+  Namespace,
+}
+
+impl std::fmt::Display for SymbolType {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      SymbolType::Variable => write!(f, "variable"),
+      SymbolType::Global => write!(f, "global variable"),
+      SymbolType::Constant => write!(f, "constant"),
+      SymbolType::Function => write!(f, "function"),
+      SymbolType::UIObject => write!(f, "UI object"),
+      SymbolType::Namespace => write!(f, "namespace"),
+    }
+  }
 }
 
 impl SymbolTable {
-  pub fn new(upper: Option<Box<SymbolTable>>, symbols: HashSet<SymbolRecord>) -> Self {
-    Self { upper: upper, symbols: symbols }
+  pub fn new(upper: Option<Box<SymbolTable>>) -> Self {
+    Self { upper, symbols: HashMap::new() }
+  }
+
+  /// Enters a new scope nested in this one
+  pub fn push_scope(&mut self) {
+    let upper = std::mem::take(self);
+    *self = SymbolTable::new(Some(Box::new(upper)));
+  }
+
+  /// Leaves the current scope, dropping its symbols
+  pub fn pop_scope(&mut self) {
+    let upper = self.upper.take().expect("Cannot leave the outermost scope");
+    *self = *upper;
+  }
+
+  /// Adds a symbol to the current scope, returning the symbol of the same name it replaces in this scope
+  pub fn add_symbol(&mut self, record: SymbolRecord) -> Option<SymbolRecord> {
+    self.symbols.insert(record.name.clone(), record)
+  }
+
+  /// Looks up a symbol in this scope only
+  pub fn lookup_local(&self, name: &str) -> Option<&SymbolRecord> {
+    self.symbols.get(name)
+  }
+
+  /// Looks up a symbol in this scope and its outer scopes, innermost first
+  pub fn lookup(&self, name: &str) -> Option<&SymbolRecord> {
+    self.symbols.get(name).or_else(|| self.upper.as_ref().and_then(|upper| upper.lookup(name)))
   }
 
-  pub fn add_symbol(&mut self, name: String, symbol_type: SymbolType) {
-    let record = SymbolRecord { name, symbol_type };
-    self.symbols.insert(record);
+  /// Looks up a symbol in the outer scopes only
+  pub fn lookup_outer(&self, name: &str) -> Option<&SymbolRecord> {
+    self.upper.as_ref().and_then(|upper| upper.lookup(name))
   }
-  
-  pub fn remove_symbol(&mut self, name: String, symbol_type: SymbolType) {
-    let record = SymbolRecord { name, symbol_type };
-    self.symbols.remove(&record);
+
+  /// Returns the visible symbol spelled most similarly to `name`, if any is close enough to be a likely typo
+  pub fn nearest(&self, name: &str) -> Option<&SymbolRecord> {
+    let max_distance = max_typo_distance(name);
+    let mut scope = Some(self);
+    let mut nearest: Option<(usize, &SymbolRecord)> = None;
+    while let Some(table) = scope {
+      for record in table.symbols.values() {
+        let distance = edit_distance(name, &record.name);
+        // Ties are broken by name, so suggestions don't depend on hash order
+        let is_nearer = nearest.is_none_or(|(nearest_distance, nearest_record)| {
+          (distance, &record.name) < (nearest_distance, &nearest_record.name)
+        });
+        if distance <= max_distance && is_nearer {
+          nearest = Some((distance, record));
+        }
+      }
+      scope = table.upper.as_deref();
+    }
+    nearest.map(|(_, record)| record)
   }
+}
 
-  pub fn lookup(&self, name: String, symbol_type: SymbolType) -> Option<&SymbolRecord> {
-    self.symbols.get(&SymbolRecord {
-      name: name,
-      symbol_type: symbol_type,
-    })
+/// Largest edit distance from `name` to a name it's likely a typo of, a third of its length. One-letter names have no
+/// likely typos, since every other one-letter name is as close.
+pub fn max_typo_distance(name: &str) -> usize {
+  let length = name.chars().count();
+  (length / 3).max(1).min(length.saturating_sub(1))
+}
+
+/// Optimal string alignment distance between two strings, which is the Levenshtein distance with swaps of adjacent
+/// characters counted as one edit, e.g. `cuont` is one edit away from `count`
+pub fn edit_distance(lhs: &str, rhs: &str) -> usize {
+  let lhs: Vec<char> = lhs.chars().collect();
+  let rhs: Vec<char> = rhs.chars().collect();
+  let mut before_previous: Vec<usize> = Vec::new();
+  let mut previous: Vec<usize> = (0..=rhs.len()).collect();
+  for (i, lhs_char) in lhs.iter().enumerate() {
+    let mut current = vec![i + 1];
+    for (j, rhs_char) in rhs.iter().enumerate() {
+      let substitution = previous[j] + usize::from(lhs_char != rhs_char);
+      let mut distance = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+      if i > 0 && j > 0 && *lhs_char == rhs[j - 1] && lhs[i - 1] == *rhs_char {
+        distance = distance.min(before_previous[j - 1] + 1);
+      }
+      current.push(distance);
+    }
+    before_previous = std::mem::replace(&mut previous, current);
   }
-}
\ No newline at end of file
+  previous[rhs.len()]
+}
@@ -0,0 +1,270 @@
+#[test]
+fn test_interpolation_escaping() {
+  use crate::codegen::{self, json};
+
+  let generator = codegen::CWBlockScriptGenerator::new();
+  let parameter_value = |argument: codegen::Argument| match generator.generate_argument(argument) {
+    json::TextFieldValue::Parameter(parameter) => parameter.value,
+    others => panic!("Expected parameter, found {:?}", others),
+  };
+
+  // Braces in literal text must not be interpolated by CatWeb
+  assert_eq!(
+    parameter_value(codegen::Argument::Literal(codegen::Literal { value: "{name}".to_string() })),
+    "\\{name\\}",
+  );
+  assert_eq!(
+    parameter_value(codegen::Argument::Template(vec![
+      codegen::TemplatePart::Text("Hi {".to_string()),
+      codegen::TemplatePart::Variable(codegen::Variable { name: "user".to_string() }),
+      codegen::TemplatePart::Text("}".to_string()),
+    ])),
+    "Hi \\{{user}\\}",
+  );
+  assert_eq!(parameter_value(codegen::Argument::Identifier(codegen::Variable { name: "user".to_string() })), "{user}");
+}
+
+#[test]
+fn test_symbol_table_scopes() {
+  use crate::codegen::symbol_table::{SymbolRecord, SymbolTable, SymbolType};
+
+  let record = |id: usize, name: &str| SymbolRecord { id, name: name.to_string(), symbol_type: SymbolType::Variable };
+
+  let mut table = SymbolTable::default();
+  table.add_symbol(record(0, "name"));
+  table.push_scope();
+  table.add_symbol(record(1, "count"));
+  table.push_scope();
+  table.add_symbol(record(2, "name"));
+
+  // Lookups walk outward, and inner symbols shadow outer ones
+  assert_eq!(table.lookup("name").map(|record| record.id), Some(2));
+  assert_eq!(table.lookup_outer("name").map(|record| record.id), Some(0));
+  assert_eq!(table.lookup("count").map(|record| record.id), Some(1));
+  assert!(table.lookup_local("count").is_none());
+
+  assert_eq!(table.nearest("cont").map(|record| record.name.as_str()), Some("count"));
+  // Swapped letters are a single typo
+  assert_eq!(table.nearest("cuont").map(|record| record.name.as_str()), Some("count"));
+  assert!(table.nearest("total").is_none());
+  // Any one-letter name is a single edit away from another, so none is suggested
+  table.add_symbol(record(3, "f"));
+  assert!(table.nearest("x").is_none());
+
+  table.pop_scope();
+  assert_eq!(table.lookup("name").map(|record| record.id), Some(0));
+  table.pop_scope();
+  assert!(table.lookup("count").is_none());
+}
+
+#[test]
+fn test_script_listing() {
+  use crate::{codegen, compiler, parser};
+
+  let input = "function f(n: number) { for (x of [n]) { console.log(x); } } f(1);";
+  let syntax_tree = parser::Parser::new().parse_program_from_str(input).unwrap();
+  let program = compiler::Compiler::new(syntax_tree).compile().unwrap();
+  let script = codegen::CWBlockScriptGenerator::new().generate_program(program);
+  assert_eq!(codegen::listing::list_script(&script, None), [
+    "event 0 (website loaded)",
+    "  87 run function: \"f\", (\"1\"), #\"\", \"\"",
+    "function f(f$n)",
+    "  100 create table: \"__t0\"",
+    "  104 insert entry: \"__t0\", \"{f$n}\"",
+    "  106 iterate table: \"__t0\", \"__t1\", \"f$x\"",
+    "    87 run function: \"console.log\", (\"{f$x}\"), #\"\", \"\"",
+    "  25 end",
+    "function console.log(console.log$arg)",
+    "  0 log: #\"\", \"{console.log$arg}\"",
+    "",
+  ].join("\n"));
+}
+
+#[test]
+fn test_ir_text() {
+  use crate::{codegen, compiler, parser};
+  use codegen::{Argument, Call, CWScriptBlockID, Item, Literal, Program, Statement, TemplatePart, Variable};
+
+  let input = "function f(n: number) { for (x of [n]) { console.log(x); } } f(1);";
+  let syntax_tree = parser::Parser::new().parse_program_from_str(input).unwrap();
+  let program = compiler::Compiler::new(syntax_tree).compile().unwrap();
+  assert_eq!(program.to_string(), [
+    "event 0 {",
+    "  with {",
+    "    f(\"1\")",
+    "  } do {}",
+    "}",
+    "",
+    "function f(f$n) {",
+    "  with {",
+    "    #100() -> __t0",
+    "    #104(&__t0, f$n)",
+    "  } #106(&__t0, &__t1, &f$x)",
+    "  with {",
+    "    console.log(f$x)",
+    "  } do {}",
+    "  #25()",
+    "}",
+    "",
+    "function console.log(console.log$arg) {",
+    "  with {",
+    "    #0(#\"\", console.log$arg)",
+    "  } do {}",
+    "}",
+    "",
+  ].join("\n"));
+
+  // Every kind of argument, and dependencies of calls
+  let variable = |name: &str| Variable { name: name.to_string() };
+  let block = |id: &str, dependencies: Vec<Call>, arguments: Vec<Argument>| Call::CWScriptBlockCall {
+    dependencies,
+    block_id: CWScriptBlockID { id: id.to_string() },
+    arguments,
+    return_var: None,
+  };
+  let program = Program::new(vec![Item::Event {
+    name: "0".to_string(),
+    body: vec![Statement::new(vec![], vec![block("1", vec![block("11", vec![], vec![Argument::VariableName(variable("x")), Argument::Literal(Literal { value: "a \"b\"".to_string() })])], vec![
+      Argument::RawString("\n".to_string()),
+      Argument::Identifier(variable("x")),
+      Argument::Template(vec![TemplatePart::Text("{`}".to_string()), TemplatePart::Variable(variable("x"))]),
+      Argument::Object("gid".to_string()),
+    ])])],
+  }]);
+  assert_eq!(program.to_string(), [
+    "event 0 {",
+    "  do {",
+    "    with {",
+    "      #11(&x, \"a \\\"b\\\"\")",
+    "    } #1(#\"\\n\", x, `\\{\\`\\}{x}`, @\"gid\")",
+    "  }",
+    "}",
+    "",
+  ].join("\n"));
+}
+
+#[test]
+fn test_ir_parsing() {
+  use crate::{codegen, compiler, parser};
+  use codegen::{ir, Argument, Call, Item, Program, Statement, Variable};
+
+  // Compiled programs round-trip
+  let input = "function f(n: number) { for (x of [n]) { console.log(`${x}{}`); } } f(#\"a\\b\"); f(-1 * 2);";
+  let syntax_tree = parser::Parser::new().parse_program_from_str(input).unwrap();
+  let program = compiler::Compiler::new(syntax_tree).compile().unwrap();
+  assert_eq!(ir::parse_program(&program.to_string()).unwrap(), program.without_positions());
+
+  // Names which aren't plain are quoted, and calls of a statement can have dependencies
+  let variable = |name: &str| Variable { name: name.to_string() };
+  let call = |name: &str, dependencies: Vec<Call>| Call::FunctionCall {
+    dependencies,
+    function_name: variable(name),
+    arguments: vec![Argument::Identifier(variable("it's \\")), Argument::Object("a\"b".to_string())],
+    return_var: Some(variable("do")),
+  };
+  let program = Program::new(vec![Item::FunctionDeclaration {
+    name: "say hi".to_string(),
+    parameters: vec![variable("with"), variable("")],
+    body: vec![
+      Statement::new(vec![], vec![call("with", vec![call("do", vec![])])]),
+      Statement::new(vec![call("a", vec![])], vec![call("b", vec![])]),
+      Statement::new(vec![], vec![]),
+    ],
+    position: Default::default(),
+    comments: vec![],
+  }]);
+  let text = program.to_string();
+  assert_eq!(text, [
+    "function 'say hi'(with, '') {",
+    "  do {",
+    "    with {",
+    "      do('it\\'s \\\\', @\"a\\\"b\") -> do",
+    "    } with('it\\'s \\\\', @\"a\\\"b\") -> do",
+    "  }",
+    "  with {",
+    "    a('it\\'s \\\\', @\"a\\\"b\") -> do",
+    "  } b('it\\'s \\\\', @\"a\\\"b\") -> do",
+    "  do {}",
+    "}",
+    "",
+  ].join("\n"));
+  assert_eq!(ir::parse_program(&text).unwrap(), program);
+
+  // Whitespace doesn't matter
+  let program = ir::parse_program("event 0{#11(&x,\"1\")->y}").unwrap();
+  assert_eq!(program.to_string(), "event 0 {\n  #11(&x, \"1\") -> y\n}\n");
+
+  for input in ["event 0 { #0(x y) }", "function f { }", "event 0 { #0(\"\\q\") }"] {
+    assert!(ir::parse_program(input).is_err(), "{}", input);
+  }
+}
+
+#[test]
+fn test_source_map() {
+  use crate::{codegen, compiler, parser};
+  use codegen::source_map::{STDLIB_FILE, SourceMap};
+
+  let input = "function f(n: number) {\n  console.log(n);\n}\n\nfor (x of [1]) {\n  f(x);\n}\n";
+  let syntax_tree = parser::Parser::new().parse_program_from_str(input).unwrap();
+  let mut compiler = compiler::Compiler::new(syntax_tree);
+  let program = compiler.compile().unwrap();
+  let generator = codegen::CWBlockScriptGenerator::new();
+  let script = generator.generate_program(program.clone());
+  let source_map = SourceMap::new(&generator, &program, &script, "main.lxs", compiler.stdlib_functions());
+
+  let location = |card, action| source_map.find(card, action).map(|mapping| mapping.location());
+  // The event creates the table, inserts its entry, iterates it, calls f in the loop and closes it
+  let locations: Vec<Option<String>> = (0..5).map(|action| location(0, Some(action))).collect();
+  assert_eq!(locations, ["main.lxs:5:1", "main.lxs:5:1", "main.lxs:5:1", "main.lxs:6:3", "main.lxs:5:1"].map(|location| Some(location.to_string())));
+  assert_eq!(location(0, None), None);
+  assert_eq!(location(1, None).as_deref(), Some("main.lxs:1:10"));
+  assert_eq!(location(1, Some(0)).as_deref(), Some("main.lxs:2:3"));
+  assert!(source_map.find(2, Some(0)).is_some_and(|mapping| mapping.file == STDLIB_FILE));
+
+  let listing = codegen::listing::list_script(&script, Some(&source_map));
+  assert!(listing.starts_with("event 0 (website loaded)\n  100 create table: \"__t0\"  // main.lxs:5:1\n"), "{}", listing);
+
+  let json = serde_json::to_string(&source_map).unwrap();
+  assert_eq!(serde_json::from_str::<SourceMap>(&json).unwrap(), source_map);
+  // Cards and actions are identified by their indices
+  assert!(!json.contains("globalid"), "{}", json);
+  let documented = r#"{"mappings": [{"card": 1, "action": 0, "file": "src/main.lxs", "line": 2, "column": 3}]}"#;
+  assert_eq!(serde_json::from_str::<SourceMap>(documented).unwrap().find(1, Some(0)).unwrap().location(), "src/main.lxs:2:3");
+}
+
+#[test]
+fn test_comments() {
+  use crate::{codegen, compiler, parser};
+  use codegen::{ir, json, source_map::SourceMap};
+
+  let input = "/// Logs n\nfunction f(n: number) {\n  #[comment(\"{n}\")]\n  console.log(n);\n}\n\nfor (x of [1]) {\n  f(x);\n}\n";
+  let syntax_tree = parser::Parser::new().parse_program_from_str(input).unwrap();
+  let mut compiler = compiler::Compiler::new(syntax_tree);
+  let program = compiler.compile().unwrap();
+  assert_eq!(ir::parse_program(&program.to_string()).unwrap(), program.clone().without_positions());
+
+  // Comments are generated unconditionally, with braces escaped
+  let script = codegen::CWBlockScriptGenerator::new().generate_program(program.clone());
+  let json::CodeCard::FunctionDeclaration(func) = &script.content[1] else {
+    panic!("Expected function declaration");
+  };
+  assert_eq!(func.description, "Logs n");
+  let listing = codegen::listing::list_script(&script, None);
+  assert!(listing.contains("function f(f$n)\n  /// Logs n\n  124 comment: \"\\\\{n\\\\}\"\n"), "{}", listing);
+
+  // Annotations show the source line of each statement, except for ends of blocks and the standard library
+  let generator = codegen::CWBlockScriptGenerator::new().with_annotations(input, compiler.stdlib_functions());
+  let script = generator.generate_program(program.clone());
+  let listing = codegen::listing::list_script(&script, None);
+  let comments: Vec<&str> = listing.lines().filter(|line| line.contains("comment")).map(str::trim).collect();
+  assert_eq!(comments, [
+    "124 comment: \"7: for (x of [1]) \\\\{\"",
+    "124 comment: \"8: f(x);\"",
+    "124 comment: \"4: console.log(n);\"",
+    "124 comment: \"\\\\{n\\\\}\"",
+  ]);
+
+  // Source maps account for the comments
+  let source_map = SourceMap::new(&generator, &program, &script, "main.lxs", compiler.stdlib_functions());
+  assert_eq!(source_map.find(0, Some(4)).map(|mapping| mapping.location()).as_deref(), Some("main.lxs:8:3"));
+}
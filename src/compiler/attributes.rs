@@ -0,0 +1,196 @@
+use crate::parser;
+use crate::codegen::symbol_table;
+
+use super::lints;
+
+/// Attributes known to the compiler
+#[derive(Debug, PartialEq, Clone)]
+pub enum Attribute {
+  /// `#[inline]`, which inlines the function where it is called
+  Inline,
+  /// `#[export_as("name")]`, which names the CatWeb function differently from the declared one
+  ExportAs(String),
+  /// `#[allow(...)]`, `#[warn(...)]` and `#[deny(...)]`, which set the level of lints within the item or statement
+  LintLevel(lints::Level, Vec<String>),
+  /// `#[comment("text")]` and doc comments, which become comment actions before a statement or the description of a
+  /// function's card
+  Comment(String),
+}
+
+/// Kinds of items attributes can belong to
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Target {
+  Function,
+  Constant,
+  Global,
+  Statement,
+}
+
+impl std::fmt::Display for Target {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Target::Function => write!(f, "functions"),
+      Target::Constant => write!(f, "constants"),
+      Target::Global => write!(f, "globals"),
+      Target::Statement => write!(f, "statements"),
+    }
+  }
+}
+
+/// Names of the known attributes, with the items they can belong to
+const KNOWN_ATTRIBUTES: &[(&str, &[Target])] = &[
+  ("inline", &[Target::Function]),
+  ("export_as", &[Target::Function]),
+  ("allow", &[Target::Function, Target::Constant, Target::Global, Target::Statement]),
+  ("warn", &[Target::Function, Target::Constant, Target::Global, Target::Statement]),
+  ("deny", &[Target::Function, Target::Constant, Target::Global, Target::Statement]),
+  ("comment", &[Target::Function, Target::Statement]),
+  // Doc comments of constants and globals only document the source
+  ("doc", &[Target::Function, Target::Constant, Target::Global, Target::Statement]),
+];
+
+/// Attributes which can be given multiple times, e.g. `#[allow(shadowing)] #[allow(unused_variables)]`
+const REPEATABLE_ATTRIBUTES: &[&str] = &["allow", "warn", "deny", "comment", "doc"];
+
+/// Pairs of attributes which can't belong to the same item, with the reason
+const CONFLICTING_ATTRIBUTES: &[(&str, &str, &str)] = &[
+  ("inline", "export_as", "inlined functions have no CatWeb function to export"),
+];
+
+impl Attribute {
+  /// Texts of the comments among attributes, in order
+  pub fn comments(attributes: &[Attribute]) -> Vec<String> {
+    attributes.iter().filter_map(|attribute| match attribute {
+      Attribute::Comment(text) => Some(text.clone()),
+      _ => None,
+    }).collect()
+  }
+
+  /// Checks the attributes of an item, and returns the attributes they stand for
+  pub fn from_attributes(attributes: &[parser::Attribute], target: Target) -> Result<Vec<Attribute>, anyhow::Error> {
+    for (index, attribute) in attributes.iter().enumerate() {
+      let Some((_, targets)) = KNOWN_ATTRIBUTES.iter().find(|(name, _)| *name == attribute.name) else {
+        let suggestion = KNOWN_ATTRIBUTES.iter()
+          .map(|(name, _)| (symbol_table::edit_distance(&attribute.name, name), *name))
+          .filter(|(distance, _)| *distance <= symbol_table::max_typo_distance(&attribute.name))
+          .min()
+          .map(|(_, name)| format!(", did you mean \"{}\"?", name))
+          .unwrap_or_default();
+        return Err(anyhow::anyhow!("Unknown attribute \"{}\"{}", attribute.name, suggestion));
+      };
+      if !targets.contains(&target) {
+        return Err(anyhow::anyhow!(
+          "Attribute \"{}\" can only be applied to {}",
+          attribute.name, targets.iter().map(|target| target.to_string()).collect::<Vec<String>>().join(" and ")
+        ));
+      }
+      if !REPEATABLE_ATTRIBUTES.contains(&attribute.name.as_str()) && attributes[..index].iter().any(|previous| previous.name == attribute.name) {
+        return Err(anyhow::anyhow!("Duplicate attribute \"{}\"", attribute.name));
+      }
+    }
+
+    for (lhs, rhs, reason) in CONFLICTING_ATTRIBUTES {
+      if attributes.iter().any(|attribute| attribute.name == *lhs) && attributes.iter().any(|attribute| attribute.name == *rhs) {
+        return Err(anyhow::anyhow!("Attributes \"{}\" and \"{}\" conflict: {}", lhs, rhs, reason));
+      }
+    }
+
+    let attributes = attributes.iter().map(Attribute::from_attribute).collect::<Result<Vec<Attribute>, anyhow::Error>>()?;
+    let mut lint_levels: Vec<(&String, lints::Level)> = Vec::new();
+    for attribute in &attributes {
+      if let Attribute::LintLevel(level, names) = attribute {
+        for name in names {
+          match lint_levels.iter().find(|(other_name, _)| *other_name == name) {
+            Some((_, other_level)) if other_level != level => {
+              return Err(anyhow::anyhow!("Lint \"{}\" is set to both {} and {}", name, other_level, level));
+            },
+            _ => lint_levels.push((name, *level)),
+          }
+        }
+      }
+    }
+    Ok(attributes)
+  }
+
+  fn from_attribute(attribute: &parser::Attribute) -> Result<Attribute, anyhow::Error> {
+    match attribute.name.as_str() {
+      "inline" => {
+        Attribute::expect_arguments(attribute, 0)?;
+        Ok(Attribute::Inline)
+      },
+      "export_as" => {
+        Attribute::expect_arguments(attribute, 1)?;
+        match &attribute.arguments[0] {
+          parser::AttributeArgument { name: None, value: parser::AttributeValue::String(name) } => Ok(Attribute::ExportAs(name.clone())),
+          _ => Err(anyhow::anyhow!("Attribute \"export_as\" expects the exported name as a string, e.g. #[export_as(\"console.log\")]")),
+        }
+      },
+      level_name @ ("allow" | "warn" | "deny") => {
+        let level = lints::Level::from_name(level_name).expect("Lint level attributes should be levels");
+        if attribute.arguments.is_empty() {
+          return Err(anyhow::anyhow!("Attribute \"{}\" expects the names of lints, e.g. #[{}(unused_variables)]", level_name, level_name));
+        }
+        let names = attribute.arguments.iter().map(|argument| match argument {
+          parser::AttributeArgument { name: None, value: parser::AttributeValue::Identifier(name) } => {
+            lints::find(name).map(|lint| lint.name.to_string())
+          },
+          _ => Err(anyhow::anyhow!("Attribute \"{}\" expects the names of lints, e.g. #[{}(unused_variables)]", level_name, level_name)),
+        }).collect::<Result<Vec<String>, anyhow::Error>>()?;
+        Ok(Attribute::LintLevel(level, names))
+      },
+      name @ ("comment" | "doc") => {
+        Attribute::expect_arguments(attribute, 1)?;
+        match &attribute.arguments[0] {
+          parser::AttributeArgument { name: None, value: parser::AttributeValue::String(text) } => Ok(Attribute::Comment(text.clone())),
+          _ => Err(anyhow::anyhow!("Attribute \"{}\" expects the text as a string, e.g. #[{}(\"Greets the user\")]", name, name)),
+        }
+      },
+      others => unreachable!("Attribute \"{}\" should be known", others),
+    }
+  }
+
+  fn expect_arguments(attribute: &parser::Attribute, count: usize) -> Result<(), anyhow::Error> {
+    match attribute.arguments.len() == count {
+      true => Ok(()),
+      false => Err(anyhow::anyhow!(
+        "Attribute \"{}\" expects {} arguments, found {}", attribute.name, count, attribute.arguments.len()
+      )),
+    }
+  }
+}
+
+/// Checks the attributes of all items and the statements in them, reporting every invalid one
+pub fn check_items(items: &[parser::Item]) -> Result<(), anyhow::Error> {
+  let mut errors = Vec::new();
+  for item in items {
+    let (attributes, target, description, body) = match item {
+      parser::Item::FunctionDeclaration(func) => (&func.attributes, Target::Function, format!("function \"{}\"", func.name), &func.body[..]),
+      parser::Item::ConstDeclaration(constant) => (&constant.attributes, Target::Constant, format!("constant \"{}\"", constant.name.name), &[][..]),
+      parser::Item::GlobalDeclaration(global) => (&global.attributes, Target::Global, format!("global \"{}\"", global.name.name), &[][..]),
+      parser::Item::Statement(statement) => {
+        check_statements(std::slice::from_ref(statement), &mut errors);
+        continue;
+      },
+    };
+    if let Err(err) = Attribute::from_attributes(attributes, target) {
+      errors.push(format!("In {}: {}", description, err));
+    }
+    check_statements(body, &mut errors);
+  }
+
+  match errors.is_empty() {
+    true => Ok(()),
+    false => Err(anyhow::anyhow!("{}", errors.join("\n"))),
+  }
+}
+
+fn check_statements(statements: &[parser::Statement], errors: &mut Vec<String>) {
+  for statement in statements {
+    if let Err(err) = Attribute::from_attributes(statement.attributes(), Target::Statement) {
+      errors.push(format!("In statement at {}: {}", statement.position(), err));
+    }
+    if let parser::Statement::For { body, .. } = statement {
+      check_statements(body, errors);
+    }
+  }
+}
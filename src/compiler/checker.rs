@@ -0,0 +1,357 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::parser::{self, format::{bin_operator, expression_source}};
+use crate::codegen::site;
+use crate::codegen::symbol_table::{SymbolId, SymbolType};
+
+use super::resolver::Resolution;
+use super::types::{self, FunctionSignature, Type};
+
+/// Type checker, which runs over the resolved syntax tree before it is lowered.
+///
+/// Types are inferred locally, within each function. Mismatches are collected, so all of them are reported at once.
+pub struct TypeChecker<'a> {
+  site: &'a site::SiteData,
+  functions: &'a HashMap<String, FunctionSignature>,
+  resolution: &'a Resolution,
+  /// Types of the variables of the current function
+  variables: HashMap<SymbolId, Type>,
+  /// Variables of the current function whose type is fixed by an annotation
+  annotated: HashSet<SymbolId>,
+  /// Types of the constants and global variables, which are fixed since any function can use them
+  globals: HashMap<SymbolId, Type>,
+  function_name: String,
+  /// Position of the declaration or statement being checked, which errors are reported at
+  position: parser::Position,
+  errors: Vec<String>,
+}
+
+impl<'a> TypeChecker<'a> {
+  pub fn new(site: &'a site::SiteData, functions: &'a HashMap<String, FunctionSignature>, resolution: &'a Resolution) -> Self {
+    Self {
+      site,
+      functions,
+      resolution,
+      variables: HashMap::new(),
+      annotated: HashSet::new(),
+      globals: HashMap::new(),
+      function_name: String::new(),
+      position: parser::Position::default(),
+      errors: Vec::new(),
+    }
+  }
+
+  pub fn check_items(mut self, items: &[parser::Item]) -> Result<(), anyhow::Error> {
+    // Constants and globals have the annotated type, or else the type of their value
+    self.function_name.clear();
+    for item in items {
+      let (name, type_annotation, value, position) = match item {
+        parser::Item::ConstDeclaration(constant) => (&constant.name, &constant.type_annotation, Some(&constant.value), constant.position),
+        parser::Item::GlobalDeclaration(global) => (&global.name, &global.type_annotation, global.value.as_ref(), global.position),
+        _ => continue,
+      };
+      self.position = position;
+      self.variables = self.globals.clone();
+      let value_type = value.map(|value| self.infer_value(value));
+      let declared_type = match (type_annotation, value_type) {
+        (Some(_), value_type) => {
+          let expected = self.annotated_type(type_annotation);
+          if let Some(value_type) = value_type && !expected.accepts(&value_type) {
+            self.error(format!("Cannot assign {} to \"{}\" of type {}", value_type, name.name, expected));
+          }
+          expected
+        },
+        (None, value_type) => value_type.unwrap_or(Type::Any),
+      };
+      self.globals.insert(self.resolution.symbol(name).id, declared_type);
+    }
+
+    // Statements outside of functions are checked like the body of a function
+    self.variables = self.globals.clone();
+    self.annotated = self.globals.keys().copied().collect();
+    for item in items {
+      if let parser::Item::Statement(statement) = item {
+        self.check_statement(statement);
+      }
+    }
+
+    for item in items {
+      if let parser::Item::FunctionDeclaration(func) = item {
+        self.check_function(func);
+      }
+    }
+
+    match self.errors.is_empty() {
+      true => Ok(()),
+      false => Err(anyhow::anyhow!("{}", self.errors.join("\n"))),
+    }
+  }
+
+  fn check_function(&mut self, func: &parser::FunctionDeclaration) {
+    self.function_name = func.name.clone();
+    self.position = func.position;
+    self.variables = self.globals.clone();
+    self.annotated = self.globals.keys().copied().collect();
+
+    for param in &func.parameters {
+      let param_type = self.annotated_type(&param.type_annotation);
+      self.declare(&param.name, param_type, param.type_annotation.is_some());
+    }
+    for statement in &func.body {
+      self.check_statement(statement);
+    }
+  }
+
+  fn check_statement(&mut self, statement: &parser::Statement) {
+    self.position = statement.position();
+    match statement {
+      parser::Statement::Expression { expr, .. } => {
+        self.infer(expr);
+      },
+      parser::Statement::Let { name, type_annotation, value, .. } => {
+        let value_type = self.infer_value(value);
+        match type_annotation {
+          Some(_) => {
+            let expected = self.annotated_type(type_annotation);
+            if !expected.accepts(&value_type) {
+              self.error(format!("Cannot assign {} to \"{}\" of type {}", value_type, name.name, expected));
+            }
+            self.declare(name, expected, true);
+          },
+          None => self.declare(name, value_type, false),
+        }
+      },
+      parser::Statement::Assignment { lhs, rhs, .. } => {
+        let value_type = self.infer_value(rhs);
+        self.check_assignment(lhs, value_type);
+      },
+      parser::Statement::For { variable, iterable, body, .. } => {
+        let iterable_type = self.infer_value(iterable);
+        if !Type::Table.accepts(&iterable_type) {
+          self.error(format!("Cannot iterate over {}, expected a table", iterable_type));
+        }
+        self.declare(variable, Type::Any, false);
+        for statement in body {
+          self.check_statement(statement);
+        }
+      },
+      parser::Statement::Link { .. } => {},
+    }
+  }
+
+  fn check_assignment(&mut self, lhs: &parser::Expression, value_type: Type) {
+    match lhs {
+      parser::Expression::Identifier(iden) => {
+        let id = self.resolution.symbol(iden).id;
+        if self.annotated.contains(&id) {
+          let expected = self.variables[&id].clone();
+          if !expected.accepts(&value_type) {
+            self.error(format!("Cannot assign {} to \"{}\" of type {}", value_type, iden.name, expected));
+          }
+        } else {
+          self.variables.insert(id, value_type);
+        }
+      },
+      parser::Expression::Member { object, property } => {
+        let expected = self.infer_member(object, property);
+        if !expected.accepts(&value_type) {
+          self.error(format!("Cannot assign {} to property \"{}\" of type {}", value_type, property, expected));
+        }
+      },
+      parser::Expression::Index { object, index } => {
+        self.infer_index(object, index);
+      },
+      // The compiler rejects other assignment targets
+      _ => {},
+    }
+  }
+
+  /// Infers the type of an expression which must have a value
+  fn infer_value(&mut self, expr: &parser::Expression) -> Type {
+    match self.infer(expr) {
+      Type::Void => {
+        self.error(format!("Expression \"{}\" has no value", expression_source(expr)));
+        Type::Any
+      },
+      value_type => value_type,
+    }
+  }
+
+  /// Infers the type of an expression, recording the mismatches within it. Erroneous expressions are `Any`,
+  /// so one mistake isn't reported repeatedly.
+  fn infer(&mut self, expr: &parser::Expression) -> Type {
+    match expr {
+      parser::Expression::Literal(literal) => match literal {
+        parser::Literal::Integer(_) | parser::Literal::Float(_) => Type::Number,
+        parser::Literal::String(_) => Type::String,
+        parser::Literal::Bool(_) => Type::Bool,
+        parser::Literal::RawString(_) => Type::Any,
+      },
+      parser::Expression::Identifier(iden) => self.infer_identifier(iden),
+      parser::Expression::CWScriptBlockID(_) => Type::Any,
+      parser::Expression::BinOperation { lhs, op, rhs } => {
+        for operand in [lhs, rhs] {
+          let operand_type = self.infer_value(operand);
+          if !Type::Number.accepts(&operand_type) {
+            self.error(format!("Operator \"{}\" expects number operands, found {}", bin_operator(op), operand_type));
+          }
+        }
+        Type::Number
+      },
+      parser::Expression::UnaryOperation { op: parser::UnaryOperator::NumeralNegation, expr } => {
+        let operand_type = self.infer_value(expr);
+        if !Type::Number.accepts(&operand_type) {
+          self.error(format!("Negation expects a number, found {}", operand_type));
+        }
+        Type::Number
+      },
+      parser::Expression::UnaryOperation { op: parser::UnaryOperator::LogicalNegation, expr } => {
+        self.infer_value(expr);
+        Type::Bool
+      },
+      parser::Expression::Call { function, arguments } => self.infer_call(function, arguments),
+      parser::Expression::Member { object, property } => self.infer_member(object, property),
+      parser::Expression::Index { object, index } => self.infer_index(object, index),
+      parser::Expression::Template(parts) => {
+        for part in parts {
+          if let parser::TemplatePart::Expression(expr) = part
+            && let Type::Object(_) = self.infer_value(expr)
+          {
+            self.error(format!("Object \"{}\" cannot be embedded in a template literal", expression_source(expr)));
+          }
+        }
+        Type::String
+      },
+      parser::Expression::Array(elements) => {
+        elements.iter().for_each(|element| { self.infer_value(element); });
+        Type::Table
+      },
+      parser::Expression::Table(entries) => {
+        entries.iter().for_each(|(_, element)| { self.infer_value(element); });
+        Type::Table
+      },
+    }
+  }
+
+  fn infer_identifier(&mut self, iden: &parser::Identifier) -> Type {
+    let symbol = self.resolution.symbol(iden);
+    match symbol.symbol_type {
+      SymbolType::Variable | SymbolType::Global | SymbolType::Constant => self.variables.get(&symbol.id).cloned().unwrap_or(Type::Any),
+      SymbolType::Function => Type::Function(iden.name.clone()),
+      // Namespaces are only used in calls, which the resolver checks
+      SymbolType::Namespace => Type::Any,
+      SymbolType::UIObject => match self.site.lookup(&iden.name) {
+        Ok(object) => Type::Object(object.map(|object| object.class.clone())),
+        Err(err) => {
+          self.error(err.to_string());
+          Type::Any
+        },
+      },
+    }
+  }
+
+  fn infer_call(&mut self, function: &parser::Expression, arguments: &[parser::Expression]) -> Type {
+    let argument_types = arguments.iter().map(|arg| self.infer_value(arg)).collect::<Vec<Type>>();
+
+    if let Some(signature) = self.resolve_function(function) {
+      if signature.parameters.len() != argument_types.len() {
+        self.error(format!(
+          "Function \"{}\" expects {} arguments, found {}", signature.name, signature.parameters.len(), argument_types.len()
+        ));
+      }
+      for ((param_name, param_type), argument_type) in signature.parameters.iter().zip(&argument_types) {
+        if !param_type.accepts(argument_type) {
+          self.error(format!(
+            "Argument \"{}\" of function \"{}\" expects {}, found {}", param_name, signature.name, param_type, argument_type
+          ));
+        }
+      }
+      // Calls aren't lowered into results and there's no `return` yet, so only the uses of a result are errors
+      return Type::Void;
+    }
+
+    match function {
+      // Raw calls and table methods don't return values
+      parser::Expression::CWScriptBlockID(_) => Type::Void,
+      parser::Expression::Member { object, property } => match self.infer_value(object) {
+        Type::Table | Type::Any => Type::Void,
+        object_type => {
+          self.error(format!("Cannot call \"{}\" on {}, it is not a function", property, object_type));
+          Type::Any
+        },
+      },
+      _ => match self.infer(function) {
+        Type::Any => Type::Void,
+        callee_type => {
+          self.error(format!("Cannot call \"{}\" of type {}, it is not a function", expression_source(function), callee_type));
+          Type::Any
+        },
+      },
+    }
+  }
+
+  fn infer_member(&mut self, object: &parser::Expression, property: &str) -> Type {
+    let object_type = self.infer_value(object);
+    match object_type {
+      Type::Object(Some(class)) if !site::known_properties(&class).contains(&property) => {
+        self.error(format!("Unknown property \"{}\" on {} object", property, class));
+        Type::Any
+      },
+      Type::Object(_) => types::property_type(property),
+      Type::Table if property == "length" => Type::Number,
+      Type::Table => Type::Any,
+      // Values of unknown type are treated as UI objects if the property is one of theirs, like the compiler does
+      Type::Any if site::all_known_properties().contains(&property) => types::property_type(property),
+      Type::Any => Type::Any,
+      others => {
+        self.error(format!("Cannot access property \"{}\" of {}", property, others));
+        Type::Any
+      },
+    }
+  }
+
+  fn infer_index(&mut self, object: &parser::Expression, index: &parser::Expression) -> Type {
+    let object_type = self.infer_value(object);
+    self.infer_value(index);
+    if !Type::Table.accepts(&object_type) {
+      self.error(format!("Cannot index {}, expected a table", object_type));
+    }
+    Type::Any
+  }
+
+  /// Returns the function a call target names
+  fn resolve_function(&self, function: &parser::Expression) -> Option<&'a FunctionSignature> {
+    types::callee_path(function, self.resolution).and_then(|path| self.functions.get(&path))
+  }
+
+  fn declare(&mut self, iden: &parser::Identifier, variable_type: Type, is_annotated: bool) {
+    let id = self.resolution.symbol(iden).id;
+    self.variables.insert(id, variable_type);
+    if is_annotated {
+      self.annotated.insert(id);
+    }
+  }
+
+  /// Resolves a type annotation, reporting unknown types
+  fn annotated_type(&mut self, annotation: &Option<String>) -> Type {
+    match annotation.as_deref().map(Type::from_name) {
+      None => Type::Any,
+      Some(Ok(annotated_type)) => annotated_type,
+      Some(Err(err)) => {
+        self.error(err.to_string());
+        Type::Any
+      },
+    }
+  }
+
+  fn error(&mut self, message: String) {
+    let message = match self.function_name.is_empty() {
+      true => message,
+      false => format!("In function \"{}\": {}", self.function_name, message),
+    };
+    match self.position.is_known() {
+      true => self.errors.push(format!("{}: {}", self.position, message)),
+      false => self.errors.push(message),
+    }
+  }
+}
@@ -0,0 +1,563 @@
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use crate::parser;
+use crate::codegen::blocks;
+use crate::codegen::symbol_table::{self, SymbolId, SymbolType};
+
+use super::attributes::{self, Attribute};
+use super::resolver::Resolution;
+use super::types::{self, FunctionSignature};
+
+/// How a lint is reported
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Level {
+  Allow,
+  Warn,
+  /// Reported as an error, which fails the compilation
+  Deny,
+}
+
+impl Level {
+  pub fn from_name(name: &str) -> Option<Level> {
+    match name {
+      "allow" => Some(Level::Allow),
+      "warn" => Some(Level::Warn),
+      "deny" => Some(Level::Deny),
+      _ => None,
+    }
+  }
+}
+
+impl std::fmt::Display for Level {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Level::Allow => write!(f, "allow"),
+      Level::Warn => write!(f, "warn"),
+      Level::Deny => write!(f, "deny"),
+    }
+  }
+}
+
+/// Check for code which compiles, but is likely a mistake
+#[derive(Debug, PartialEq, Clone)]
+pub struct Lint {
+  pub name: &'static str,
+  pub description: &'static str,
+  pub default_level: Level,
+}
+
+pub const UNUSED_VARIABLES: &str = "unused_variables";
+pub const UNUSED_FUNCTIONS: &str = "unused_functions";
+pub const UNUSED_ASSIGNMENTS: &str = "unused_assignments";
+pub const SHADOWING: &str = "shadowing";
+pub const ALWAYS_TRUE_COMPARISONS: &str = "always_true_comparisons";
+pub const LOOPS_WITHOUT_WAIT: &str = "loops_without_wait";
+
+pub const LINTS: &[Lint] = &[
+  Lint { name: UNUSED_VARIABLES, description: "Variables and parameters which are never read", default_level: Level::Warn },
+  Lint { name: UNUSED_FUNCTIONS, description: "Functions which are never called", default_level: Level::Warn },
+  Lint { name: UNUSED_ASSIGNMENTS, description: "Values assigned to variables which are never read", default_level: Level::Warn },
+  Lint { name: SHADOWING, description: "Declarations which hide a symbol of the same name", default_level: Level::Warn },
+  Lint { name: ALWAYS_TRUE_COMPARISONS, description: "Comparisons of a value with itself", default_level: Level::Warn },
+  Lint { name: LOOPS_WITHOUT_WAIT, description: "Loops which never wait, freezing CatWeb until they finish", default_level: Level::Warn },
+];
+
+/// Finds a lint by name
+pub fn find(name: &str) -> Result<&'static Lint, anyhow::Error> {
+  LINTS.iter().find(|lint| lint.name == name).ok_or_else(|| {
+    let suggestion = LINTS.iter()
+      .map(|lint| (symbol_table::edit_distance(name, lint.name), lint.name))
+      .filter(|(distance, _)| *distance <= symbol_table::max_typo_distance(name))
+      .min()
+      .map(|(_, name)| format!(", did you mean \"{}\"?", name))
+      .unwrap_or_default();
+    anyhow::anyhow!("Unknown lint \"{}\"{}", name, suggestion)
+  })
+}
+
+/// Lint reported in the program
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+  pub lint: &'static str,
+  pub level: Level,
+  pub message: String,
+}
+
+impl Diagnostic {
+  /// Whether the lint is reported as an "error" or a "warning"
+  pub fn severity(&self) -> &'static str {
+    match self.level {
+      Level::Deny => "error",
+      Level::Allow | Level::Warn => "warning",
+    }
+  }
+}
+
+impl std::fmt::Display for Diagnostic {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}[{}]: {}", self.severity(), self.lint, self.message)
+  }
+}
+
+/// Levels set for lints, e.g. on the command line. Lints which aren't set have their default level.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct LintLevels {
+  levels: HashMap<&'static str, Level>,
+}
+
+impl LintLevels {
+  pub fn set(&mut self, name: &str, level: Level) -> Result<(), anyhow::Error> {
+    self.levels.insert(find(name)?.name, level);
+    Ok(())
+  }
+
+  pub fn level(&self, lint: &'static str) -> Level {
+    self.levels.get(lint).copied().unwrap_or_else(|| find(lint).expect("Lints should be known").default_level)
+  }
+
+  /// Returns the levels overridden by the `allow`, `warn` and `deny` attributes of an item or statement
+  fn with_attributes(&self, item_attributes: &[parser::Attribute], target: attributes::Target) -> LintLevels {
+    let mut levels = self.clone();
+    // Attributes are checked before linting
+    for attribute in Attribute::from_attributes(item_attributes, target).unwrap_or_default() {
+      if let Attribute::LintLevel(level, names) = attribute {
+        for name in names {
+          levels.set(&name, level).expect("Lints of attributes should be known");
+        }
+      }
+    }
+    levels
+  }
+}
+
+/// Levels within a statement, which are those around it unless it has attributes
+fn statement_levels(levels: &Rc<LintLevels>, statement: &parser::Statement) -> Rc<LintLevels> {
+  match statement.attributes().is_empty() {
+    true => levels.clone(),
+    false => Rc::new(levels.with_attributes(statement.attributes(), attributes::Target::Statement)),
+  }
+}
+
+/// Lint pass, which runs over the resolved and type checked syntax tree.
+///
+/// Every item is linted with the levels of the program, overridden by its `allow`, `warn` and `deny` attributes, and
+/// every statement with those of its item, overridden by its own attributes.
+pub struct Linter<'a> {
+  resolution: &'a Resolution,
+  functions: &'a HashMap<String, FunctionSignature>,
+  program_levels: &'a LintLevels,
+  /// Levels of the current item
+  levels: Rc<LintLevels>,
+  function_name: String,
+  function_position: parser::Position,
+  /// CatWeb names of the functions which wait, directly or through the functions they call
+  waiting: HashSet<String>,
+  diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Linter<'a> {
+  pub fn new(resolution: &'a Resolution, functions: &'a HashMap<String, FunctionSignature>, program_levels: &'a LintLevels) -> Self {
+    Self {
+      resolution,
+      functions,
+      program_levels,
+      levels: Rc::new(program_levels.clone()),
+      function_name: String::new(),
+      function_position: parser::Position::default(),
+      waiting: HashSet::new(),
+      diagnostics: Vec::new(),
+    }
+  }
+
+  pub fn lint_items(mut self, items: &[parser::Item]) -> Vec<Diagnostic> {
+    // Statements outside of functions run when the website loads
+    let statements = items.iter().filter_map(|item| match item {
+      parser::Item::Statement(statement) => Some(statement.clone()),
+      _ => None,
+    }).collect::<Vec<parser::Statement>>();
+    let called = self.called_functions(items);
+    self.find_waiting_functions(items);
+    self.lint_body(&[], &statements);
+
+    for item in items {
+      match item {
+        parser::Item::ConstDeclaration(constant) => {
+          self.enter_item(&constant.attributes, attributes::Target::Constant);
+          self.lint_shadowing(&constant.name, &self.levels.clone());
+        },
+        parser::Item::GlobalDeclaration(global) => {
+          self.enter_item(&global.attributes, attributes::Target::Global);
+          self.lint_shadowing(&global.name, &self.levels.clone());
+        },
+        parser::Item::FunctionDeclaration(func) => {
+          self.enter_item(&func.attributes, attributes::Target::Function);
+          if !called.contains(&self.functions[&func.name].name) && !func.name.starts_with('_') {
+            self.report(UNUSED_FUNCTIONS, &self.levels.clone(), func.position, format!("function \"{}\" is never called", func.name));
+          }
+          self.function_name = func.name.clone();
+          self.function_position = func.position;
+          self.lint_body(&func.parameters, &func.body);
+        },
+        parser::Item::Statement(_) => {},
+      }
+    }
+    self.diagnostics
+  }
+
+  /// Sets the lint levels of the item being linted
+  fn enter_item(&mut self, item_attributes: &[parser::Attribute], target: attributes::Target) {
+    self.function_name.clear();
+    self.levels = Rc::new(self.program_levels.with_attributes(item_attributes, target));
+  }
+
+  /// Finds the functions which wait, repeating until no caller of a waiting function is left
+  fn find_waiting_functions(&mut self, items: &[parser::Item]) {
+    loop {
+      let found = items.iter().filter_map(|item| match item {
+        parser::Item::FunctionDeclaration(func) => Some(func),
+        _ => None,
+      }).filter(|func| {
+        !self.waiting.contains(&self.functions[&func.name].name) && self.waits(&func.body)
+      }).map(|func| self.functions[&func.name].name.clone()).collect::<Vec<String>>();
+      if found.is_empty() {
+        break;
+      }
+      self.waiting.extend(found);
+    }
+  }
+
+  /// Returns the CatWeb names of the functions called by the program, not counting functions calling themselves
+  fn called_functions(&self, items: &[parser::Item]) -> HashSet<String> {
+    let mut called = HashSet::new();
+    for item in items {
+      let (caller, statements) = match item {
+        parser::Item::FunctionDeclaration(func) => (Some(&self.functions[&func.name].name), func.body.as_slice()),
+        parser::Item::Statement(statement) => (None, std::slice::from_ref(statement)),
+        _ => continue,
+      };
+      visit_expressions(statements, &mut |expr| {
+        if let parser::Expression::Call { function, .. } = expr
+          && let Some(signature) = self.callee(function)
+          && caller != Some(&signature.name)
+        {
+          called.insert(signature.name.clone());
+        }
+      });
+    }
+    called
+  }
+
+  /// Lints a function body, or the top-level statements. Parameters are reported at the position of the function.
+  fn lint_body(&mut self, parameters: &[parser::Parameter], body: &[parser::Statement]) {
+    let mut accesses = Accesses::new(self.resolution, self.levels.clone());
+    for param in parameters {
+      accesses.declare(&param.name, "parameter", self.function_position);
+    }
+    for statement in body {
+      accesses.statement(statement);
+    }
+
+    for declaration in &accesses.declarations {
+      self.lint_shadowing(&declaration.name, &declaration.levels);
+    }
+    self.lint_unused_variables(&accesses);
+    self.lint_comparisons(body, &self.levels.clone());
+    self.lint_loops(body, &self.levels.clone());
+  }
+
+  fn lint_shadowing(&mut self, iden: &parser::Identifier, levels: &LintLevels) {
+    let symbol = self.resolution.symbol(iden);
+    if let Some(shadowed) = self.resolution.shadowed.get(&symbol.id) {
+      self.report(SHADOWING, levels, iden.position, format!("{} \"{}\" shadows the {} of the same name", symbol.symbol_type, iden.name, shadowed));
+    }
+  }
+
+  fn lint_unused_variables(&mut self, accesses: &Accesses) {
+    for declaration in &accesses.declarations {
+      let iden = &declaration.name;
+      let id = self.resolution.symbol(iden).id;
+      // Names starting with an underscore are unused on purpose
+      if iden.name.starts_with('_') {
+        continue;
+      }
+      if !accesses.is_read(id) {
+        self.report(UNUSED_VARIABLES, &declaration.levels, declaration.position, format!("unused {} \"{}\"", declaration.kind, iden.name));
+        continue;
+      }
+      for index in accesses.unread_writes(id) {
+        let write = &accesses.events[index];
+        self.report(UNUSED_ASSIGNMENTS, &write.levels, write.position, format!("value assigned to \"{}\" is never read", write.name));
+      }
+    }
+  }
+
+  /// Reports raw `if equal` calls on two equal literals or on the same variable
+  fn lint_comparisons(&mut self, statements: &[parser::Statement], levels: &Rc<LintLevels>) {
+    for statement in statements {
+      let levels = statement_levels(levels, statement);
+      let mut messages = Vec::new();
+      visit_statement_expressions(statement, &mut |expr| {
+        if let parser::Expression::Call { function, arguments } = expr
+          && let parser::Expression::CWScriptBlockID(block_id) = function.as_ref()
+          && block_id == blocks::IF_EQUAL
+          && let [lhs, rhs] = arguments.as_slice()
+        {
+          match (lhs, rhs) {
+            (parser::Expression::Identifier(lhs), parser::Expression::Identifier(rhs)) if lhs.symbol == rhs.symbol => {
+              messages.push(format!("comparison of \"{}\" with itself is always true", lhs.name));
+            },
+            (parser::Expression::Literal(lhs), parser::Expression::Literal(rhs)) if lhs == rhs => {
+              messages.push("comparison of equal literals is always true".to_string());
+            },
+            _ => {},
+          }
+        }
+      });
+      for message in messages {
+        self.report(ALWAYS_TRUE_COMPARISONS, &levels, statement.position(), message);
+      }
+      if let parser::Statement::For { body, .. } = statement {
+        self.lint_comparisons(body, &levels);
+      }
+    }
+  }
+
+  fn lint_loops(&mut self, statements: &[parser::Statement], levels: &Rc<LintLevels>) {
+    for statement in statements {
+      let levels = statement_levels(levels, statement);
+      if let parser::Statement::For { body, position, .. } = statement {
+        if !self.waits(body) {
+          self.report(LOOPS_WITHOUT_WAIT, &levels, *position, "loop never waits, so CatWeb freezes until it finishes. Call wait() in it.".to_string());
+        }
+        self.lint_loops(body, &levels);
+      }
+    }
+  }
+
+  /// Whether the statements call `wait`, the CatWeb wait action, or a function which waits
+  fn waits(&self, statements: &[parser::Statement]) -> bool {
+    let mut waits = false;
+    visit_expressions(statements, &mut |expr| {
+      if let parser::Expression::Call { function, .. } = expr {
+        waits |= match function.as_ref() {
+          parser::Expression::CWScriptBlockID(block_id) => block_id == blocks::WAIT,
+          function => self.callee(function).is_some_and(|signature| signature.name == "wait" || self.waiting.contains(&signature.name)),
+        };
+      }
+    });
+    waits
+  }
+
+  fn callee(&self, function: &parser::Expression) -> Option<&'a FunctionSignature> {
+    types::callee_path(function, self.resolution).and_then(|path| self.functions.get(&path))
+  }
+
+  fn report(&mut self, lint: &'static str, levels: &LintLevels, position: parser::Position, message: String) {
+    let level = levels.level(lint);
+    if level == Level::Allow {
+      return;
+    }
+    let message = match self.function_name.is_empty() {
+      true => message,
+      false => format!("In function \"{}\": {}", self.function_name, message),
+    };
+    let message = match position.is_known() {
+      true => format!("{}: {}", position, message),
+      false => message,
+    };
+    self.diagnostics.push(Diagnostic { lint, level, message });
+  }
+}
+
+/// Read or write of a variable
+struct Access {
+  symbol: SymbolId,
+  name: String,
+  is_write: bool,
+  /// Loops the access is in, outermost first
+  loops: Vec<usize>,
+  /// Position of the statement the access is in
+  position: parser::Position,
+  /// Levels of the statement the access is in
+  levels: Rc<LintLevels>,
+}
+
+/// Variable or parameter declared in a body
+struct Declaration {
+  name: parser::Identifier,
+  kind: &'static str,
+  position: parser::Position,
+  levels: Rc<LintLevels>,
+}
+
+/// Declarations of a body, and the accesses of its variables in execution order
+struct Accesses<'a> {
+  resolution: &'a Resolution,
+  declarations: Vec<Declaration>,
+  events: Vec<Access>,
+  loops: Vec<usize>,
+  loop_count: usize,
+  /// Position of the current statement
+  position: parser::Position,
+  /// Levels of the current statement
+  levels: Rc<LintLevels>,
+}
+
+impl<'a> Accesses<'a> {
+  fn new(resolution: &'a Resolution, levels: Rc<LintLevels>) -> Self {
+    Self {
+      resolution,
+      declarations: Vec::new(),
+      events: Vec::new(),
+      loops: Vec::new(),
+      loop_count: 0,
+      position: parser::Position::default(),
+      levels,
+    }
+  }
+
+  fn declare(&mut self, iden: &parser::Identifier, kind: &'static str, position: parser::Position) {
+    self.declarations.push(Declaration { name: iden.clone(), kind, position, levels: self.levels.clone() });
+  }
+
+  fn statement(&mut self, statement: &parser::Statement) {
+    let outer_levels = self.levels.clone();
+    self.levels = statement_levels(&outer_levels, statement);
+    self.position = statement.position();
+    match statement {
+      parser::Statement::Expression { expr, .. } => self.read(expr),
+      parser::Statement::Let { name, value, .. } => {
+        self.read(value);
+        self.declare(name, "variable", self.position);
+        self.write(name);
+      },
+      parser::Statement::Assignment { lhs, rhs, .. } => {
+        self.read(rhs);
+        match lhs {
+          parser::Expression::Identifier(iden) => self.write(iden),
+          // Writing an entry or property uses the table or object
+          others => self.read(others),
+        }
+      },
+      parser::Statement::For { variable, iterable, body, .. } => {
+        self.read(iterable);
+        self.loop_count += 1;
+        self.loops.push(self.loop_count);
+        self.declare(variable, "variable", self.position);
+        self.write(variable);
+        for statement in body {
+          self.statement(statement);
+        }
+        self.loops.pop();
+      },
+      parser::Statement::Link { .. } => {},
+    }
+    self.levels = outer_levels;
+  }
+
+  fn read(&mut self, expr: &parser::Expression) {
+    visit_expression(expr, &mut |expr| {
+      if let parser::Expression::Identifier(iden) = expr {
+        self.access(iden, false);
+      }
+    });
+  }
+
+  fn write(&mut self, iden: &parser::Identifier) {
+    self.access(iden, true);
+  }
+
+  fn access(&mut self, iden: &parser::Identifier, is_write: bool) {
+    let symbol = self.resolution.symbol(iden);
+    if symbol.symbol_type == SymbolType::Variable {
+      self.events.push(Access {
+        symbol: symbol.id,
+        name: iden.name.clone(),
+        is_write,
+        loops: self.loops.clone(),
+        position: self.position,
+        levels: self.levels.clone(),
+      });
+    }
+  }
+
+  fn is_read(&self, symbol: SymbolId) -> bool {
+    self.events.iter().any(|event| event.symbol == symbol && !event.is_write)
+  }
+
+  /// Returns the writes of a variable whose value is never read
+  fn unread_writes(&self, symbol: SymbolId) -> Vec<usize> {
+    let events = self.events.iter().enumerate().filter(|(_, event)| event.symbol == symbol).collect::<Vec<(usize, &Access)>>();
+    events.iter().enumerate().filter(|(position, (_, write))| {
+      if !write.is_write {
+        return false;
+      }
+      let later = &events[position + 1..];
+      // Loops around the write may read the value on their next iteration
+      let read_in_loop = events.iter().any(|(_, read)| !read.is_write && read.loops.iter().any(|id| write.loops.contains(id)));
+      let read_later = later.iter().any(|(_, read)| !read.is_write);
+      // Writes in the same loop always run, while writes in nested loops may not
+      let overwritten = later.first().is_some_and(|(_, next)| next.is_write && next.loops == write.loops);
+      overwritten || !(read_later || read_in_loop)
+    }).map(|(_, (index, _))| *index).collect()
+  }
+}
+
+/// Visits every expression of the statements, including nested ones, outermost first
+fn visit_expressions(statements: &[parser::Statement], visitor: &mut impl FnMut(&parser::Expression)) {
+  visit_statements(statements, &mut |statement| visit_statement_expressions(statement, visitor));
+}
+
+/// Visits the expressions of a statement, but not those of the statements in its body
+fn visit_statement_expressions(statement: &parser::Statement, visitor: &mut impl FnMut(&parser::Expression)) {
+  match statement {
+    parser::Statement::Expression { expr, .. } | parser::Statement::Let { value: expr, .. } => visit_expression(expr, visitor),
+    parser::Statement::Assignment { lhs, rhs, .. } => {
+      visit_expression(rhs, visitor);
+      visit_expression(lhs, visitor);
+    },
+    parser::Statement::For { iterable, .. } => visit_expression(iterable, visitor),
+    parser::Statement::Link { .. } => {},
+  }
+}
+
+/// Visits every statement, including those in loops, in order
+fn visit_statements(statements: &[parser::Statement], visitor: &mut impl FnMut(&parser::Statement)) {
+  for statement in statements {
+    visitor(statement);
+    if let parser::Statement::For { body, .. } = statement {
+      visit_statements(body, visitor);
+    }
+  }
+}
+
+fn visit_expression(expr: &parser::Expression, visitor: &mut impl FnMut(&parser::Expression)) {
+  visitor(expr);
+  match expr {
+    parser::Expression::Literal(_) | parser::Expression::Identifier(_) | parser::Expression::CWScriptBlockID(_) => {},
+    parser::Expression::BinOperation { lhs, rhs, .. } => {
+      visit_expression(lhs, visitor);
+      visit_expression(rhs, visitor);
+    },
+    parser::Expression::UnaryOperation { expr, .. } => visit_expression(expr, visitor),
+    parser::Expression::Call { function, arguments } => {
+      visit_expression(function, visitor);
+      arguments.iter().for_each(|arg| visit_expression(arg, visitor));
+    },
+    parser::Expression::Member { object, .. } => visit_expression(object, visitor),
+    parser::Expression::Index { object, index } => {
+      visit_expression(object, visitor);
+      visit_expression(index, visitor);
+    },
+    parser::Expression::Template(parts) => {
+      for part in parts {
+        if let parser::TemplatePart::Expression(expr) = part {
+          visit_expression(expr, visitor);
+        }
+      }
+    },
+    parser::Expression::Array(elements) => elements.iter().for_each(|element| visit_expression(element, visitor)),
+    parser::Expression::Table(entries) => entries.iter().for_each(|(_, element)| visit_expression(element, visitor)),
+  }
+}
@@ -1,51 +1,65 @@
-use std::vec;
+use std::collections::{HashMap, HashSet};
 
 use crate::parser;
 use crate::codegen;
-use crate::parser::BinOperator;
-use crate::parser::Item;
-
-use anyhow;
-use regex_macro::{regex};
+use crate::codegen::{blocks, site};
+use crate::codegen::symbol_table::{SymbolId, SymbolType};
+use crate::parser::{BinOperator, UnaryOperator};
 
+pub mod attributes;
+mod checker;
+pub mod lints;
+pub mod recursion;
+pub mod resolver;
+pub mod stdlib;
+mod tables;
+pub mod temporaries;
 mod test;
+pub mod types;
+
+use attributes::Attribute;
+
+/// Identifiers with this prefix are reserved for compiler-generated variables
+pub const RESERVED_PREFIX: &str = "__";
 
 #[derive(Debug, PartialEq, Clone)]
-pub enum Attribute {
-  Inline,
-  ExportAs(String),
+pub struct CompilerState {
+  temporary_count: usize,
+  /// Kinds of the values held by variables in the current function, where known
+  value_kinds: HashMap<SymbolId, ValueKind>,
+  /// Kinds of the values of global variables, where known
+  global_value_kinds: HashMap<SymbolId, ValueKind>,
+  /// Values of the constants, which are substituted where they are used
+  constants: HashMap<SymbolId, codegen::Literal>,
 }
 
+/// Kinds of values whose operations lower to different CatWeb blocks
 #[derive(Debug, PartialEq, Clone)]
-pub struct CompilerState {
-  attributes: Vec<Attribute>,
+pub enum ValueKind {
+  /// Linked UI object of the given class
+  Object(String),
+  Table,
 }
 
 impl CompilerState {
   pub fn new() -> Self {
     Self {
-      attributes: Vec::new(),
+      temporary_count: 0,
+      value_kinds: HashMap::new(),
+      global_value_kinds: HashMap::new(),
+      constants: HashMap::new(),
     }
   }
 
-  pub fn add_attribute(&mut self, attribute: &parser::Attribute) -> Result<(), anyhow::Error> {
-    match attribute {
-      parser::Attribute::Inline => {
-        self.attributes.push(Attribute::Inline);
-        return Ok(())
-      },
-      parser::Attribute::ExportAs(content_str) => {
-        self.attributes.push(Attribute::ExportAs(content_str.clone()));
-        return Ok(())
-      },
-      _ => {
-        return Err(anyhow::anyhow!("Attribute \"export_as\" requires a content string"))
-      }
-    }
+  /// Clears the state local to a function
+  pub fn enter_function(&mut self) {
+    self.value_kinds = self.global_value_kinds.clone();
   }
 
-  pub fn pop_all_attributes(&mut self) -> Vec<Attribute> {
-    std::mem::take(&mut self.attributes)
+  /// Creates a new virtual temporary. Slots are assigned later by `temporaries::allocate_temporaries`.
+  pub fn new_temporary(&mut self) -> codegen::Variable {
+    self.temporary_count += 1;
+    codegen::Variable::virtual_temporary(self.temporary_count - 1)
   }
 }
 
@@ -56,50 +70,272 @@ impl CompilerState {
 /// This includes tasks such as:
 /// - Expanding expressions and statements into CatWeb equivalent structures (calls/ control flow structures)
 /// - Inlining functions marked with the `inline` attribute
+/// - Allocating CatWeb variables for the temporaries of expressions
+/// - Checking the types of expressions against the annotated and inferred types
 #[derive(Debug, PartialEq, Clone)]
 pub struct Compiler {
   syntax_tree: parser::Program,
-  state: CompilerState
+  state: CompilerState,
+  site: site::SiteData,
+  /// Signatures of the functions of the program and the standard library, by the names they can be called with
+  functions: HashMap<String, types::FunctionSignature>,
+  /// Symbols the identifiers of the syntax tree are resolved to
+  resolution: resolver::Resolution,
+  /// Warnings of the passes after linting
+  warnings: Vec<String>,
+  lint_levels: lints::LintLevels,
+  /// Whether the standard library is linked
+  use_stdlib: bool,
+  lints: Vec<lints::Diagnostic>,
+  variable_reports: Vec<temporaries::VariableReport>,
+  stdlib_functions: Vec<String>,
 }
 
 impl Compiler {
   pub fn new(syntax_tree: parser::Program) -> Self {
-    let mut temp = Self { syntax_tree, state: CompilerState::new() };
-    // FIXME: Hoisting is unnecessary in CatWeb
-    // temp.hoist_items();
-    temp
+    Self {
+      syntax_tree,
+      state: CompilerState::new(),
+      site: site::SiteData::default(),
+      functions: HashMap::new(),
+      resolution: resolver::Resolution::default(),
+      warnings: Vec::new(),
+      lint_levels: lints::LintLevels::default(),
+      use_stdlib: true,
+      lints: Vec::new(),
+      variable_reports: Vec::new(),
+      stdlib_functions: Vec::new(),
+    }
+  }
+
+  /// Sets the linked site data which UI object identifiers are resolved against
+  pub fn with_site_data(mut self, site: site::SiteData) -> Self {
+    self.site = site;
+    self
+  }
+
+  /// Sets the levels lints are reported with, unless an item or statement overrides them with attributes
+  pub fn with_lint_levels(mut self, levels: lints::LintLevels) -> Self {
+    self.lint_levels = levels;
+    self
+  }
+
+  /// Sets whether the standard library is linked. Without it, programs can only use raw CatWeb block calls.
+  pub fn with_stdlib(mut self, use_stdlib: bool) -> Self {
+    self.use_stdlib = use_stdlib;
+    self
   }
 
   // TODO: See if the implementation is correct
-  pub fn compile(self: &mut Compiler) -> codegen::Program {
+  pub fn compile(self: &mut Compiler) -> Result<codegen::Program, anyhow::Error> {
     // Temporarily take the main_block out of self to avoid borrow conflict
     // This leaves an empty Vec inside self.syntax_tree.main_block temporarily
-    let main_block = std::mem::take(&mut self.syntax_tree.main_block);
+    let mut main_block = std::mem::take(&mut self.syntax_tree.main_block);
 
     // Compilation logic goes here
-    let compiled_items: Vec<codegen::Item> = main_block.iter().filter_map(|item| {
-      self.compile_item(item)
-    }).collect();
+    let compiled_program = self.compile_program(&mut main_block);
+
+    // Restore the main block, which keeps the resolved symbols of its identifiers
+    self.syntax_tree.main_block = main_block;
+
+    let mut program = compiled_program?;
+    self.variable_reports = temporaries::allocate_temporaries(&mut program);
+    Ok(program)
+  }
+
+  /// Syntax tree of the program, whose identifiers are resolved after `compile` or `lint`
+  pub fn syntax_tree(&self) -> &parser::Program {
+    &self.syntax_tree
+  }
 
-    // Restore the main block
+  /// CatWeb names of the locals of each compiled function, available after `compile`
+  pub fn name_mapping(&self) -> Vec<&resolver::MangledName> {
+    // Standard library functions which aren't called are not compiled
+    self.resolution.name_mapping().into_iter()
+      .filter(|mapping| self.variable_reports.iter().any(|report| report.item == mapping.function))
+      .collect()
+  }
+
+  /// Checks the program without generating code, reporting the lints which fire, available as `lints`
+  pub fn lint(&mut self) -> Result<(), anyhow::Error> {
+    let mut main_block = std::mem::take(&mut self.syntax_tree.main_block);
+    let analyzed = self.analyze(&mut main_block);
     self.syntax_tree.main_block = main_block;
+    analyzed.map(|_| ())
+  }
 
-    codegen::Program::new(compiled_items)
+  /// Fails if a denied lint fired
+  pub fn check_denied_lints(&self) -> Result<(), anyhow::Error> {
+    let denied = self.lints.iter().filter(|diagnostic| diagnostic.level == lints::Level::Deny).count();
+    match denied {
+      0 => Ok(()),
+      _ => Err(anyhow::anyhow!("Aborting because of {} denied lints", denied)),
+    }
+  }
+
+  /// Warnings found during compilation, available after `compile`
+  pub fn warnings(&self) -> &[String] {
+    &self.warnings
+  }
+
+  /// Lints which fired, except allowed ones, available after `compile` or `lint`
+  pub fn lints(&self) -> &[lints::Diagnostic] {
+    &self.lints
+  }
+
+  fn compile_program(&mut self, main_block: &mut [parser::Item]) -> Result<codegen::Program, anyhow::Error> {
+    let stdlib_items = self.analyze(main_block)?;
+    self.check_denied_lints()?;
+
+    let load_statements = self.compile_load_statements(main_block)?;
+    let mut items = self.compile_items(main_block)?;
+    if !load_statements.is_empty() {
+      Compiler::load_event(&mut items).extend(load_statements);
+    }
+    items.extend(self.link_stdlib(&items, &stdlib_items)?);
+
+    if self.save_recursive_frames(&mut items) {
+      Compiler::load_event(&mut items).insert(0, Compiler::generate_call_stack());
+    }
+    Ok(codegen::Program::new(items))
   }
 
-  fn compile_item(self: &mut Compiler, item: &parser::Item) -> Option<codegen::Item> {
+  /// Checks attributes, resolves names, checks types and lints the program. Returns the analyzed standard library.
+  fn analyze(&mut self, main_block: &mut [parser::Item]) -> Result<Vec<parser::Item>, anyhow::Error> {
+    // Functions of the program take precedence over those of the standard library
+    let mut stdlib_items = match self.use_stdlib {
+      true => stdlib::items()?,
+      false => Vec::new(),
+    };
+    attributes::check_items(&stdlib_items)?;
+    attributes::check_items(main_block)?;
+    self.functions.clear();
+    types::collect_signatures(&stdlib_items, &mut self.functions)?;
+    types::collect_signatures(main_block, &mut self.functions)?;
+
+    let mut resolver = resolver::Resolver::new(&self.site, &self.functions);
+    resolver.resolve_items(&mut stdlib_items);
+    resolver.resolve_items(main_block);
+    self.resolution = resolver.finish()?;
+    self.warnings.clear();
+
+    checker::TypeChecker::new(&self.site, &self.functions, &self.resolution).check_items(main_block)?;
+    self.lints = lints::Linter::new(&self.resolution, &self.functions, &self.lint_levels).lint_items(main_block);
+    Ok(stdlib_items)
+  }
+
+  /// Evaluates the constants, then compiles the initializers of the globals and the statements outside of functions,
+  /// which run in order when the website loads
+  fn compile_load_statements(&mut self, items: &[parser::Item]) -> Result<Vec<codegen::Statement>, anyhow::Error> {
+    for item in items {
+      if let parser::Item::ConstDeclaration(constant) = item {
+        let id = self.resolution.symbol(&constant.name).id;
+        let compiled = self.compile_expression(&constant.value)?;
+        match compiled.content {
+          Some(codegen::Argument::Literal(literal)) if compiled.dependencies.is_empty() => {
+            self.state.constants.insert(id, literal);
+          },
+          _ => return Err(anyhow::anyhow!("Constant \"{}\" must be initialized with a literal", constant.name.name)),
+        }
+      }
+    }
+
+    let mut statements = Vec::new();
+    self.state.enter_function();
+    for item in items {
+      match item {
+        parser::Item::GlobalDeclaration(global) => {
+          let id = self.resolution.symbol(&global.name).id;
+          let annotated_kind = global.type_annotation.as_deref().map(types::Type::from_name).transpose()?.and_then(|ty| ty.value_kind());
+          if let Some(kind) = &annotated_kind {
+            self.state.global_value_kinds.insert(id, kind.clone());
+            self.state.value_kinds.insert(id, kind.clone());
+          }
+          if let Some(value) = &global.value {
+            let statement = self.compile_variable_assignment(&global.name, value, annotated_kind)?;
+            statements.push(codegen::Statement { position: global.position, ..statement });
+            // The type checker fixes the type of globals to that of their value
+            if let Some(kind) = self.state.value_kinds.get(&id) {
+              self.state.global_value_kinds.insert(id, kind.clone());
+            }
+          }
+        },
+        parser::Item::Statement(statement) => statements.extend(self.compile_statement(statement)?),
+        _ => {},
+      }
+    }
+    Ok(statements)
+  }
+
+  /// Returns the body of the event which runs when the website loads, creating it as the first item if needed
+  fn load_event(items: &mut Vec<codegen::Item>) -> &mut Vec<codegen::Statement> {
+    let is_load_event = |item: &codegen::Item| matches!(item, codegen::Item::Event { name, .. } if name == blocks::WEBSITE_LOADED);
+    if !items.first().is_some_and(is_load_event) {
+      items.insert(0, codegen::Item::Event { name: blocks::WEBSITE_LOADED.to_string(), body: Vec::new() });
+    }
+    match &mut items[0] {
+      codegen::Item::Event { body, .. } => body,
+      codegen::Item::FunctionDeclaration { .. } => unreachable!("The load event should be the first item"),
+    }
+  }
+
+  fn compile_items(&mut self, items: &[parser::Item]) -> Result<Vec<codegen::Item>, anyhow::Error> {
+    let compiled_items = items.iter()
+      .map(|item| self.compile_item(item))
+      .collect::<Result<Vec<Option<codegen::Item>>, anyhow::Error>>()?;
+    Ok(compiled_items.into_iter().flatten().collect())
+  }
+
+  /// Compiles the standard library functions called by the items, which the program doesn't declare itself
+  fn link_stdlib(&mut self, items: &[codegen::Item], stdlib_items: &[parser::Item]) -> Result<Vec<codegen::Item>, anyhow::Error> {
+    let declared = items.iter().filter_map(|item| match item {
+      codegen::Item::FunctionDeclaration { name, .. } => Some(name.clone()),
+      codegen::Item::Event { .. } => None,
+    }).collect::<HashSet<String>>();
+    let called = items.iter().flat_map(|item| match item {
+      codegen::Item::FunctionDeclaration { body, .. } | codegen::Item::Event { body, .. } => body.clone(),
+    })
+      .flat_map(|statement| statement.into_calls())
+      .filter_map(|call| match call {
+        codegen::Call::FunctionCall { function_name, .. } => Some(function_name.name),
+        codegen::Call::CWScriptBlockCall { .. } => None,
+      })
+      .collect::<HashSet<String>>();
+
+    let linked: Vec<codegen::Item> = self.compile_items(stdlib_items)?.into_iter().filter(|item| match item {
+      codegen::Item::FunctionDeclaration { name, .. } => called.contains(name) && !declared.contains(name),
+      codegen::Item::Event { .. } => false,
+    }).collect();
+    self.stdlib_functions = linked.iter().filter_map(|item| match item {
+      codegen::Item::FunctionDeclaration { name, .. } => Some(name.clone()),
+      codegen::Item::Event { .. } => None,
+    }).collect();
+    Ok(linked)
+  }
+
+  /// Standard library functions compiled into the program, available after `compile`
+  pub fn stdlib_functions(&self) -> &[String] {
+    &self.stdlib_functions
+  }
+
+  /// Variable usage of each compiled item, available after `compile`
+  pub fn variable_reports(&self) -> &[temporaries::VariableReport] {
+    &self.variable_reports
+  }
+
+  fn compile_item(self: &mut Compiler, item: &parser::Item) -> Result<Option<codegen::Item>, anyhow::Error> {
     match item {
-      parser::Item::Attribute(attr) => {
-        self.state.add_attribute(attr).unwrap();
-        None
-      },
+      // Globals are plain CatWeb variables, which don't need to be declared, and constants are substituted
+      parser::Item::ConstDeclaration(_) | parser::Item::GlobalDeclaration(_) => Ok(None),
+      // Statements outside of functions are compiled into the load event
+      parser::Item::Statement(_) => Ok(None),
       parser::Item::FunctionDeclaration(func) => {
         // Compile function declaration
 
-        let attributes = self.state.pop_all_attributes();
+        let attributes = Attribute::from_attributes(&func.attributes, attributes::Target::Function)?;
 
         // FIXME: Implement function inlining
-        let inlining: bool = attributes.iter().any(|attr| matches!(attr, Attribute::Inline));
+        let _inlining: bool = attributes.iter().any(|attr| matches!(attr, Attribute::Inline));
 
         // FIXME: Implement function renaming on symbol table
 
@@ -110,37 +346,112 @@ impl Compiler {
           _ => None,
         });
 
-        Some(codegen::Item::FunctionDeclaration {
-          // TODO: Register function in symbol table
+        self.state.enter_function();
+        let parameters = func.parameters.iter().map(|param| {
+          // Annotated parameters have a known kind, e.g. `label: TextLabel`
+          if let Some(kind) = param.type_annotation.as_deref().map(types::Type::from_name).transpose()?.and_then(|ty| ty.value_kind()) {
+            self.state.value_kinds.insert(self.resolution.symbol(&param.name).id, kind);
+          }
+          self.variable(&param.name)
+        }).collect::<Result<Vec<codegen::Variable>, anyhow::Error>>()?;
+
+        let mut body = Vec::new();
+        for stmt in &func.body {
+          body.extend(self.compile_statement(stmt)?);
+        }
+        Ok(Some(codegen::Item::FunctionDeclaration {
           name: export_as.unwrap_or_else(|| func.name.clone()),
-          body: func.body.iter().map(|stmt| self.compile_statement(stmt)).collect::<Vec<codegen::Statement>>(),
-          // TODO: SYMBOL TABLE?
-          parameters: func.parameters.iter().map(|param| {
-            match param {
-              parser::Expression::Identifier(iden) => codegen::Variable { name: iden.clone() },
-              _ => unimplemented!("Unsupported parameter type in function declaration: {:?}", param),
-            }
-          }).collect::<Vec<codegen::Variable>>(),
-        })
+          body,
+          parameters,
+          position: func.position,
+          comments: Attribute::comments(&attributes),
+        }))
       }
     }
   }
 
-  fn compile_statement(self: &mut Compiler, stmt: &parser::Statement) -> codegen::Statement {
-    match stmt {
+  /// Compiles a statement, into several if it's a loop so the statements of its body keep their own position
+  fn compile_statement(self: &mut Compiler, stmt: &parser::Statement) -> Result<Vec<codegen::Statement>, anyhow::Error> {
+    let comments = Attribute::comments(&Attribute::from_attributes(stmt.attributes(), attributes::Target::Statement)?);
+    let statement = match stmt {
       // TODO: Implement link statement compilation
-      parser::Statement::Expression { expr } => {
-        codegen::Statement {
-          dependencies: self.compile_expression(expr).dependencies,
-          content: Vec::new(),
+      parser::Statement::Expression { expr, .. } => {
+        Ok(codegen::Statement::new(self.compile_expression(expr)?.dependencies, Vec::new()))
+      },
+      parser::Statement::Let { name, type_annotation, value, .. } => {
+        // Annotations take precedence over the kind of the value
+        let annotated_kind = type_annotation.as_deref().map(types::Type::from_name).transpose()?.and_then(|ty| ty.value_kind());
+        self.compile_variable_assignment(name, value, annotated_kind)
+      },
+      parser::Statement::Assignment { lhs, rhs, .. } => {
+        match lhs {
+          parser::Expression::Identifier(iden) => self.compile_variable_assignment(iden, rhs, None),
+          // Table writes, e.g. `t[i] = 1;` and `t.key = 1;`
+          parser::Expression::Index { object, index } => {
+            let key = self.compile_expression(index)?;
+            self.compile_table_write(object, key, rhs)
+          },
+          parser::Expression::Member { object, property } if self.is_table_member(object, property)? => {
+            let key = codegen::Expression {
+              dependencies: Vec::new(),
+              content: Some(codegen::Argument::Literal(codegen::Literal { value: property.clone() })),
+            };
+            self.compile_table_write(object, key, rhs)
+          },
+          // Property writes, e.g. `obj.text = "Hi";`
+          parser::Expression::Member { object, property } => {
+            let (object_compiled, property) = self.compile_property_access(object, property)?;
+            let rhs_compiled = self.compile_expression(rhs)?;
+            let value = rhs_compiled.content.ok_or_else(|| anyhow::anyhow!("Expression assigned to property \"{}\" has no value", property))?;
+            Ok(codegen::Statement::new(
+              object_compiled.dependencies.into_iter().chain(rhs_compiled.dependencies).collect(),
+              vec![Compiler::generate_block_call(blocks::SET_PROPERTY, vec![
+                codegen::Argument::Literal(codegen::Literal { value: property }),
+                object_compiled.content.expect("Compiled object should have a value"),
+                value,
+              ])],
+            ))
+          },
+          others => Err(anyhow::anyhow!("Unsupported assignment target: {:?}", others)),
         }
       },
-      parser::Statement::Assignment { lhs, rhs } => todo!("Assignment statement compilation to be implemented: {:?} = {:?}", lhs, rhs),
-      parser::Statement::Link { path } => todo!("Linking CatWeb JSON to object identifiers to be implemented: {}", path),
-    }
+      parser::Statement::For { variable, iterable, body, position, .. } => {
+        let mut statements = self.compile_for_statement(variable, iterable, body, *position)?;
+        statements[0].comments = comments;
+        return Ok(statements);
+      },
+      parser::Statement::Link { path, .. } => Err(anyhow::anyhow!("Link statement \"{}\" is only allowed at the start of a program", path)),
+    }?;
+    Ok(vec![codegen::Statement { position: stmt.position(), comments, ..statement }])
   }
 
-  fn compile_expression(self: &mut Compiler, expr: &parser::Expression) -> codegen::Expression {
+  /// Compiles `name = rhs;` and `let name = rhs;`
+  fn compile_variable_assignment(&mut self, name: &parser::Identifier, rhs: &parser::Expression, annotated_kind: Option<ValueKind>) -> Result<codegen::Statement, anyhow::Error> {
+    let target = self.variable(name)?;
+    let id = self.resolution.symbol(name).id;
+    // Remember what kind of value the variable holds, for lowering member accesses on it
+    let kind = match annotated_kind {
+      Some(kind) => Some(kind),
+      None => self.value_kind(rhs)?,
+    };
+
+    let statement = if let parser::Expression::Array(_) | parser::Expression::Table(_) = rhs {
+      // Collections are built in the variable directly, rather than in a temporary
+      codegen::Statement::new(Vec::new(), self.compile_collection(rhs, target)?)
+    } else {
+      let rhs_compiled = self.compile_expression(rhs)?;
+      let value = rhs_compiled.content.ok_or_else(|| anyhow::anyhow!("Expression assigned to \"{}\" has no value", target.name))?;
+      codegen::Statement::new(rhs_compiled.dependencies, vec![Compiler::generate_set_variable(target, value)])
+    };
+
+    match kind {
+      Some(kind) => self.state.value_kinds.insert(id, kind),
+      None => self.state.value_kinds.remove(&id),
+    };
+    Ok(statement)
+  }
+
+  fn compile_expression(self: &mut Compiler, expr: &parser::Expression) -> Result<codegen::Expression, anyhow::Error> {
     // Compile the expression based on it's type
     match expr {
       parser::Expression::Literal (literal) => {
@@ -158,152 +469,352 @@ impl Compiler {
           ),
           parser::Literal::RawString(inner_string) => codegen::Argument::RawString(inner_string.to_owned()),
         };
-        codegen::Expression {
+        Ok(codegen::Expression {
           dependencies: Vec::new(),
           content: Some(content),
-        }
+        })
       },
-      parser::Expression::Identifier (name) => {
-        // TODO: Lookup identifier in symbol table
-        codegen::Expression { 
+      parser::Expression::Identifier (iden) => {
+        let content = match self.resolution.symbol(iden).symbol_type {
+          SymbolType::Variable | SymbolType::Global => codegen::Argument::Identifier(self.variable(iden)?),
+          SymbolType::Constant => {
+            let value = self.state.constants.get(&self.resolution.symbol(iden).id)
+              .ok_or_else(|| anyhow::anyhow!("Constant \"{}\" is used before its declaration", iden.name))?;
+            codegen::Argument::Literal(value.clone())
+          },
+          SymbolType::UIObject => {
+            let object = self.site.lookup(&iden.name)?.expect("Resolved UI objects should be in the linked sites");
+            codegen::Argument::Object(object.globalid.clone())
+          },
+          symbol_type => return Err(anyhow::anyhow!("The {} \"{}\" cannot be used as a value", symbol_type, iden.name)),
+        };
+        Ok(codegen::Expression { 
           dependencies: Vec::new(),
-          content: Some(codegen::Argument::Identifier(codegen::Variable { name: name.clone() })),
-        }
-      },
-      parser::Expression::CWScriptBlockID (block_id) => {
-        unimplemented!("CWScriptBlockID cannot be read as expressions or values.");
-        // codegen::Expression {
-        //   dependencies: Vec::new(),
-        //   content: Some(codegen::CWScriptBlockID{ id: block_id.clone() }),
-        // }
+          content: Some(content),
+        })
       },
+      parser::Expression::CWScriptBlockID (_) => Err(anyhow::anyhow!("CWScriptBlockID cannot be read as expressions or values.")),
       parser::Expression::Call { function, arguments } => self.compile_call(function, arguments),
+      parser::Expression::Member { object, property } if self.is_table_member(object, property)? => self.compile_table_member(object, property),
+      parser::Expression::Index { object, index } => self.compile_table_index(object, index),
+      parser::Expression::Array(_) | parser::Expression::Table(_) => {
+        let result = self.state.new_temporary();
+        Ok(codegen::Expression {
+          dependencies: self.compile_collection(expr, result.clone())?,
+          content: Some(codegen::Argument::Identifier(result)),
+        })
+      },
+      // Property reads, e.g. `obj.visible`
+      parser::Expression::Member { object, property } => {
+        let (object_compiled, property) = self.compile_property_access(object, property)?;
+        let result = self.state.new_temporary();
+        Ok(codegen::Expression {
+          dependencies: object_compiled.dependencies.into_iter().chain([
+            codegen::Call::CWScriptBlockCall {
+              dependencies: Vec::new(),
+              block_id: codegen::CWScriptBlockID { id: blocks::GET_PROPERTY.to_string() },
+              arguments: vec![
+                codegen::Argument::Literal(codegen::Literal { value: property }),
+                object_compiled.content.expect("Compiled object should have a value"),
+              ],
+              return_var: Some(result.clone()),
+            },
+          ]).collect(),
+          content: Some(codegen::Argument::Identifier(result)),
+        })
+      },
       parser::Expression::BinOperation { lhs, op, rhs } => {
         // TODO: Operator overloading?
-        let lhs_compiled = self.compile_expression(lhs);
-        let rhs_compiled = self.compile_expression(rhs);
+        let lhs_compiled = self.compile_expression(lhs)?;
+        let rhs_compiled = self.compile_expression(rhs)?;
+
+        let lhs_as_arg = lhs_compiled.content.ok_or_else(|| anyhow::anyhow!("Binary operation expected LHS argument"))?;
+        let rhs_as_arg = rhs_compiled.content.ok_or_else(|| anyhow::anyhow!("Binary operation expected RHS argument"))?;
 
-        let lhs_as_arg = lhs_compiled.content.expect("Binary operation expected LHS argument"); 
-        let rhs_as_arg = rhs_compiled.content.expect("Binary operation expected RHS argument");
-        // Generate the call
-        let call = codegen::Call::FunctionCall {
+        // CatWeb math blocks modify a variable in place, so the LHS is copied into a temporary first
+        let result = self.state.new_temporary();
+        let operation = codegen::Call::CWScriptBlockCall {
           dependencies: vec![
             lhs_compiled.dependencies,
             rhs_compiled.dependencies,
+            vec![Compiler::generate_set_variable(result.clone(), lhs_as_arg)],
           ].into_iter().flatten().collect(),
-          function_name: codegen::Variable { name: Compiler::map_bin_op(op) },
+          block_id: codegen::CWScriptBlockID { id: Compiler::map_bin_op(op) },
           arguments: vec![
-            // FIXME: Wrong implementation: Calculation of expressions should be standalone dependencies as well
-            lhs_as_arg,
+            codegen::Argument::VariableName(result.clone()),
             rhs_as_arg,
           ],
-          return_var: None, // FIXME: Match return variable
+          return_var: None,
         };
 
-        // Wrap it in an expression
-        codegen::Expression {
-          dependencies: vec![call],
-          // FIXME: Binary operations compilation unimplemented, calculated contents are not passed as variable
-          content: None, // FIXME: Match return variable
-        }
+        Ok(codegen::Expression {
+          dependencies: vec![operation],
+          content: Some(codegen::Argument::Identifier(result)),
+        })
       },
       
-      parser::Expression::UnaryOperation { op, expr } => todo!()
+      parser::Expression::UnaryOperation { op, expr } => self.compile_unary_operation(op, expr),
+      parser::Expression::Template(parts) => self.compile_template(parts),
+    }
+  }
+
+  /// Compiles a template literal into text interpolated by CatWeb. Embedded expressions which aren't plain variables
+  /// or literals are evaluated into temporaries first.
+  fn compile_template(&mut self, parts: &[parser::TemplatePart]) -> Result<codegen::Expression, anyhow::Error> {
+    let mut dependencies: Vec<codegen::Call> = Vec::new();
+    let mut compiled_parts: Vec<codegen::TemplatePart> = Vec::new();
+
+    for part in parts {
+      let compiled_part = match part {
+        parser::TemplatePart::Text(text) => vec![codegen::TemplatePart::Text(text.clone())],
+        parser::TemplatePart::Expression(expr) => {
+          let expr_compiled = self.compile_expression(expr)?;
+          dependencies.extend(expr_compiled.dependencies);
+          match expr_compiled.content {
+            Some(codegen::Argument::Identifier(var)) => vec![codegen::TemplatePart::Variable(var)],
+            Some(codegen::Argument::Literal(lit)) => vec![codegen::TemplatePart::Text(lit.value)],
+            Some(codegen::Argument::RawString(text)) => vec![codegen::TemplatePart::Text(text)],
+            Some(codegen::Argument::Template(inner_parts)) => inner_parts,
+            Some(codegen::Argument::VariableName(var)) => unreachable!("Expression evaluated to variable name {:?}", var),
+            Some(codegen::Argument::Object(_)) => return Err(anyhow::anyhow!("Objects cannot be embedded in template literals: {:?}", expr)),
+            None => return Err(anyhow::anyhow!("Expression embedded in template literal has no value: {:?}", expr)),
+          }
+        },
+      };
+      compiled_parts.extend(compiled_part);
+    }
+
+    let content = match compiled_parts.as_slice() {
+      [codegen::TemplatePart::Variable(var)] => codegen::Argument::Identifier(var.clone()),
+      _ => codegen::Argument::Template(compiled_parts),
+    };
+    Ok(codegen::Expression { dependencies, content: Some(content) })
+  }
+
+  fn compile_unary_operation(&mut self, op: &UnaryOperator, expr: &parser::Expression) -> Result<codegen::Expression, anyhow::Error> {
+    // Collapse repeated operators, e.g. `--x` and `!!!x`, so they don't produce redundant blocks
+    let mut operand = expr;
+    let mut count = 1;
+    while let parser::Expression::UnaryOperation { op: inner_op, expr: inner_expr } = operand {
+      if inner_op != op {
+        break;
+      }
+      operand = inner_expr;
+      count += 1;
+    }
+
+    match op {
+      UnaryOperator::NumeralNegation => {
+        if count % 2 == 0 {
+          return self.compile_expression(operand);
+        }
+        // Negative literals are folded
+        if let parser::Expression::Literal(parser::Literal::Integer(value) | parser::Literal::Float(value)) = operand {
+          return Ok(codegen::Expression {
+            dependencies: Vec::new(),
+            content: Some(codegen::Argument::Literal(codegen::Literal { value: Compiler::negate_numeral(value) })),
+          });
+        }
+
+        let operand_compiled = self.compile_expression(operand)?;
+        let operand_as_arg = operand_compiled.content.ok_or_else(|| anyhow::anyhow!("Negation expected an operand"))?;
+        let result = self.state.new_temporary();
+        Ok(codegen::Expression {
+          dependencies: operand_compiled.dependencies.into_iter().chain([
+            Compiler::generate_set_variable(result.clone(), codegen::Argument::Literal(codegen::Literal { value: "0".to_string() })),
+            Compiler::generate_block_call(blocks::SUBTRACT_VARIABLE, vec![codegen::Argument::VariableName(result.clone()), operand_as_arg]),
+          ]).collect(),
+          content: Some(codegen::Argument::Identifier(result)),
+        })
+      },
+      UnaryOperator::LogicalNegation => {
+        let negate = count % 2 == 1;
+        if let parser::Expression::Literal(parser::Literal::Bool(value)) = operand {
+          return Ok(codegen::Expression {
+            dependencies: Vec::new(),
+            content: Some(codegen::Argument::Literal(codegen::Literal { value: (*value != negate).to_string() })),
+          });
+        }
+
+        // Even counts convert the operand to a boolean. Both cases assume the result, and flip it if the operand is true:
+        // set result to (negate); if {operand} is equal to true; set result to (!negate); end
+        let operand_compiled = self.compile_expression(operand)?;
+        let operand_as_arg = operand_compiled.content.ok_or_else(|| anyhow::anyhow!("Logical negation expected an operand"))?;
+        let result = self.state.new_temporary();
+        Ok(codegen::Expression {
+          dependencies: operand_compiled.dependencies.into_iter().chain([
+            Compiler::generate_set_variable(result.clone(), codegen::Argument::Literal(codegen::Literal { value: negate.to_string() })),
+            Compiler::generate_block_call(blocks::IF_EQUAL, vec![operand_as_arg, codegen::Argument::Literal(codegen::Literal { value: "true".to_string() })]),
+            Compiler::generate_set_variable(result.clone(), codegen::Argument::Literal(codegen::Literal { value: (!negate).to_string() })),
+            Compiler::generate_block_call(blocks::END, vec![]),
+          ]).collect(),
+          content: Some(codegen::Argument::Identifier(result)),
+        })
+      },
     }
   }
 
-  pub fn compile_call(&mut self, function: &parser::Expression, arguments: &Vec<parser::Expression>) -> codegen::Expression {
+  pub fn compile_call(&mut self, function: &parser::Expression, arguments: &[parser::Expression]) -> Result<codegen::Expression, anyhow::Error> {
     // Compile arguments of the call first
     let (dependencies, arguments): (Vec<Vec<codegen::Call>>, Vec<codegen::Argument>) = arguments.iter()
       .map(|arg| {
-        let dep_expr = self.compile_expression(arg);
-        let dep_var = dep_expr.content.expect("Expected argument value");
-        (
+        let dep_expr = self.compile_expression(arg)?;
+        let dep_var = dep_expr.content.ok_or_else(|| anyhow::anyhow!("Expected argument value"))?;
+        Ok((
           dep_expr.dependencies, 
           dep_var
-        )
+        ))
       }
-    ).unzip();
+    ).collect::<Result<Vec<(Vec<codegen::Call>, codegen::Argument)>, anyhow::Error>>()?
+      .into_iter()
+      .unzip();
     
+    // Calls of declared functions use their CatWeb name, e.g. `console.log(x)` calls the stdlib `log`
+    if let Some(signature) = types::callee_path(function, &self.resolution).and_then(|path| self.functions.get(&path)) {
+      return Ok(codegen::Expression {
+        dependencies: vec![
+          codegen::Call::FunctionCall {
+            dependencies: dependencies.into_iter().flatten().collect(),
+            function_name: codegen::Variable { name: signature.name.clone() },
+            arguments,
+            // FIXME: Return variable handling
+            return_var: None,
+          }
+        ],
+        content: None,
+      });
+    }
+
     // Match cases based on type of the function call
-    match &*function {
+    match function {
       // Normal function calls
     // FIXME: Handle function inlining
       parser::Expression::Identifier(iden) => {
-        codegen::Expression {
+        Ok(codegen::Expression {
           dependencies: vec![
             codegen::Call::FunctionCall {
               dependencies: dependencies.into_iter().flatten().collect(),
-              function_name: codegen::Variable { name: iden.clone() },
-              arguments: arguments,
+              function_name: codegen::Variable { name: iden.name.clone() },
+              arguments,
               // FIXME: Return variable handling
               return_var: None,
             }
           ],
           // FIXME: Return values from function calls is not implemented yet
           content: None
-        }
+        })
       },
 
       // Function call by block id (raw calls)
-      // FIXME: Argument compilation not implemented yet
       parser::Expression::CWScriptBlockID(action_id) => {
-        codegen::Expression {
+        Ok(codegen::Expression {
           dependencies: vec![
             codegen::Call::CWScriptBlockCall {
               dependencies: dependencies.into_iter().flatten().collect(),
               block_id: codegen::CWScriptBlockID { id: action_id.to_owned() },
-              arguments: arguments,
+              arguments,
               return_var: None,
             }
           ], 
           // FIXME: Return values from raw calls not implemented yet
           content: None
+        })
+      },
+      // Table methods, e.g. `t.push(x)`
+      parser::Expression::Member { object, property } if self.is_table_member(object, property)? => {
+        self.compile_table_method(object, property, dependencies.into_iter().flatten().collect(), arguments)
+      },
+      parser::Expression::Call { .. } => Err(anyhow::anyhow!("Function as return value and chained calls are not supported yet.")),
+      others => Err(anyhow::anyhow!("Unsupported call target in call: {:?}", others)),
+    }
+  }
+
+  /// Compiles the object of a property access, and checks the property against the properties of its class
+  fn compile_property_access(&mut self, object: &parser::Expression, property: &str) -> Result<(codegen::Expression, String), anyhow::Error> {
+    // The class of objects passed around in variables is not always known at compile time
+    let class = match self.value_kind(object)? {
+      Some(ValueKind::Object(class)) => Some(class),
+      _ => None,
+    };
+    let known_properties = class.as_deref().map_or_else(site::all_known_properties, site::known_properties);
+    if !known_properties.contains(&property) {
+      return Err(anyhow::anyhow!(
+        "Unknown property \"{}\" on {} object, expected one of: {}",
+        property, class.as_deref().unwrap_or("an"), known_properties.join(", ")
+      ));
+    }
+
+    let object_compiled = self.compile_expression(object)?;
+    match object_compiled.content {
+      Some(codegen::Argument::Object(_) | codegen::Argument::Identifier(_)) => Ok((object_compiled, property.to_string())),
+      _ => Err(anyhow::anyhow!("Expected an object before \".{}\", found {:?}", property, object)),
+    }
+  }
+
+  /// Returns the kind of value an expression evaluates to, if known at compile time
+  fn value_kind(&self, expr: &parser::Expression) -> Result<Option<ValueKind>, anyhow::Error> {
+    match expr {
+      parser::Expression::Identifier(iden) => {
+        let symbol = self.resolution.symbol(iden);
+        match symbol.symbol_type {
+          SymbolType::Variable | SymbolType::Global => Ok(self.state.value_kinds.get(&symbol.id).cloned()),
+          SymbolType::UIObject => Ok(self.site.lookup(&iden.name)?.map(|object| ValueKind::Object(object.class.clone()))),
+          _ => Ok(None),
         }
       },
-      parser::Expression::Call { .. } => unimplemented!("Function as return value and chained calls are not supported yet."),
-      others => unimplemented!("Unsupported call target in call: {:?}", others),
+      parser::Expression::Array(_) | parser::Expression::Table(_) => Ok(Some(ValueKind::Table)),
+      _ => Ok(None),
     }
   }
 
-  /// Returns call signature string for given binary operator
+  /// Returns the CatWeb block ID of the in-place math operation for given binary operator
   pub fn map_bin_op(op: &BinOperator) -> String {
     match op {
-      &BinOperator::Addition => "add".to_string(),
-      &BinOperator::Subtraction => "sub".to_string(),
-      &BinOperator::Multiplication => "mul".to_string(),
-      &BinOperator::Division => "div".to_string(),
-      &BinOperator::Power => "pow".to_string(),
-      // &BinOperator::Dot => panic!("Dot cannot be mapped to call signature"),
-      // &BinOperator::Comma => panic!("Comma cannot be mapped to call signature"),
-      op_rest => panic!("Binary operator {:?} cannot be mapped to call signature", op_rest),
+      BinOperator::Addition => blocks::INCREASE_VARIABLE.to_string(),
+      BinOperator::Subtraction => blocks::SUBTRACT_VARIABLE.to_string(),
+      BinOperator::Multiplication => blocks::MULTIPLY_VARIABLE.to_string(),
+      BinOperator::Division => blocks::DIVIDE_VARIABLE.to_string(),
+      BinOperator::Power => blocks::POWER_VARIABLE.to_string(),
     }
   }
-  
-  /// Moves all the function declarations to the top of the main block
-  pub fn hoist_items(&mut self) {
-    let mut hoisted_items: Vec<parser::Item> = Vec::new();
-    let mut other_items: Vec<parser::Item> = Vec::new();
 
-    for item in self.syntax_tree.main_block.drain(..) {
-      match item {
-        parser::Item::FunctionDeclaration { .. } => hoisted_items.push(item),
-        _ => other_items.push(item),
-      }
+  /// Returns the negation of a numeral literal, without a redundant sign on double negatives
+  fn negate_numeral(value: &str) -> String {
+    match value.strip_prefix('-') {
+      Some(positive) => positive.to_string(),
+      None => format!("-{}", value),
     }
+  }
 
-    // Reconstruct the main block with hoisted items first
-    self.syntax_tree.main_block = hoisted_items;
-    self.syntax_tree.main_block.extend(other_items);
+  /// Returns the CatWeb variable of a resolved variable identifier
+  fn variable(&self, iden: &parser::Identifier) -> Result<codegen::Variable, anyhow::Error> {
+    Compiler::check_identifier(&iden.name)?;
+    Ok(codegen::Variable { name: self.resolution.variable_name(iden) })
+  }
+
+  /// Rejects identifiers which could collide with compiler-generated variables
+  fn check_identifier(name: &str) -> Result<String, anyhow::Error> {
+    if name.starts_with(RESERVED_PREFIX) {
+      return Err(anyhow::anyhow!("Identifier \"{}\" is reserved: names starting with \"{}\" are used by the compiler", name, RESERVED_PREFIX));
+    }
+    Ok(name.to_string())
   }
-  
-  /// Generate CatWeb calls
-  pub fn generate_catweb_sync_call(name: codegen::Variable, argument: Vec<codegen::Argument>) -> codegen::Call {
-    codegen::Call::CWScriptBlockCall { 
+
+  /// Generates the CatWeb call that assigns `value` to `target`
+  pub fn generate_set_variable(target: codegen::Variable, value: codegen::Argument) -> codegen::Call {
+    Compiler::generate_block_call(blocks::SET_VARIABLE, vec![
+      codegen::Argument::VariableName(target),
+      value,
+    ])
+  }
+
+  /// Generates a CatWeb call without dependencies or return variable
+  pub fn generate_block_call(block_id: &str, arguments: Vec<codegen::Argument>) -> codegen::Call {
+    codegen::Call::CWScriptBlockCall {
       dependencies: Vec::new(),
-      block_id: codegen::CWScriptBlockID { id: "".to_string() }, // FIXME: Change ActionBlockID to the correct block ID
-      arguments: argument,
-      return_var: None, // FIXME: Handle return variable for sync calls
+      block_id: codegen::CWScriptBlockID { id: block_id.to_string() },
+      arguments,
+      return_var: None,
     }
   }
-}
\ No newline at end of file
+}
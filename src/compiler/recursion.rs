@@ -0,0 +1,233 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use crate::codegen::{self, blocks};
+
+use super::Compiler;
+use super::temporaries::{loop_ranges, visit_calls};
+
+/// Table which recursive functions save their live variables on, created when the website loads
+pub const CALL_STACK: &str = "__stack";
+
+/// Support for recursive functions, whose parameters and locals are CatWeb variables shared by every call.
+///
+/// Functions in a cycle of the call graph save their live variables on the call stack before each call into the
+/// cycle, and restore them afterwards. Functions which aren't recursive are left untouched.
+impl Compiler {
+  /// Saves the live variables of recursive functions around their recursive calls. Returns whether any function
+  /// is recursive, in which case the call stack has to be created.
+  ///
+  /// Runs before temporary allocation, so temporaries which are live across a call are saved as well.
+  pub(super) fn save_recursive_frames(&mut self, items: &mut [codegen::Item]) -> bool {
+    let call_graph = items.iter().filter_map(|item| match item {
+      codegen::Item::FunctionDeclaration { name, body, .. } => Some((name.clone(), called_functions(body))),
+      codegen::Item::Event { .. } => None,
+    }).collect::<HashMap<String, BTreeSet<String>>>();
+
+    let mut is_recursive = false;
+    for cycle in recursive_cycles(&call_graph) {
+      for item in items.iter_mut() {
+        let codegen::Item::FunctionDeclaration { name, parameters, body, .. } = item else {
+          continue;
+        };
+        if !cycle.contains(name) {
+          continue;
+        }
+        let locals = self.resolution.mangled_names.values()
+          .filter(|mangled| &mangled.function == name)
+          .map(|mangled| mangled.mangled.clone())
+          .collect::<HashSet<String>>();
+        let parameters = parameters.iter().map(|param| param.name.clone()).collect::<HashSet<String>>();
+
+        let added_blocks = self.save_frame(body, &cycle, &locals, &parameters);
+        if added_blocks > 0 {
+          is_recursive = true;
+          self.warnings.push(format!(
+            "In function \"{}\": recursive calls save and restore the live variables on the call stack, adding {} blocks",
+            name, added_blocks
+          ));
+        }
+      }
+    }
+    is_recursive
+  }
+
+  /// Saves the live variables of a function around its calls into `cycle`, returning the number of blocks added
+  fn save_frame(&mut self, body: &mut [codegen::Statement], cycle: &BTreeSet<String>, locals: &HashSet<String>, parameters: &HashSet<String>) -> usize {
+    let mut occurrences: HashMap<String, Vec<usize>> = HashMap::new();
+    let mut recursive_calls: Vec<usize> = Vec::new();
+    visit_calls(body, &mut |call, position| {
+      if let codegen::Call::FunctionCall { function_name, .. } = call
+        && cycle.contains(&function_name.name)
+      {
+        recursive_calls.push(position);
+      }
+      for var in call.variables_mut() {
+        if locals.contains(&var.name) || var.is_virtual_temporary() {
+          occurrences.entry(var.name.clone()).or_default().push(position);
+        }
+      }
+    });
+    let loops = loop_ranges(body);
+
+    // A variable is live across a call if it may be read after it, and it may hold a value from before it.
+    // Variables used in a loop around the call are both, since the loop may run again.
+    let mut frames: HashMap<usize, Vec<codegen::Variable>> = HashMap::new();
+    for call in recursive_calls {
+      let enclosing_loops = loops.iter().filter(|(start, end)| *start < call && call < *end).collect::<Vec<_>>();
+      let mut live = occurrences.iter().filter(|(name, positions)| {
+        let in_loop = positions.iter().any(|position| enclosing_loops.iter().any(|(start, end)| start <= position && position <= end));
+        let before = parameters.contains(*name) || positions.iter().any(|position| *position <= call);
+        let after = positions.iter().any(|position| *position > call);
+        in_loop || (before && after)
+      }).map(|(name, _)| codegen::Variable { name: name.clone() }).collect::<Vec<codegen::Variable>>();
+      live.sort_by(|lhs, rhs| lhs.name.cmp(&rhs.name));
+      frames.insert(call, live);
+    }
+
+    let mut added_blocks = 0;
+    let mut position = 0;
+    for statement in body.iter_mut() {
+      statement.dependencies = self.wrap_calls(std::mem::take(&mut statement.dependencies), &frames, &mut position, &mut added_blocks);
+      statement.content = self.wrap_calls(std::mem::take(&mut statement.content), &frames, &mut position, &mut added_blocks);
+    }
+    added_blocks
+  }
+
+  /// Surrounds the calls at the positions of `frames` with the saving and restoring of their live variables
+  fn wrap_calls(&mut self, calls: Vec<codegen::Call>, frames: &HashMap<usize, Vec<codegen::Variable>>, position: &mut usize, added_blocks: &mut usize) -> Vec<codegen::Call> {
+    let mut wrapped = Vec::new();
+    for mut call in calls {
+      let dependencies = std::mem::take(call.dependencies_mut());
+      *call.dependencies_mut() = self.wrap_calls(dependencies, frames, position, added_blocks);
+
+      match frames.get(position).filter(|frame| !frame.is_empty()) {
+        Some(frame) => {
+          let (save, restore) = self.frame_calls(frame);
+          *added_blocks += save.len() + restore.len();
+          wrapped.extend(save);
+          wrapped.push(call);
+          wrapped.extend(restore);
+        },
+        None => wrapped.push(call),
+      }
+      *position += 1;
+    }
+    wrapped
+  }
+
+  /// Generates the calls which push the variables on the call stack, and the calls which pop them in reverse order
+  fn frame_calls(&mut self, frame: &[codegen::Variable]) -> (Vec<codegen::Call>, Vec<codegen::Call>) {
+    let stack = codegen::Argument::VariableName(codegen::Variable { name: CALL_STACK.to_string() });
+    let save = frame.iter()
+      .map(|var| Compiler::generate_block_call(blocks::INSERT_ENTRY, vec![stack.clone(), codegen::Argument::Identifier(var.clone())]))
+      .collect();
+
+    let top = self.state.new_temporary();
+    let restore = frame.iter().rev().flat_map(|var| [
+      codegen::Call::CWScriptBlockCall {
+        dependencies: Vec::new(),
+        block_id: codegen::CWScriptBlockID { id: blocks::TABLE_LENGTH.to_string() },
+        arguments: vec![stack.clone()],
+        return_var: Some(top.clone()),
+      },
+      codegen::Call::CWScriptBlockCall {
+        dependencies: Vec::new(),
+        block_id: codegen::CWScriptBlockID { id: blocks::GET_ENTRY.to_string() },
+        arguments: vec![stack.clone(), codegen::Argument::Identifier(top.clone())],
+        return_var: Some(var.clone()),
+      },
+      Compiler::generate_block_call(blocks::REMOVE_ENTRY, vec![stack.clone(), codegen::Argument::Identifier(top.clone())]),
+    ]).collect();
+    (save, restore)
+  }
+
+  /// Generates the call which creates the call stack
+  pub(super) fn generate_call_stack() -> codegen::Statement {
+    codegen::Statement::new(Vec::new(), vec![codegen::Call::CWScriptBlockCall {
+      dependencies: Vec::new(),
+      block_id: codegen::CWScriptBlockID { id: blocks::CREATE_TABLE.to_string() },
+      arguments: vec![],
+      return_var: Some(codegen::Variable { name: CALL_STACK.to_string() }),
+    }])
+  }
+}
+
+/// Returns the names of the functions called in a body
+fn called_functions(body: &[codegen::Statement]) -> BTreeSet<String> {
+  body.iter()
+    .flat_map(|statement| statement.clone().into_calls())
+    .filter_map(|call| match call {
+      codegen::Call::FunctionCall { function_name, .. } => Some(function_name.name),
+      codegen::Call::CWScriptBlockCall { .. } => None,
+    })
+    .collect()
+}
+
+/// Returns the strongly connected components of the call graph which contain a cycle, found with Tarjan's algorithm
+fn recursive_cycles(call_graph: &HashMap<String, BTreeSet<String>>) -> Vec<BTreeSet<String>> {
+  struct Tarjan<'a> {
+    call_graph: &'a HashMap<String, BTreeSet<String>>,
+    indices: HashMap<&'a str, usize>,
+    low_links: HashMap<&'a str, usize>,
+    stack: Vec<&'a str>,
+    on_stack: HashSet<&'a str>,
+    cycles: Vec<BTreeSet<String>>,
+  }
+
+  impl<'a> Tarjan<'a> {
+    fn visit(&mut self, function: &'a str) {
+      let index = self.indices.len();
+      self.indices.insert(function, index);
+      self.low_links.insert(function, index);
+      self.stack.push(function);
+      self.on_stack.insert(function);
+
+      // Calls of functions which aren't compiled, e.g. unknown ones, can't be part of a cycle
+      for callee in self.call_graph.get(function).into_iter().flatten() {
+        let Some((callee, _)) = self.call_graph.get_key_value(callee) else {
+          continue;
+        };
+        if !self.indices.contains_key(callee.as_str()) {
+          self.visit(callee);
+          let low_link = self.low_links[function].min(self.low_links[callee.as_str()]);
+          self.low_links.insert(function, low_link);
+        } else if self.on_stack.contains(callee.as_str()) {
+          let low_link = self.low_links[function].min(self.indices[callee.as_str()]);
+          self.low_links.insert(function, low_link);
+        }
+      }
+
+      if self.low_links[function] == self.indices[function] {
+        let mut component = BTreeSet::new();
+        while let Some(member) = self.stack.pop() {
+          self.on_stack.remove(member);
+          component.insert(member.to_string());
+          if member == function {
+            break;
+          }
+        }
+        let calls_itself = self.call_graph[function].contains(function);
+        if component.len() > 1 || calls_itself {
+          self.cycles.push(component);
+        }
+      }
+    }
+  }
+
+  let mut tarjan = Tarjan {
+    call_graph,
+    indices: HashMap::new(),
+    low_links: HashMap::new(),
+    stack: Vec::new(),
+    on_stack: HashSet::new(),
+    cycles: Vec::new(),
+  };
+  let mut functions = call_graph.keys().collect::<Vec<&String>>();
+  functions.sort();
+  for function in functions {
+    if !tarjan.indices.contains_key(function.as_str()) {
+      tarjan.visit(function);
+    }
+  }
+  tarjan.cycles
+}
@@ -0,0 +1,322 @@
+use std::collections::HashMap;
+
+use crate::parser;
+use crate::codegen::{blocks, site};
+use crate::codegen::symbol_table::{self, SymbolId, SymbolRecord, SymbolTable, SymbolType};
+
+use super::types::FunctionSignature;
+
+/// Separates the function name from the variable name in the CatWeb names of locals, e.g. `greet$user`.
+///
+/// `$` cannot appear in identifiers, so mangled names never collide with globals.
+pub const MANGLING_SEPARATOR: &str = "$";
+
+/// Symbols of a compilation
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Resolution {
+  /// Every symbol, indexed by its `SymbolId`
+  pub symbols: Vec<SymbolRecord>,
+  /// CatWeb names of the parameters and locals of functions
+  pub mangled_names: HashMap<SymbolId, MangledName>,
+  /// Declarations which shadow a symbol of an outer scope, with the type of the shadowed symbol
+  pub shadowed: HashMap<SymbolId, SymbolType>,
+}
+
+/// Function-unique CatWeb name of a local variable
+#[derive(Debug, PartialEq, Clone)]
+pub struct MangledName {
+  /// CatWeb name of the function declaring the variable
+  pub function: String,
+  pub name: String,
+  pub mangled: String,
+}
+
+impl Resolution {
+  /// Returns the symbol a resolved identifier refers to
+  pub fn symbol(&self, iden: &parser::Identifier) -> &SymbolRecord {
+    &self.symbols[iden.symbol.unwrap_or_else(|| panic!("Identifier \"{}\" should be resolved", iden.name))]
+  }
+
+  /// Returns the CatWeb name of a resolved variable. Locals are mangled, while globals keep their name.
+  pub fn variable_name(&self, iden: &parser::Identifier) -> String {
+    self.mangled_names.get(&self.symbol(iden).id).map_or_else(|| iden.name.clone(), |mangled| mangled.mangled.clone())
+  }
+
+  /// Mangled names of all locals, in declaration order
+  pub fn name_mapping(&self) -> Vec<&MangledName> {
+    let mut ids = self.mangled_names.keys().collect::<Vec<&SymbolId>>();
+    ids.sort();
+    ids.into_iter().map(|id| &self.mangled_names[id]).collect()
+  }
+}
+
+/// Name resolution, which assigns every identifier of the syntax tree the symbol it refers to.
+///
+/// Scopes are nested as: linked UI objects, module (functions and globals), function (parameters), blocks.
+/// Statements outside of functions have a scope of their own, nested in the module scope.
+/// Declarations can shadow the symbols of outer scopes, but can't be repeated within a scope.
+///
+/// CatWeb variables are shared by the whole script, so locals get function-unique names, e.g. `f$x`. Locals
+/// redeclared in the same function get a numbered name, e.g. `f$x$2`.
+pub struct Resolver<'a> {
+  functions: &'a HashMap<String, FunctionSignature>,
+  table: SymbolTable,
+  resolution: Resolution,
+  function_name: String,
+  /// CatWeb name of the current function, which prefixes the names of its locals
+  catweb_function_name: String,
+  /// Number of locals declared with each name in the current function
+  declaration_counts: HashMap<String, usize>,
+  errors: Vec<String>,
+}
+
+impl<'a> Resolver<'a> {
+  /// Creates a resolver with the UI objects and functions of the program in scope
+  pub fn new(site: &site::SiteData, functions: &'a HashMap<String, FunctionSignature>) -> Self {
+    let mut resolver = Self {
+      functions,
+      table: SymbolTable::default(),
+      resolution: Resolution::default(),
+      function_name: String::new(),
+      catweb_function_name: String::new(),
+      declaration_counts: HashMap::new(),
+      errors: Vec::new(),
+    };
+
+    let mut aliases = site.aliases().collect::<Vec<&String>>();
+    aliases.sort();
+    for alias in aliases {
+      resolver.add_symbol(alias, SymbolType::UIObject);
+    }
+
+    resolver.table.push_scope();
+    let mut names = functions.keys().collect::<Vec<&String>>();
+    names.sort();
+    for name in names {
+      match name.split_once('.') {
+        Some((namespace, _)) if resolver.table.lookup_local(namespace).is_none() => {
+          resolver.add_symbol(namespace, SymbolType::Namespace);
+        },
+        Some(_) => {},
+        None => {
+          resolver.add_symbol(name, SymbolType::Function);
+        },
+      }
+    }
+    resolver
+  }
+
+  pub fn resolve_items(&mut self, items: &mut [parser::Item]) {
+    // Constants and globals can be used by functions declared before them
+    self.function_name.clear();
+    for item in items.iter_mut() {
+      match item {
+        parser::Item::ConstDeclaration(constant) => {
+          self.resolve_expression(&mut constant.value);
+          self.declare(&mut constant.name, SymbolType::Constant);
+        },
+        parser::Item::GlobalDeclaration(global) => {
+          if let Some(value) = &mut global.value {
+            self.resolve_expression(value);
+          }
+          self.declare(&mut global.name, SymbolType::Global);
+        },
+        _ => {},
+      }
+    }
+
+    // Statements outside of functions run in the load event, so their locals belong to it
+    self.catweb_function_name = blocks::WEBSITE_LOADED.to_string();
+    self.declaration_counts.clear();
+    self.table.push_scope();
+    for item in items.iter_mut() {
+      if let parser::Item::Statement(statement) = item {
+        self.resolve_statement(statement);
+      }
+    }
+    self.table.pop_scope();
+
+    for item in items {
+      if let parser::Item::FunctionDeclaration(func) = item {
+        self.resolve_function(func);
+      }
+    }
+  }
+
+  /// Returns the resolved symbols, or all resolution errors
+  pub fn finish(self) -> Result<Resolution, anyhow::Error> {
+    match self.errors.is_empty() {
+      true => Ok(self.resolution),
+      false => Err(anyhow::anyhow!("{}", self.errors.join("\n"))),
+    }
+  }
+
+  fn resolve_function(&mut self, func: &mut parser::FunctionDeclaration) {
+    self.function_name = func.name.clone();
+    self.catweb_function_name = self.functions.get(&func.name).map_or_else(|| func.name.clone(), |signature| signature.name.clone());
+    self.declaration_counts.clear();
+    self.table.push_scope();
+    for param in &mut func.parameters {
+      self.declare(&mut param.name, SymbolType::Variable);
+    }
+    for statement in &mut func.body {
+      self.resolve_statement(statement);
+    }
+    self.table.pop_scope();
+  }
+
+  fn resolve_statement(&mut self, statement: &mut parser::Statement) {
+    match statement {
+      parser::Statement::Expression { expr, .. } => self.resolve_expression(expr),
+      parser::Statement::Let { name, value, .. } => {
+        // The value can't refer to the variable it initializes, e.g. `let x = x + 1;` reads an outer `x`
+        self.resolve_expression(value);
+        self.declare(name, SymbolType::Variable);
+      },
+      parser::Statement::Assignment { lhs, rhs, .. } => {
+        self.resolve_expression(rhs);
+        self.resolve_expression(lhs);
+        if let parser::Expression::Identifier(iden) = lhs
+          && let Some(id) = iden.symbol
+          && !matches!(self.resolution.symbols[id].symbol_type, SymbolType::Variable | SymbolType::Global)
+        {
+          let record = &self.resolution.symbols[id];
+          let message = format!("Cannot assign to the {} \"{}\", only to variables", record.symbol_type, record.name);
+          self.error(iden.position, message);
+        }
+      },
+      parser::Statement::For { variable, iterable, body, .. } => {
+        self.resolve_expression(iterable);
+        self.table.push_scope();
+        self.declare(variable, SymbolType::Variable);
+        for statement in body {
+          self.resolve_statement(statement);
+        }
+        self.table.pop_scope();
+      },
+      parser::Statement::Link { .. } => {},
+    }
+  }
+
+  fn resolve_expression(&mut self, expr: &mut parser::Expression) {
+    match expr {
+      parser::Expression::Literal(_) | parser::Expression::CWScriptBlockID(_) => {},
+      parser::Expression::Identifier(iden) => self.resolve_identifier(iden),
+      parser::Expression::BinOperation { lhs, rhs, .. } => {
+        self.resolve_expression(lhs);
+        self.resolve_expression(rhs);
+      },
+      parser::Expression::UnaryOperation { expr, .. } => self.resolve_expression(expr),
+      parser::Expression::Call { function, arguments } => {
+        self.resolve_expression(function);
+        arguments.iter_mut().for_each(|arg| self.resolve_expression(arg));
+      },
+      parser::Expression::Member { object, .. } => {
+        self.resolve_expression(object);
+        // Members of namespaces must name functions, e.g. `console.log`
+        if let Some((path, position)) = self.namespace_path(expr) {
+          let prefix = format!("{}.", path);
+          if !self.functions.contains_key(&path) && !self.functions.keys().any(|name| name.starts_with(&prefix)) {
+            let suggestion = self.functions.keys()
+              .filter(|name| name.contains('.'))
+              .map(|name| (symbol_table::edit_distance(&path, name), name))
+              .filter(|(distance, _)| *distance <= symbol_table::max_typo_distance(&path))
+              .min();
+            let message = format!("Undefined function \"{}\"{}", path, Resolver::suggestion(suggestion.map(|(_, name)| name.as_str())));
+            self.error(position, message);
+          }
+        }
+      },
+      parser::Expression::Index { object, index } => {
+        self.resolve_expression(object);
+        self.resolve_expression(index);
+      },
+      parser::Expression::Template(parts) => {
+        for part in parts {
+          if let parser::TemplatePart::Expression(expr) = part {
+            self.resolve_expression(expr);
+          }
+        }
+      },
+      parser::Expression::Array(elements) => elements.iter_mut().for_each(|element| self.resolve_expression(element)),
+      parser::Expression::Table(entries) => entries.iter_mut().for_each(|(_, element)| self.resolve_expression(element)),
+    }
+  }
+
+  fn resolve_identifier(&mut self, iden: &mut parser::Identifier) {
+    match self.table.lookup(&iden.name) {
+      Some(record) => iden.symbol = Some(record.id),
+      None => {
+        let suggestion = self.table.nearest(&iden.name).map(|record| record.name.clone());
+        self.error(iden.position, format!("Undefined identifier \"{}\"{}", iden.name, Resolver::suggestion(suggestion.as_deref())));
+      },
+    }
+  }
+
+  /// Declares a symbol in the current scope
+  fn declare(&mut self, iden: &mut parser::Identifier, symbol_type: SymbolType) {
+    let shadowed = self.table.lookup_outer(&iden.name).map(|shadowed| shadowed.symbol_type);
+    if self.table.lookup_local(&iden.name).is_some() {
+      self.error(iden.position, format!("\"{}\" is already declared in this scope", iden.name));
+    }
+    let id = self.add_symbol(&iden.name, symbol_type);
+    iden.symbol = Some(id);
+    if let Some(shadowed) = shadowed {
+      self.resolution.shadowed.insert(id, shadowed);
+    }
+
+    if symbol_type == SymbolType::Variable {
+      let count = self.declaration_counts.entry(iden.name.clone()).or_default();
+      *count += 1;
+      let mut mangled = format!("{}{}{}", self.catweb_function_name, MANGLING_SEPARATOR, iden.name);
+      if *count > 1 {
+        mangled = format!("{}{}{}", mangled, MANGLING_SEPARATOR, count);
+      }
+      self.resolution.mangled_names.insert(id, MangledName {
+        function: self.catweb_function_name.clone(),
+        name: iden.name.clone(),
+        mangled,
+      });
+    }
+  }
+
+  fn add_symbol(&mut self, name: &str, symbol_type: SymbolType) -> SymbolId {
+    let record = SymbolRecord { id: self.resolution.symbols.len(), name: name.to_string(), symbol_type };
+    self.resolution.symbols.push(record.clone());
+    self.table.add_symbol(record);
+    self.resolution.symbols.len() - 1
+  }
+
+  /// Returns the dotted path of a member access on a namespace, e.g. `console.log`, and the position of the namespace
+  fn namespace_path(&self, expr: &parser::Expression) -> Option<(String, parser::Position)> {
+    match expr {
+      parser::Expression::Identifier(iden) => iden.symbol
+        .filter(|id| self.resolution.symbols[*id].symbol_type == SymbolType::Namespace)
+        .map(|_| (iden.name.clone(), iden.position)),
+      parser::Expression::Member { object, property } => self.namespace_path(object)
+        .map(|(path, position)| (format!("{}.{}", path, property), position)),
+      _ => None,
+    }
+  }
+
+  fn suggestion(name: Option<&str>) -> String {
+    name.map(|name| format!(", did you mean \"{}\"?", name)).unwrap_or_default()
+  }
+
+  /// Records an error at the position of an identifier, if it's known
+  fn error(&mut self, position: parser::Position, message: String) {
+    let error = self.locate(message);
+    match position.is_known() {
+      true => self.errors.push(format!("{}: {}", position, error)),
+      false => self.errors.push(error),
+    }
+  }
+
+  /// Prefixes a message with the function it concerns, if any
+  fn locate(&self, message: String) -> String {
+    match self.function_name.is_empty() {
+      true => message,
+      false => format!("In function \"{}\": {}", self.function_name, message),
+    }
+  }
+}
@@ -0,0 +1,11 @@
+use crate::parser;
+
+/// Source of the standard library, which is written in LynxScript
+pub const SOURCE: &str = include_str!("../lib/stdlib.lxs");
+
+/// Parses the items of the standard library
+pub fn items() -> Result<Vec<parser::Item>, anyhow::Error> {
+  let program = parser::Parser::new().parse_program_from_str(SOURCE)
+    .map_err(|err| anyhow::anyhow!("Failed to parse the standard library: {}", err))?;
+  Ok(program.main_block)
+}
@@ -0,0 +1,175 @@
+use crate::parser;
+use crate::codegen;
+use crate::codegen::{blocks, site};
+
+use super::{Compiler, ValueKind};
+
+/// Lowering of arrays, tables and `for ... of` loops to CatWeb's table actions.
+///
+/// CatWeb tables live in variables, so every table operand has to be a variable. Indices follow CatWeb, starting at 1.
+impl Compiler {
+  /// Builds an array or table literal in the `target` variable
+  pub(super) fn compile_collection(&mut self, expr: &parser::Expression, target: codegen::Variable) -> Result<Vec<codegen::Call>, anyhow::Error> {
+    let table = codegen::Argument::VariableName(target.clone());
+    let mut calls = vec![codegen::Call::CWScriptBlockCall {
+      dependencies: Vec::new(),
+      block_id: codegen::CWScriptBlockID { id: blocks::CREATE_TABLE.to_string() },
+      arguments: vec![],
+      return_var: Some(target),
+    }];
+
+    match expr {
+      parser::Expression::Array(elements) => {
+        for element in elements {
+          let (dependencies, value) = self.compile_table_value(element)?;
+          calls.extend(dependencies);
+          calls.push(Compiler::generate_block_call(blocks::INSERT_ENTRY, vec![table.clone(), value]));
+        }
+      },
+      parser::Expression::Table(entries) => {
+        for (key, element) in entries {
+          let (dependencies, value) = self.compile_table_value(element)?;
+          calls.extend(dependencies);
+          calls.push(Compiler::generate_block_call(blocks::SET_ENTRY, vec![
+            table.clone(),
+            codegen::Argument::Literal(codegen::Literal { value: key.clone() }),
+            value,
+          ]));
+        }
+      },
+      others => unreachable!("Expected array or table literal, found {:?}", others),
+    }
+    Ok(calls)
+  }
+
+  /// Whether `object.property` accesses a table entry rather than a UI object property.
+  ///
+  /// Values of unknown kind are treated as UI objects if the property is one of theirs.
+  pub(super) fn is_table_member(&self, object: &parser::Expression, property: &str) -> Result<bool, anyhow::Error> {
+    match self.value_kind(object)? {
+      Some(ValueKind::Table) => Ok(true),
+      Some(ValueKind::Object(_)) => Ok(false),
+      None => Ok(!site::all_known_properties().contains(&property)),
+    }
+  }
+
+  /// Compiles `t.length` and `t.key` reads
+  pub(super) fn compile_table_member(&mut self, object: &parser::Expression, property: &str) -> Result<codegen::Expression, anyhow::Error> {
+    let (dependencies, table) = self.compile_table_operand(object)?;
+    let result = self.state.new_temporary();
+    let read = match property {
+      "length" => codegen::Call::CWScriptBlockCall {
+        dependencies: Vec::new(),
+        block_id: codegen::CWScriptBlockID { id: blocks::TABLE_LENGTH.to_string() },
+        arguments: vec![table],
+        return_var: Some(result.clone()),
+      },
+      _ => codegen::Call::CWScriptBlockCall {
+        dependencies: Vec::new(),
+        block_id: codegen::CWScriptBlockID { id: blocks::GET_ENTRY.to_string() },
+        arguments: vec![table, codegen::Argument::Literal(codegen::Literal { value: property.to_string() })],
+        return_var: Some(result.clone()),
+      },
+    };
+    Ok(codegen::Expression {
+      dependencies: dependencies.into_iter().chain([read]).collect(),
+      content: Some(codegen::Argument::Identifier(result)),
+    })
+  }
+
+  /// Compiles `t[i]` reads
+  pub(super) fn compile_table_index(&mut self, object: &parser::Expression, index: &parser::Expression) -> Result<codegen::Expression, anyhow::Error> {
+    let (dependencies, table) = self.compile_table_operand(object)?;
+    let (index_dependencies, key) = self.compile_table_value(index)?;
+    let result = self.state.new_temporary();
+    Ok(codegen::Expression {
+      dependencies: dependencies.into_iter().chain(index_dependencies).chain([
+        codegen::Call::CWScriptBlockCall {
+          dependencies: Vec::new(),
+          block_id: codegen::CWScriptBlockID { id: blocks::GET_ENTRY.to_string() },
+          arguments: vec![table, key],
+          return_var: Some(result.clone()),
+        },
+      ]).collect(),
+      content: Some(codegen::Argument::Identifier(result)),
+    })
+  }
+
+  /// Compiles `t[key] = value;` and `t.key = value;`
+  pub(super) fn compile_table_write(&mut self, object: &parser::Expression, key: codegen::Expression, value: &parser::Expression) -> Result<codegen::Statement, anyhow::Error> {
+    let (dependencies, table) = self.compile_table_operand(object)?;
+    let key_value = key.content.ok_or_else(|| anyhow::anyhow!("Table key has no value"))?;
+    let (value_dependencies, value) = self.compile_table_value(value)?;
+    Ok(codegen::Statement::new(
+      dependencies.into_iter().chain(key.dependencies).chain(value_dependencies).collect(),
+      vec![Compiler::generate_block_call(blocks::SET_ENTRY, vec![table, key_value, value])],
+    ))
+  }
+
+  /// Compiles `t.push(value)` and `t.remove(key)`
+  pub(super) fn compile_table_method(
+    &mut self,
+    object: &parser::Expression,
+    method: &str,
+    argument_dependencies: Vec<codegen::Call>,
+    arguments: Vec<codegen::Argument>,
+  ) -> Result<codegen::Expression, anyhow::Error> {
+    let block_id = match method {
+      "push" => blocks::INSERT_ENTRY,
+      "remove" => blocks::REMOVE_ENTRY,
+      others => return Err(anyhow::anyhow!("Unknown table method \"{}\", expected push or remove", others)),
+    };
+    let [argument] = <[codegen::Argument; 1]>::try_from(arguments)
+      .map_err(|arguments| anyhow::anyhow!("Table method \"{}\" expects 1 argument, found {}", method, arguments.len()))?;
+
+    let (dependencies, table) = self.compile_table_operand(object)?;
+    Ok(codegen::Expression {
+      dependencies: dependencies.into_iter().chain(argument_dependencies).chain([
+        Compiler::generate_block_call(block_id, vec![table, argument]),
+      ]).collect(),
+      content: None,
+    })
+  }
+
+  /// Compiles `for (variable of iterable) { ... }` into the statement starting an iteration over the table, the
+  /// statements of its body and the statement ending it, which has the position of the loop. The index of each entry
+  /// is written to a temporary, which is live for the whole loop.
+  pub(super) fn compile_for_statement(
+    &mut self,
+    variable: &parser::Identifier,
+    iterable: &parser::Expression,
+    body: &[parser::Statement],
+    position: parser::Position,
+  ) -> Result<Vec<codegen::Statement>, anyhow::Error> {
+    let (dependencies, table) = self.compile_table_operand(iterable)?;
+    let value = self.variable(variable)?;
+
+    let start = codegen::Statement::new(dependencies, vec![Compiler::generate_block_call(blocks::ITERATE_TABLE, vec![
+      table,
+      codegen::Argument::VariableName(self.state.new_temporary()),
+      codegen::Argument::VariableName(value),
+    ])]);
+    let mut statements = vec![codegen::Statement { position, ..start }];
+    for statement in body {
+      statements.extend(self.compile_statement(statement)?);
+    }
+    let end = codegen::Statement::new(Vec::new(), vec![Compiler::generate_block_call(blocks::END, vec![])]);
+    statements.push(codegen::Statement { position, ..end });
+    Ok(statements)
+  }
+
+  /// Compiles an expression used as a table, which must be held by a variable
+  fn compile_table_operand(&mut self, expr: &parser::Expression) -> Result<(Vec<codegen::Call>, codegen::Argument), anyhow::Error> {
+    let compiled = self.compile_expression(expr)?;
+    match compiled.content {
+      Some(codegen::Argument::Identifier(var)) => Ok((compiled.dependencies, codegen::Argument::VariableName(var))),
+      _ => Err(anyhow::anyhow!("Expected a table, found {:?}", expr)),
+    }
+  }
+
+  fn compile_table_value(&mut self, expr: &parser::Expression) -> Result<(Vec<codegen::Call>, codegen::Argument), anyhow::Error> {
+    let compiled = self.compile_expression(expr)?;
+    let value = compiled.content.ok_or_else(|| anyhow::anyhow!("Expression stored in table has no value: {:?}", expr))?;
+    Ok((compiled.dependencies, value))
+  }
+}
@@ -0,0 +1,169 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use crate::codegen::{self, blocks};
+
+/// Number of CatWeb variables an item uses after temporary allocation
+#[derive(Debug, PartialEq, Clone)]
+pub struct VariableReport {
+  pub item: String,
+  pub variables: usize,
+  pub temporaries: usize,
+}
+
+/// Assigns the virtual temporaries of every item a reusable `__tN` slot.
+///
+/// A temporary is live from its first to its last occurrence in the execution order of the item's calls,
+/// which is the order `codegen::Statement::into_calls` emits them in.
+/// Temporaries whose live ranges do not overlap share a slot, and slots are handed out lowest first so the
+/// naming is deterministic. A temporary may take over the slot of one whose last use is the call defining it,
+/// since CatWeb actions read their inputs before writing their output.
+///
+/// Temporaries which are live when a loop starts stay live until its end, since the loop may read them again
+/// on its next iteration.
+pub fn allocate_temporaries(program: &mut codegen::Program) -> Vec<VariableReport> {
+  program.main_block.iter_mut().map(|item| {
+    let (name, parameters, body) = match item {
+      codegen::Item::FunctionDeclaration { name, parameters, body, .. } => (name.clone(), parameters.as_slice(), body),
+      codegen::Item::Event { name, body } => (name.clone(), [].as_slice(), body),
+    };
+
+    let slots = assign_slots(live_ranges(body));
+    visit_variables(body, &mut |var, _| {
+      if let Some(slot) = slots.get(&var.name) {
+        *var = codegen::Variable::temporary(*slot);
+      }
+    });
+    // Sharing slots turns some copies between temporaries into `set __tN to {__tN}`
+    for statement in body.iter_mut() {
+      statement.dependencies = remove_self_assignments(std::mem::take(&mut statement.dependencies));
+      statement.content = remove_self_assignments(std::mem::take(&mut statement.content));
+    }
+
+    let mut variables: HashSet<String> = parameters.iter().map(|param| param.name.clone()).collect();
+    visit_variables(body, &mut |var, _| {
+      variables.insert(var.name.clone());
+    });
+
+    VariableReport {
+      item: name,
+      variables: variables.len(),
+      temporaries: slots.values().collect::<HashSet<_>>().len(),
+    }
+  }).collect()
+}
+
+/// Returns the first and last position each virtual temporary is live at
+fn live_ranges(body: &mut [codegen::Statement]) -> HashMap<String, (usize, usize)> {
+  let mut ranges: HashMap<String, (usize, usize)> = HashMap::new();
+  visit_variables(body, &mut |var, position| {
+    if var.is_virtual_temporary() {
+      ranges.entry(var.name.clone())
+        .and_modify(|range| range.1 = position)
+        .or_insert((position, position));
+    }
+  });
+
+  for (loop_start, loop_end) in loop_ranges(body) {
+    for (start, end) in ranges.values_mut() {
+      if *start <= loop_start && *end >= loop_start {
+        *end = (*end).max(loop_end);
+      }
+    }
+  }
+  ranges
+}
+
+/// Returns the positions of the first and last (`END`) action of every loop
+pub(super) fn loop_ranges(body: &mut [codegen::Statement]) -> Vec<(usize, usize)> {
+  let mut loops: Vec<(usize, usize)> = Vec::new();
+  let mut open_blocks: Vec<(usize, bool)> = Vec::new(); // (start, is loop)
+  visit_calls(body, &mut |call, position| {
+    let codegen::Call::CWScriptBlockCall { block_id, .. } = call else {
+      return;
+    };
+    if blocks::BLOCK_OPENERS.contains(&block_id.id.as_str()) {
+      open_blocks.push((position, blocks::LOOPS.contains(&block_id.id.as_str())));
+    } else if block_id.id == blocks::END && let Some((start, true)) = open_blocks.pop() {
+      loops.push((start, position));
+    }
+  });
+  loops
+}
+
+/// Linear scan over the live ranges, reusing the slots of temporaries which are no longer live
+fn assign_slots(ranges: HashMap<String, (usize, usize)>) -> HashMap<String, usize> {
+  let mut ordered: Vec<(String, (usize, usize))> = ranges.into_iter().collect();
+  ordered.sort_by(|(lhs_name, lhs_range), (rhs_name, rhs_range)| (lhs_range, lhs_name).cmp(&(rhs_range, rhs_name)));
+
+  let mut slots: HashMap<String, usize> = HashMap::new();
+  let mut active: Vec<(usize, usize)> = Vec::new(); // (end, slot)
+  let mut free: BTreeSet<usize> = BTreeSet::new();
+  let mut slot_count = 0;
+
+  for (name, (start, end)) in ordered {
+    active.retain(|&(active_end, slot)| {
+      if active_end <= start {
+        free.insert(slot);
+        false
+      } else {
+        true
+      }
+    });
+
+    let slot = free.pop_first().unwrap_or_else(|| {
+      slot_count += 1;
+      slot_count - 1
+    });
+    active.push((end, slot));
+    slots.insert(name, slot);
+  }
+
+  slots
+}
+
+/// Removes `set x to {x}` calls, keeping their dependencies in place
+fn remove_self_assignments(calls: Vec<codegen::Call>) -> Vec<codegen::Call> {
+  calls.into_iter().flat_map(|mut call| {
+    let dependencies = remove_self_assignments(std::mem::take(call.dependencies_mut()));
+    let is_self_assignment = match &call {
+      codegen::Call::CWScriptBlockCall { block_id, arguments, .. } if block_id.id == blocks::SET_VARIABLE => matches!(
+        arguments.as_slice(),
+        [codegen::Argument::VariableName(target), codegen::Argument::Identifier(value)] if target == value
+      ),
+      _ => false,
+    };
+    if is_self_assignment {
+      dependencies
+    } else {
+      *call.dependencies_mut() = dependencies;
+      vec![call]
+    }
+  }).collect()
+}
+
+/// Visits every variable in the body together with the execution position of the call it occurs in
+fn visit_variables(body: &mut [codegen::Statement], visitor: &mut impl FnMut(&mut codegen::Variable, usize)) {
+  visit_calls(body, &mut |call, position| {
+    for var in call.variables_mut() {
+      visitor(var, position);
+    }
+  });
+}
+
+/// Visits every call in the body in execution order, together with its position
+pub(super) fn visit_calls(body: &mut [codegen::Statement], visitor: &mut impl FnMut(&mut codegen::Call, usize)) {
+  let mut position = 0;
+  for statement in body {
+    for call in statement.dependencies.iter_mut().chain(statement.content.iter_mut()) {
+      visit_call(call, &mut position, visitor);
+    }
+  }
+}
+
+fn visit_call(call: &mut codegen::Call, position: &mut usize, visitor: &mut impl FnMut(&mut codegen::Call, usize)) {
+  for dependency in call.dependencies_mut() {
+    visit_call(dependency, position, visitor);
+  }
+  visitor(call, *position);
+  *position += 1;
+}
@@ -7,17 +7,22 @@ fn test_simple_function_compiling() {
     main_block: vec![
       parser::Item::FunctionDeclaration(
         parser::FunctionDeclaration {         
+          attributes: vec![],
+          position: Default::default(),
           name: "add".to_string(),
           parameters: vec![
-            parser::Expression::Identifier("a".to_string()),
-            parser::Expression::Identifier("b".to_string()),
+            parser::Parameter { name: parser::Identifier::new("a"), type_annotation: None },
+            parser::Parameter { name: parser::Identifier::new("b"), type_annotation: None },
           ],
+          return_type: None,
           body: vec![
             parser::Statement::Expression {
+              attributes: vec![],
+              position: Default::default(),
               expr: parser::Expression::Call {
                 function: Box::new(parser::Expression::CWScriptBlockID("0".to_string())),
                 arguments: vec![
-                  parser::Expression::Literal(parser::Literal::String("".to_string())),
+                  parser::Expression::Literal(parser::Literal::RawString("".to_string())),
                   parser::Expression::Literal(parser::Literal::String("Hello, World!".to_string())),
                 ]
               }
@@ -30,7 +35,7 @@ fn test_simple_function_compiling() {
 
   let mut compiler = super::Compiler::new(input);
   
-  let structure_res = compiler.compile();
+  let structure_res = compiler.compile().unwrap();
 
   let expected_structure = structures::Program {
     main_block: vec![
@@ -38,10 +43,10 @@ fn test_simple_function_compiling() {
         name: "add".to_string(),
         parameters: vec![
           structures::Variable {
-            name: "a".to_string(),
+            name: "add$a".to_string(),
           },
           structures::Variable {
-            name: "b".to_string(),
+            name: "add$b".to_string(),
           },
         ],
         body: vec![
@@ -66,11 +71,846 @@ fn test_simple_function_compiling() {
                   },
                 ],
                 content: vec![],
+                position: Default::default(),
+                comments: vec![],
               },
           ],
+          position: Default::default(),
+          comments: vec![],
       },
   ],
 };
 
   assert_eq!(expected_structure, structure_res);
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_temporary_allocation() {
+  use crate::{codegen::{blocks, structures}, parser};
+
+  let input = r#"
+  function f(a, b) {
+    let x = (a + b) * (a - b);
+    let y = a + b;
+  }"#;
+
+  let syntax_tree = parser::Parser::new().parse_program_from_str(input).unwrap();
+  let mut compiler = super::Compiler::new(syntax_tree);
+  let structure_res = compiler.compile().unwrap();
+
+  let math = |block_id: &str, target: &str, value: structures::Argument| structures::Call::CWScriptBlockCall {
+    dependencies: vec![],
+    block_id: structures::CWScriptBlockID { id: block_id.to_string() },
+    arguments: vec![structures::Argument::VariableName(structures::Variable { name: target.to_string() }), value],
+    return_var: None,
+  };
+  let set = |target: &str, value: structures::Argument| math(blocks::SET_VARIABLE, target, value);
+  let read = |name: &str| structures::Argument::Identifier(structures::Variable { name: name.to_string() });
+
+  let structures::Item::FunctionDeclaration { body, .. } = structure_res.main_block[0].clone() else {
+    panic!("Expected function declaration");
+  };
+  let calls: Vec<structures::Call> = body.into_iter().flat_map(|statement| statement.into_calls()).collect();
+
+  // `__t0` is free again once it is copied into the multiplication result, which makes the copy redundant
+  assert_eq!(calls, vec![
+    set("__t0", read("f$a")),
+    math(blocks::INCREASE_VARIABLE, "__t0", read("f$b")),
+    set("__t1", read("f$a")),
+    math(blocks::SUBTRACT_VARIABLE, "__t1", read("f$b")),
+    math(blocks::MULTIPLY_VARIABLE, "__t0", read("__t1")),
+    set("f$x", read("__t0")),
+    set("__t0", read("f$a")),
+    math(blocks::INCREASE_VARIABLE, "__t0", read("f$b")),
+    set("f$y", read("__t0")),
+  ]);
+
+  assert_eq!(compiler.variable_reports(), &[super::temporaries::VariableReport {
+    item: "f".to_string(),
+    variables: 6,
+    temporaries: 2,
+  }]);
+}
+
+#[test]
+fn test_reserved_identifiers() {
+  use crate::parser;
+
+  let input = r#"
+  function f(a) {
+    let __t0 = a;
+  }"#;
+
+  let syntax_tree = parser::Parser::new().parse_program_from_str(input).unwrap();
+  let mut compiler = super::Compiler::new(syntax_tree);
+  assert!(compiler.compile().is_err());
+}
+
+#[test]
+fn test_unary_operation_compiling() {
+  use crate::{codegen::{blocks, structures}, parser};
+
+  let input = r#"
+  function f(a) {
+    let x = -5;
+    let y = --a;
+    let z = -a;
+    let b = !!!true;
+    let c = !a;
+    let d = !!a;
+  }"#;
+
+  let syntax_tree = parser::Parser::new().parse_program_from_str(input).unwrap();
+  let structure_res = super::Compiler::new(syntax_tree).compile().unwrap();
+
+  let block = |block_id: &str, arguments: Vec<structures::Argument>| structures::Call::CWScriptBlockCall {
+    dependencies: vec![],
+    block_id: structures::CWScriptBlockID { id: block_id.to_string() },
+    arguments,
+    return_var: None,
+  };
+  let name = |name: &str| structures::Argument::VariableName(structures::Variable { name: name.to_string() });
+  let read = |name: &str| structures::Argument::Identifier(structures::Variable { name: name.to_string() });
+  let literal = |value: &str| structures::Argument::Literal(structures::Literal { value: value.to_string() });
+
+  let structures::Item::FunctionDeclaration { body, .. } = structure_res.main_block[0].clone() else {
+    panic!("Expected function declaration");
+  };
+  let calls: Vec<structures::Call> = body.into_iter().flat_map(|statement| statement.into_calls()).collect();
+
+  assert_eq!(calls, vec![
+    block(blocks::SET_VARIABLE, vec![name("f$x"), literal("-5")]),
+    block(blocks::SET_VARIABLE, vec![name("f$y"), read("f$a")]),
+    block(blocks::SET_VARIABLE, vec![name("__t0"), literal("0")]),
+    block(blocks::SUBTRACT_VARIABLE, vec![name("__t0"), read("f$a")]),
+    block(blocks::SET_VARIABLE, vec![name("f$z"), read("__t0")]),
+    block(blocks::SET_VARIABLE, vec![name("f$b"), literal("false")]),
+    block(blocks::SET_VARIABLE, vec![name("__t0"), literal("true")]),
+    block(blocks::IF_EQUAL, vec![read("f$a"), literal("true")]),
+    block(blocks::SET_VARIABLE, vec![name("__t0"), literal("false")]),
+    block(blocks::END, vec![]),
+    block(blocks::SET_VARIABLE, vec![name("f$c"), read("__t0")]),
+    block(blocks::SET_VARIABLE, vec![name("__t0"), literal("false")]),
+    block(blocks::IF_EQUAL, vec![read("f$a"), literal("true")]),
+    block(blocks::SET_VARIABLE, vec![name("__t0"), literal("true")]),
+    block(blocks::END, vec![]),
+    block(blocks::SET_VARIABLE, vec![name("f$d"), read("__t0")]),
+  ]);
+}
+
+#[test]
+fn test_template_literal_compiling() {
+  use crate::{codegen::{blocks, structures}, parser};
+
+  let input = r#"
+  function f(user, score) {
+    #0(`Hello ${user}, ${"{literal}"} ${score + 1}`, `${user}`);
+  }"#;
+
+  let syntax_tree = parser::Parser::new().parse_program_from_str(input).unwrap();
+  let structure_res = super::Compiler::new(syntax_tree).compile().unwrap();
+
+  let variable = |name: &str| structures::Variable { name: name.to_string() };
+
+  let structures::Item::FunctionDeclaration { body, .. } = structure_res.main_block[0].clone() else {
+    panic!("Expected function declaration");
+  };
+  let calls: Vec<structures::Call> = body.into_iter().flat_map(|statement| statement.into_calls()).collect();
+
+  assert_eq!(calls.last().unwrap(), &structures::Call::CWScriptBlockCall {
+    dependencies: vec![],
+    block_id: structures::CWScriptBlockID { id: "0".to_string() },
+    arguments: vec![
+      structures::Argument::Template(vec![
+        structures::TemplatePart::Text("Hello ".to_string()),
+        structures::TemplatePart::Variable(variable("f$user")),
+        structures::TemplatePart::Text(", ".to_string()),
+        structures::TemplatePart::Text("{literal}".to_string()),
+        structures::TemplatePart::Text(" ".to_string()),
+        structures::TemplatePart::Variable(variable("__t0")),
+      ]),
+      // A lone variable is passed as is
+      structures::Argument::Identifier(variable("f$user")),
+    ],
+    return_var: None,
+  });
+  // `score + 1` is evaluated into a temporary first
+  assert!(matches!(&calls[0], structures::Call::CWScriptBlockCall { block_id, .. } if block_id.id == blocks::SET_VARIABLE));
+}
+
+#[test]
+fn test_property_access_compiling() {
+  use crate::{codegen::{blocks, site, structures}, parser};
+
+  let input = r#"
+  link "site.json";
+
+  function f() {
+    title.text = "Hi";
+    let label = title;
+    let v = label.visible;
+  }"#;
+
+  let mut site_data = site::SiteData::default();
+  site_data.add_json(r#"[{"class": "Frame", "globalid": "frame", "alias": "page", "children": [
+    {"class": "TextLabel", "globalid": "label", "alias": "title"}
+  ]}]"#).unwrap();
+
+  let syntax_tree = parser::Parser::new().parse_program_from_str(input).unwrap();
+  assert_eq!(syntax_tree.link_statements, vec![parser::Statement::Link { path: "site.json".to_string(), position: parser::Position { line: 2, column: 3 } }]);
+  let structure_res = super::Compiler::new(syntax_tree).with_site_data(site_data.clone()).compile().unwrap();
+
+  let block = |block_id: &str, arguments: Vec<structures::Argument>, return_var: Option<&str>| structures::Call::CWScriptBlockCall {
+    dependencies: vec![],
+    block_id: structures::CWScriptBlockID { id: block_id.to_string() },
+    arguments,
+    return_var: return_var.map(|name| structures::Variable { name: name.to_string() }),
+  };
+  let name = |name: &str| structures::Argument::VariableName(structures::Variable { name: name.to_string() });
+  let read = |name: &str| structures::Argument::Identifier(structures::Variable { name: name.to_string() });
+  let literal = |value: &str| structures::Argument::Literal(structures::Literal { value: value.to_string() });
+
+  let structures::Item::FunctionDeclaration { body, .. } = structure_res.main_block[0].clone() else {
+    panic!("Expected function declaration");
+  };
+  let calls: Vec<structures::Call> = body.into_iter().flat_map(|statement| statement.into_calls()).collect();
+
+  assert_eq!(calls, vec![
+    block(blocks::SET_PROPERTY, vec![literal("text"), structures::Argument::Object("label".to_string()), literal("Hi")], None),
+    block(blocks::SET_VARIABLE, vec![name("f$label"), structures::Argument::Object("label".to_string())], None),
+    block(blocks::GET_PROPERTY, vec![literal("visible"), read("f$label")], Some("__t0")),
+    block(blocks::SET_VARIABLE, vec![name("f$v"), read("__t0")], None),
+  ]);
+
+  // Properties are checked against the class of the object, also through variables
+  for body in ["title.image = 1;", "let label = title; label.image = 1;", "page.text = 1;"] {
+    let input = format!("function f() {{ {} }}", body);
+    let syntax_tree = parser::Parser::new().parse_program_from_str(&input).unwrap();
+    let error = super::Compiler::new(syntax_tree).with_site_data(site_data.clone()).compile().unwrap_err();
+    assert!(error.to_string().contains("Unknown property"), "{}", error);
+  }
+}
+
+#[test]
+fn test_table_compiling() {
+  use crate::{codegen::{blocks, structures}, parser};
+
+  let input = r#"
+  function f(i) {
+    let t = [1, 2];
+    t.push(3);
+    t[i] = t.length;
+    let scores = { name: "x", score: 3 };
+    scores.score = scores.score + 1;
+    for (x of t) {
+      #0(#"", x);
+    }
+  }"#;
+
+  let syntax_tree = parser::Parser::new().parse_program_from_str(input).unwrap();
+  let structure_res = super::Compiler::new(syntax_tree).compile().unwrap();
+
+  let block = |block_id: &str, arguments: Vec<structures::Argument>, return_var: Option<&str>| structures::Call::CWScriptBlockCall {
+    dependencies: vec![],
+    block_id: structures::CWScriptBlockID { id: block_id.to_string() },
+    arguments,
+    return_var: return_var.map(|name| structures::Variable { name: name.to_string() }),
+  };
+  let name = |name: &str| structures::Argument::VariableName(structures::Variable { name: name.to_string() });
+  let read = |name: &str| structures::Argument::Identifier(structures::Variable { name: name.to_string() });
+  let literal = |value: &str| structures::Argument::Literal(structures::Literal { value: value.to_string() });
+
+  let structures::Item::FunctionDeclaration { body, .. } = structure_res.main_block[0].clone() else {
+    panic!("Expected function declaration");
+  };
+  let calls: Vec<structures::Call> = body.into_iter().flat_map(|statement| statement.into_calls()).collect();
+
+  assert_eq!(calls, vec![
+    block(blocks::CREATE_TABLE, vec![], Some("f$t")),
+    block(blocks::INSERT_ENTRY, vec![name("f$t"), literal("1")], None),
+    block(blocks::INSERT_ENTRY, vec![name("f$t"), literal("2")], None),
+    block(blocks::INSERT_ENTRY, vec![name("f$t"), literal("3")], None),
+    block(blocks::TABLE_LENGTH, vec![name("f$t")], Some("__t0")),
+    block(blocks::SET_ENTRY, vec![name("f$t"), read("f$i"), read("__t0")], None),
+    block(blocks::CREATE_TABLE, vec![], Some("f$scores")),
+    block(blocks::SET_ENTRY, vec![name("f$scores"), literal("name"), literal("x")], None),
+    block(blocks::SET_ENTRY, vec![name("f$scores"), literal("score"), literal("3")], None),
+    block(blocks::GET_ENTRY, vec![name("f$scores"), literal("score")], Some("__t0")),
+    // The sum shares the slot of the entry it starts from, so no copy is needed
+    block(blocks::INCREASE_VARIABLE, vec![name("__t0"), literal("1")], None),
+    block(blocks::SET_ENTRY, vec![name("f$scores"), literal("score"), read("__t0")], None),
+    block(blocks::ITERATE_TABLE, vec![name("f$t"), name("__t0"), name("f$x")], None),
+    block("0", vec![structures::Argument::RawString("".to_string()), read("f$x")], None),
+    block(blocks::END, vec![], None),
+  ]);
+}
+
+#[test]
+fn test_loop_temporaries_stay_live() {
+  use crate::{codegen::structures, parser};
+
+  // The temporary holding the array literal is read by the loop on every iteration,
+  // so the temporaries inside the loop body must not reuse its slot
+  let input = r#"
+  function f(a) {
+    for (x of [1, 2]) {
+      let y = a + x;
+    }
+  }"#;
+
+  let syntax_tree = parser::Parser::new().parse_program_from_str(input).unwrap();
+  let structure_res = super::Compiler::new(syntax_tree).compile().unwrap();
+
+  let structures::Item::FunctionDeclaration { body, .. } = structure_res.main_block[0].clone() else {
+    panic!("Expected function declaration");
+  };
+  let calls: Vec<structures::Call> = body.into_iter().flat_map(|statement| statement.into_calls()).collect();
+  let structures::Call::CWScriptBlockCall { arguments, .. } = &calls[3] else {
+    panic!("Expected iteration");
+  };
+  let structures::Call::CWScriptBlockCall { arguments: body_arguments, .. } = &calls[4] else {
+    panic!("Expected assignment");
+  };
+  assert_eq!(arguments[0], structures::Argument::VariableName(structures::Variable { name: "__t0".to_string() }));
+  assert_eq!(arguments[1], structures::Argument::VariableName(structures::Variable { name: "__t1".to_string() }));
+  assert_eq!(body_arguments[0], structures::Argument::VariableName(structures::Variable { name: "__t2".to_string() }));
+}
+
+#[test]
+fn test_type_checking() {
+  use crate::{codegen::{site, structures}, parser};
+
+  let mut site_data = site::SiteData::default();
+  site_data.add_json(r#"[{"class": "TextLabel", "globalid": "label", "alias": "title"}]"#).unwrap();
+  let compile = |input: &str| {
+    let syntax_tree = parser::Parser::new().parse_program_from_str(input).unwrap();
+    super::Compiler::new(syntax_tree).with_site_data(site_data.clone()).compile()
+  };
+
+  // Annotated and inferred types which are used consistently
+  let program = compile(r#"
+  function double(n: number) {
+    let result: number = n * 2;
+  }
+  function f(label: TextLabel, anything) {
+    let x = 1;
+    double(x + anything);
+    label.text = `Hi ${x}`;
+    console.log(label.visible);
+  }"#).unwrap();
+
+  // Calls into the standard library use its exported name, and pull in its implementation
+  let names = program.main_block.iter().map(|item| match item {
+    structures::Item::FunctionDeclaration { name, .. } | structures::Item::Event { name, .. } => name.as_str(),
+  }).collect::<Vec<&str>>();
+  assert_eq!(names, vec!["double", "f", "console.log"]);
+
+  for (body, message) in [
+    ("let n: number = title;", "Cannot assign TextLabel to \"n\" of type number"),
+    ("let n: number = 0; n = \"a\";", "Cannot assign string to \"n\" of type number"),
+    ("double(title);", "Argument \"n\" of function \"double\" expects number, found TextLabel"),
+    ("double();", "Function \"double\" expects 1 arguments, found 0"),
+    ("let x = 1; x();", "Cannot call \"x\" of type number, it is not a function"),
+    ("let x = title.image;", "Unknown property \"image\" on TextLabel object"),
+    ("title.visible = \"yes\";", "Cannot assign string to property \"visible\" of type bool"),
+    ("let x = \"a\" + 1;", "Operator \"+\" expects number operands, found string"),
+    ("let x = double(1);", "Expression \"double(1)\" has no value"),
+    ("console.log(`${title}`);", "Object \"title\" cannot be embedded in a template literal"),
+    ("let t: thing = 1;", "Unknown type \"thing\""),
+  ] {
+    let input = format!("function double(n: number) {{}}\nfunction f() {{ {} }}", body);
+    let error = compile(&input).unwrap_err();
+    assert!(error.to_string().contains(message), "{}: {}", body, error);
+  }
+
+  // Errors are reported at the declaration or statement they're in
+  let error = compile("function f() {\n  let x = 1;\n  x();\n}").unwrap_err();
+  assert_eq!(error.to_string(), "3:3: In function \"f\": Cannot call \"x\" of type number, it is not a function");
+  let error = compile("global n: number = \"one\";").unwrap_err();
+  assert_eq!(error.to_string(), "1:8: Cannot assign string to \"n\" of type number");
+
+  // Functions can't return values yet, so only the calls which use their results are errors
+  compile("function f(): void {} f();").unwrap();
+  compile("function f(a: string): bool {}\nf(\"hi\");").unwrap();
+  let error = compile("function f(a: string): bool {}\nlet x = f(\"hi\");").unwrap_err();
+  assert_eq!(error.to_string(), "2:1: Expression \"f(\"hi\")\" has no value");
+  let error = compile("function f(a: string) {}\nlet x = f(\"hi\");").unwrap_err();
+  assert_eq!(error.to_string(), "2:1: Expression \"f(\"hi\")\" has no value");
+}
+
+#[test]
+fn test_name_resolution() {
+  use crate::{codegen::{site, symbol_table::SymbolType}, parser};
+
+  let mut site_data = site::SiteData::default();
+  site_data.add_json(r#"[{"class": "TextLabel", "globalid": "label", "alias": "title"}]"#).unwrap();
+  let compiler = |input: &str| {
+    let syntax_tree = parser::Parser::new().parse_program_from_str(input).unwrap();
+    super::Compiler::new(syntax_tree).with_site_data(site_data.clone())
+  };
+
+  let mut shadowing = compiler(r#"
+  function f(title) {
+    let x = [1];
+    for (x of x) {
+      let y = x;
+    }
+    let y = title;
+  }"#);
+  shadowing.compile().unwrap();
+  let shadowing_lints = shadowing.lints().iter()
+    .filter(|diagnostic| diagnostic.lint == super::lints::SHADOWING)
+    .map(|diagnostic| diagnostic.message.as_str())
+    .collect::<Vec<&str>>();
+  assert_eq!(shadowing_lints, [
+    "2:14: In function \"f\": variable \"title\" shadows the UI object of the same name",
+    "4:10: In function \"f\": variable \"x\" shadows the variable of the same name",
+  ]);
+
+  // Identifiers keep the symbol they were resolved to, e.g. the loop variable and the array it iterates over differ
+  let parser::Item::FunctionDeclaration(func) = &shadowing.syntax_tree.main_block[0] else {
+    panic!("Expected function declaration");
+  };
+  let parser::Statement::For { variable, iterable: parser::Expression::Identifier(iterable), body, .. } = &func.body[1] else {
+    panic!("Expected for statement");
+  };
+  let parser::Statement::Let { value: parser::Expression::Identifier(loop_value), .. } = &body[0] else {
+    panic!("Expected let statement");
+  };
+  assert_ne!(variable.symbol, iterable.symbol);
+  assert_eq!(variable.symbol, loop_value.symbol);
+  assert_eq!(shadowing.resolution.symbol(iterable).symbol_type, SymbolType::Variable);
+
+  for (body, message) in [
+    ("let count = 1; let total = cont;", "Undefined identifier \"cont\", did you mean \"count\"?"),
+    ("let x = unknown;", "Undefined identifier \"unknown\""),
+    ("x = 1;", "Undefined identifier \"x\""),
+    ("let x = 1; let x = 2;", "\"x\" is already declared in this scope"),
+    ("for (x of [1]) { let y = x; } let z = y;", "Undefined identifier \"y\""),
+    ("console.lg(1);", "Undefined function \"console.lg\", did you mean \"console.log\"?"),
+    ("title = 1;", "Cannot assign to the UI object \"title\", only to variables"),
+    ("let count = 1; let total = cuont;", "Undefined identifier \"cuont\", did you mean \"count\"?"),
+  ] {
+    let input = format!("function f() {{ {} }}", body);
+    let error = compiler(&input).compile().unwrap_err();
+    assert!(error.to_string().contains(message), "{}: {}", body, error);
+  }
+
+  // Errors are reported at the identifier they're about, and one-letter names have no likely typos
+  for (input, message) in [
+    ("function f() {\n  let x = 1;\n  let x = 2;\n}", "3:7: In function \"f\": \"x\" is already declared in this scope"),
+    ("function f() {}\nf(x);", "2:3: Undefined identifier \"x\""),
+    ("let n = 1;\nconsole.lg(n);", "2:1: Undefined function \"console.lg\", did you mean \"console.log\"?"),
+    ("function f() {}\nf = 1;", "2:1: Cannot assign to the function \"f\", only to variables"),
+  ] {
+    let error = compiler(input).compile().unwrap_err();
+    assert_eq!(error.to_string(), message, "{}", input);
+  }
+
+  // Functions can't be declared twice, by their name or by the name they're exported as
+  for (input, message) in [
+    ("function f() {}\nfunction f() {}", "2:10: In function \"f\": \"f\" is already declared at 1:10"),
+    ("#[export_as(\"g\")] function f() {}\nfunction g() {}", "2:10: In function \"g\": \"g\" is already declared at 1:28"),
+    (
+      "#[export_as(\"console.warn\")] function f() {}\n#[export_as(\"console.warn\")] function g() {}",
+      "2:39: In function \"g\": \"console.warn\" is already declared at 1:39",
+    ),
+  ] {
+    let error = compiler(input).compile().unwrap_err();
+    assert_eq!(error.to_string(), message, "{}", input);
+  }
+}
+
+#[test]
+fn test_local_name_mangling() {
+  use crate::{codegen::{blocks, structures}, parser};
+
+  let input = r#"
+  global score: number;
+
+  function f(i) {
+    let x = i;
+    score = x;
+    for (x of [1]) {
+      let y = x;
+    }
+  }
+  function g(i) {
+    let x = score;
+  }"#;
+
+  let syntax_tree = parser::Parser::new().parse_program_from_str(input).unwrap();
+  let mut compiler = super::Compiler::new(syntax_tree);
+  let structure_res = compiler.compile().unwrap();
+
+  let variable = |name: &str| structures::Variable { name: name.to_string() };
+  let read = |name: &str| structures::Argument::Identifier(variable(name));
+  let assignments = |item: &structures::Item| {
+    let structures::Item::FunctionDeclaration { parameters, body, .. } = item.clone() else {
+      panic!("Expected function declaration");
+    };
+    let assignments = body.into_iter().flat_map(|statement| statement.into_calls()).filter_map(|call| match call {
+      structures::Call::CWScriptBlockCall { block_id, arguments, .. } if block_id.id == blocks::SET_VARIABLE => match &arguments[..] {
+        [structures::Argument::VariableName(target), value] => Some((target.name.clone(), value.clone())),
+        _ => None,
+      },
+      _ => None,
+    }).collect::<Vec<(String, structures::Argument)>>();
+    (parameters, assignments)
+  };
+
+  // Locals and parameters are unique to their function, while globals keep their name
+  let (parameters, assignments_f) = assignments(&structure_res.main_block[0]);
+  assert_eq!(parameters, vec![variable("f$i")]);
+  assert_eq!(assignments_f, vec![
+    ("f$x".to_string(), read("f$i")),
+    ("score".to_string(), read("f$x")),
+    ("f$y".to_string(), read("f$x$2")),
+  ]);
+  let (parameters, assignments_g) = assignments(&structure_res.main_block[1]);
+  assert_eq!(parameters, vec![variable("g$i")]);
+  assert_eq!(assignments_g, vec![("g$x".to_string(), read("score"))]);
+
+  let mapping = compiler.name_mapping().into_iter()
+    .map(|mapping| format!("{}: {} = {}", mapping.function, mapping.name, mapping.mangled))
+    .collect::<Vec<String>>();
+  assert_eq!(mapping, vec!["f: i = f$i", "f: x = f$x", "f: x = f$x$2", "f: y = f$y", "g: i = g$i", "g: x = g$x"]);
+
+  // Globals are typed like other variables
+  let syntax_tree = parser::Parser::new().parse_program_from_str("global score: number; function f() { score = \"a\"; }").unwrap();
+  let error = super::Compiler::new(syntax_tree).compile().unwrap_err();
+  assert!(error.to_string().contains("Cannot assign string to \"score\" of type number"), "{}", error);
+}
+
+#[test]
+fn test_recursive_call_frames() {
+  use crate::{codegen::{blocks, structures}, parser};
+
+  let input = r#"
+  function countdown(n: number) {
+    let left = n - 1;
+    countdown(left);
+    console.log(n);
+  }
+  function ping(x) { pong(x); }
+  function pong(x) { ping(x); }
+  function twice(x) {
+    console.log(x);
+    console.log(x);
+  }"#;
+
+  let syntax_tree = parser::Parser::new().parse_program_from_str(input).unwrap();
+  let mut compiler = super::Compiler::new(syntax_tree);
+  let structure_res = compiler.compile().unwrap();
+
+  let names = structure_res.main_block.iter().map(|item| match item {
+    structures::Item::FunctionDeclaration { name, .. } | structures::Item::Event { name, .. } => name.as_str(),
+  }).collect::<Vec<&str>>();
+  assert_eq!(names, vec![blocks::WEBSITE_LOADED, "countdown", "ping", "pong", "twice", "console.log"]);
+
+  let calls = |index: usize| {
+    let (structures::Item::FunctionDeclaration { body, .. } | structures::Item::Event { body, .. }) = structure_res.main_block[index].clone();
+    body.into_iter().flat_map(|statement| statement.into_calls()).map(|call| match call {
+      structures::Call::FunctionCall { function_name, .. } => function_name.name,
+      structures::Call::CWScriptBlockCall { block_id, arguments, return_var, .. } => format!(
+        "{}({}){}",
+        block_id.id,
+        arguments.iter().map(|arg| match arg {
+          structures::Argument::Identifier(var) | structures::Argument::VariableName(var) => var.name.clone(),
+          structures::Argument::Literal(lit) => lit.value.clone(),
+          others => format!("{:?}", others),
+        }).collect::<Vec<String>>().join(", "),
+        return_var.as_ref().map(|var| format!(" -> {}", var.name)).unwrap_or_default(),
+      ),
+    }).collect::<Vec<String>>()
+  };
+
+  // The stack is created when the website loads
+  assert_eq!(calls(0), vec![format!("{}() -> __stack", blocks::CREATE_TABLE)]);
+
+  // Only `n` is read after the recursive call, so only it is saved
+  assert_eq!(calls(1), vec![
+    format!("{}(__t0, countdown$n)", blocks::SET_VARIABLE),
+    format!("{}(__t0, 1)", blocks::SUBTRACT_VARIABLE),
+    format!("{}(countdown$left, __t0)", blocks::SET_VARIABLE),
+    format!("{}(__stack, countdown$n)", blocks::INSERT_ENTRY),
+    "countdown".to_string(),
+    format!("{}(__stack) -> __t0", blocks::TABLE_LENGTH),
+    format!("{}(__stack, __t0) -> countdown$n", blocks::GET_ENTRY),
+    format!("{}(__stack, __t0)", blocks::REMOVE_ENTRY),
+    "console.log".to_string(),
+  ]);
+
+  // Mutually recursive functions without variables live across their calls, and functions which aren't recursive, are unchanged
+  assert_eq!(calls(2), vec!["pong".to_string()]);
+  assert_eq!(calls(3), vec!["ping".to_string()]);
+  assert_eq!(calls(4), vec!["console.log".to_string(), "console.log".to_string()]);
+
+  assert_eq!(compiler.warnings(), &[
+    "In function \"countdown\": recursive calls save and restore the live variables on the call stack, adding 4 blocks".to_string(),
+  ]);
+
+  // Programs without recursion don't create the stack
+  let syntax_tree = parser::Parser::new().parse_program_from_str(input.replace("countdown(left);", "").as_str()).unwrap();
+  let structure_res = super::Compiler::new(syntax_tree).compile().unwrap();
+  assert!(structure_res.main_block.iter().all(|item| matches!(item, structures::Item::FunctionDeclaration { .. })));
+}
+
+#[test]
+fn test_constants_and_globals() {
+  use crate::{codegen::{blocks, structures}, parser};
+
+  let input = r#"
+  const GREETING = "Hi";
+  const LIMIT: number = -3;
+  global count = LIMIT;
+  global names = ["cat"];
+  global unset;
+
+  function f() {
+    count = LIMIT;
+    names.push(`${GREETING} there`);
+  }"#;
+
+  let syntax_tree = parser::Parser::new().parse_program_from_str(input).unwrap();
+  let structure_res = super::Compiler::new(syntax_tree).compile().unwrap();
+
+  let variable = |name: &str| structures::Variable { name: name.to_string() };
+  let literal = |value: &str| structures::Argument::Literal(structures::Literal { value: value.to_string() });
+  let calls = |item: &structures::Item| match item.clone() {
+    structures::Item::FunctionDeclaration { body, .. } | structures::Item::Event { body, .. } => {
+      body.into_iter().flat_map(|statement| statement.into_calls()).collect::<Vec<structures::Call>>()
+    },
+  };
+
+  // Globals are initialized in the load event, in declaration order. Constants are never emitted.
+  let structures::Item::Event { name, .. } = &structure_res.main_block[0] else {
+    panic!("Expected the load event first");
+  };
+  assert_eq!(name, blocks::WEBSITE_LOADED);
+  assert_eq!(calls(&structure_res.main_block[0]), vec![
+    super::Compiler::generate_set_variable(variable("count"), literal("-3")),
+    structures::Call::CWScriptBlockCall {
+      dependencies: vec![],
+      block_id: structures::CWScriptBlockID { id: blocks::CREATE_TABLE.to_string() },
+      arguments: vec![],
+      return_var: Some(variable("names")),
+    },
+    super::Compiler::generate_block_call(blocks::INSERT_ENTRY, vec![structures::Argument::VariableName(variable("names")), literal("cat")]),
+  ]);
+
+  // Constants are substituted, and globals initialized with tables are known to be tables
+  assert_eq!(calls(&structure_res.main_block[1]), vec![
+    super::Compiler::generate_set_variable(variable("count"), literal("-3")),
+    super::Compiler::generate_block_call(blocks::INSERT_ENTRY, vec![
+      structures::Argument::VariableName(variable("names")),
+      structures::Argument::Template(vec![
+        structures::TemplatePart::Text("Hi".to_string()),
+        structures::TemplatePart::Text(" there".to_string()),
+      ]),
+    ]),
+  ]);
+
+  for (input, message) in [
+    ("const MAX = 1; function f() { MAX = 2; }", "Cannot assign to the constant \"MAX\", only to variables"),
+    ("global count = 1; function f() { count = \"a\"; }", "Cannot assign string to \"count\" of type number"),
+    ("global count: string = 1;", "Cannot assign number to \"count\" of type string"),
+    ("function f() { let x = 1; } const MAX = f;", "The function \"f\" cannot be used as a value"),
+    ("global count = 1; const MAX = count;", "Constant \"MAX\" must be initialized with a literal"),
+    ("const MAX = MIN; const MIN = 1;", "Undefined identifier \"MIN\""),
+  ] {
+    let syntax_tree = parser::Parser::new().parse_program_from_str(input).unwrap();
+    let error = super::Compiler::new(syntax_tree).compile().unwrap_err();
+    assert!(error.to_string().contains(message), "{}: {}", input, error);
+  }
+}
+
+#[test]
+fn test_top_level_statements() {
+  use crate::{codegen::{blocks, structures}, parser};
+
+  let input = r#"
+  greet("cat");
+  global count = 1;
+  let name = "lynx";
+  count = 2;
+
+  function greet(user) {
+    let name = user;
+  }"#;
+
+  let syntax_tree = parser::Parser::new().parse_program_from_str(input).unwrap();
+  let structure_res = super::Compiler::new(syntax_tree).compile().unwrap();
+
+  let variable = |name: &str| structures::Variable { name: name.to_string() };
+  let literal = |value: &str| structures::Argument::Literal(structures::Literal { value: value.to_string() });
+
+  // Statements run in order in the load event, with global initializers in between
+  let structures::Item::Event { name, body } = structure_res.main_block[0].clone() else {
+    panic!("Expected the load event first");
+  };
+  assert_eq!(name, blocks::WEBSITE_LOADED);
+  assert_eq!(body.into_iter().flat_map(|statement| statement.into_calls()).collect::<Vec<structures::Call>>(), vec![
+    structures::Call::FunctionCall {
+      dependencies: vec![],
+      function_name: variable("greet"),
+      arguments: vec![literal("cat")],
+      return_var: None,
+    },
+    super::Compiler::generate_set_variable(variable("count"), literal("1")),
+    // Locals of the load event don't collide with those of functions
+    super::Compiler::generate_set_variable(variable(&format!("{}$name", blocks::WEBSITE_LOADED)), literal("lynx")),
+    super::Compiler::generate_set_variable(variable("count"), literal("2")),
+  ]);
+
+  // Functions are still separate cards
+  assert!(matches!(&structure_res.main_block[1], structures::Item::FunctionDeclaration { name, .. } if name == "greet"));
+
+  // Locals of the load event aren't visible in functions
+  let syntax_tree = parser::Parser::new().parse_program_from_str("let x = 1; function f() { let y = x; }").unwrap();
+  let error = super::Compiler::new(syntax_tree).compile().unwrap_err();
+  assert!(error.to_string().contains("In function \"f\": Undefined identifier \"x\""), "{}", error);
+}
+
+#[test]
+fn test_attribute_checking() {
+  use crate::parser;
+
+  for (input, message) in [
+    ("#[inlien] function f() {}", "In function \"f\": Unknown attribute \"inlien\", did you mean \"inline\"?"),
+    ("#[inline] #[inline] function f() {}", "In function \"f\": Duplicate attribute \"inline\""),
+    ("#[inline] #[export_as(\"g\")] function f() {}", "Attributes \"inline\" and \"export_as\" conflict"),
+    ("#[inline] const MAX = 1;", "In constant \"MAX\": Attribute \"inline\" can only be applied to functions"),
+    ("#[export_as] function f() {}", "Attribute \"export_as\" expects 1 arguments, found 0"),
+    ("#[export_as(g)] function f() {}", "Attribute \"export_as\" expects the exported name as a string"),
+    ("#[inline(true)] function f() {}", "Attribute \"inline\" expects 0 arguments, found 1"),
+    ("#[allow(shadowng)] function f() {}", "Unknown lint \"shadowng\", did you mean \"shadowing\"?"),
+    ("#[allow] global x;", "Attribute \"allow\" expects the names of lints"),
+    ("#[allow(shadowing)] #[deny(shadowing)] function f() {}", "Lint \"shadowing\" is set to both allow and deny"),
+    ("#[inline]\nconsole.log(1);", "In statement at 2:1: Attribute \"inline\" can only be applied to functions"),
+    ("function f() { for (x of []) { #[comment(x)] f(); } }", "Attribute \"comment\" expects the text as a string"),
+    ("#[comment(\"MAX\")] const MAX = 1;", "In constant \"MAX\": Attribute \"comment\" can only be applied to functions and statements"),
+  ] {
+    let syntax_tree = parser::Parser::new().parse_program_from_str(input).unwrap();
+    let error = super::Compiler::new(syntax_tree).compile().unwrap_err();
+    assert!(error.to_string().contains(message), "{}: {}", input, error);
+  }
+
+  // Every invalid attribute is reported
+  let syntax_tree = parser::Parser::new().parse_program_from_str("#[a] function f() {} #[b] global x;").unwrap();
+  let error = super::Compiler::new(syntax_tree).compile().unwrap_err().to_string();
+  assert_eq!(error, "In function \"f\": Unknown attribute \"a\"\nIn global \"x\": Unknown attribute \"b\"");
+}
+
+#[test]
+fn test_lints() {
+  use super::lints::{self, Level, LintLevels};
+  use crate::parser;
+
+  let lint = |input: &str, levels: LintLevels| {
+    let syntax_tree = parser::Parser::new().parse_program_from_str(input).unwrap();
+    let mut compiler = super::Compiler::new(syntax_tree).with_lint_levels(levels);
+    compiler.lint().unwrap();
+    compiler.lints().iter().map(|diagnostic| (diagnostic.lint, diagnostic.message.clone())).collect::<Vec<(&str, String)>>()
+  };
+
+  for (input, expected) in [
+    ("function f(a, _b) { let c = 1; } f(1, 2);", vec![
+      (lints::UNUSED_VARIABLES, "1:10: In function \"f\": unused parameter \"a\""),
+      (lints::UNUSED_VARIABLES, "1:21: In function \"f\": unused variable \"c\""),
+    ]),
+    ("function f() { f(); } function _g() {}", vec![
+      (lints::UNUSED_FUNCTIONS, "1:10: function \"f\" is never called"),
+    ]),
+    ("let x = 1; x = 2; console.log(x); x = 3;", vec![
+      (lints::UNUSED_ASSIGNMENTS, "1:1: value assigned to \"x\" is never read"),
+      (lints::UNUSED_ASSIGNMENTS, "1:35: value assigned to \"x\" is never read"),
+    ]),
+    ("const x = 1;\nfunction f() { let x = 2; console.log(x); } f();", vec![
+      (lints::SHADOWING, "2:20: In function \"f\": variable \"x\" shadows the constant of the same name"),
+    ]),
+    ("let x = 1; #18(x, x); #18(1, 1); #18(x, 1);", vec![
+      (lints::ALWAYS_TRUE_COMPARISONS, "1:12: comparison of \"x\" with itself is always true"),
+      (lints::ALWAYS_TRUE_COMPARISONS, "1:23: comparison of equal literals is always true"),
+    ]),
+    ("for (_n of [1]) { wait(1); #[allow(always_true_comparisons)] #18(1, 1); #18(\"a\", \"a\"); }", vec![
+      (lints::ALWAYS_TRUE_COMPARISONS, "1:73: comparison of equal literals is always true"),
+    ]),
+    // The value assigned in the loop is read on its next iteration
+    ("let total = 0; for (n of [1, 2]) { console.log(total); total = n; wait(1); }", vec![]),
+    ("for (n of [1, 2]) { console.log(n); }", vec![
+      (lints::LOOPS_WITHOUT_WAIT, "1:1: loop never waits, so CatWeb freezes until it finishes. Call wait() in it."),
+    ]),
+    ("for (n of [1, 2]) { console.log(n); #22(1); }", vec![]),
+    // Functions which wait, directly or through other functions, make loops calling them wait
+    ("function pause() { wait(1); } function step() { pause(); } for (_n of [1, 2]) { step(); }", vec![]),
+    ("function log() { console.log(1); } for (_n of [1, 2]) { log(); }", vec![
+      (lints::LOOPS_WITHOUT_WAIT, "1:36: loop never waits, so CatWeb freezes until it finishes. Call wait() in it."),
+    ]),
+    // Attributes of statements set the levels within them, including at the top level
+    ("function f() { #[allow(unused_variables)] let a = 1; let b = 2; } f();", vec![
+      (lints::UNUSED_VARIABLES, "1:54: In function \"f\": unused variable \"b\""),
+    ]),
+    ("#[allow(loops_without_wait, unused_variables)]\nfor (_n of [1, 2]) { let x = 1; }\nlet y = 2;", vec![
+      (lints::UNUSED_VARIABLES, "3:1: unused variable \"y\""),
+    ]),
+    ("let x = 1;\nconsole.log(x);\n#[allow(unused_assignments)] x = 2;", vec![]),
+  ] {
+    let expected = expected.into_iter().map(|(lint, message)| (lint, message.to_string())).collect::<Vec<(&str, String)>>();
+    assert_eq!(lint(input, LintLevels::default()), expected, "{}", input);
+  }
+
+  // Attributes override the levels of the program within their item
+  let input = "#[allow(unused_variables)] function f(a) {} #[warn(unused_variables)] function g(b) {} f(1); g(1);";
+  let mut levels = LintLevels::default();
+  levels.set("unused_variables", Level::Allow).unwrap();
+  assert_eq!(lint(input, levels), [(lints::UNUSED_VARIABLES, "1:80: In function \"g\": unused parameter \"b\"".to_string())]);
+  assert!(LintLevels::default().set("unused", Level::Deny).is_err());
+
+  // Denied lints fail the compilation
+  let syntax_tree = parser::Parser::new().parse_program_from_str("#[deny(unused_variables)] function f(a) {} f(1);").unwrap();
+  let mut compiler = super::Compiler::new(syntax_tree);
+  let error = compiler.compile().unwrap_err();
+  assert_eq!(error.to_string(), "Aborting because of 1 denied lints");
+  assert_eq!(compiler.lints()[0].to_string(), "error[unused_variables]: 1:36: In function \"f\": unused parameter \"a\"");
+
+  let syntax_tree = parser::Parser::new().parse_program_from_str("let x = 1;\n#[deny(unused_variables)] let y = x;").unwrap();
+  let mut compiler = super::Compiler::new(syntax_tree);
+  assert!(compiler.compile().is_err());
+  assert_eq!(compiler.lints()[0].to_string(), "error[unused_variables]: 2:27: unused variable \"y\"");
+}
+
+#[test]
+fn test_compiling_to_ir() {
+  use crate::{codegen::ir, parser};
+
+  for (input, expected) in [
+    ("function add(a, b) { #0(#\"\", a + b); }", r#"
+    function add(add$a, add$b) {
+      with {
+        with {
+          with {
+            #11(&__t0, add$a)
+          } #12(&__t0, add$b)
+        } #0(#"", __t0)
+      } do {}
+    }"#),
+    ("let x = 1; console.log(`x = ${x}`);", r#"
+    event 0 {
+      #11(&0$x, "1")
+      with {
+        console.log(`x = {0$x}`)
+      } do {}
+    }
+
+    function console.log(console.log$arg) {
+      with {
+        #0(#"", console.log$arg)
+      } do {}
+    }"#),
+  ] {
+    let syntax_tree = parser::Parser::new().parse_program_from_str(input).unwrap();
+    let program = super::Compiler::new(syntax_tree).compile().unwrap();
+    assert_eq!(program.clone().without_positions(), ir::parse_program(expected).unwrap(), "{}:\n{}", input, program);
+  }
+}
@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+
+use crate::parser;
+use crate::codegen::site;
+use crate::codegen::symbol_table::SymbolType;
+
+use super::ValueKind;
+use super::attributes::{self, Attribute};
+use super::resolver::Resolution;
+
+/// Static types of LynxScript values.
+///
+/// Types are optional: unannotated parameters are `Any`, and unannotated variables take the type of the value
+/// last assigned to them.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Type {
+  /// Unknown type, which is compatible with every other type
+  Any,
+  Number,
+  String,
+  Bool,
+  Table,
+  /// UI object of a linked site, with its class if known
+  Object(Option<String>),
+  /// Function, by the name it is called with
+  Function(String),
+  /// Result of calls which don't return a value
+  Void,
+}
+
+impl Type {
+  /// Resolves the name of a type annotation
+  pub fn from_name(name: &str) -> Result<Type, anyhow::Error> {
+    match name {
+      "any" => Ok(Type::Any),
+      "number" => Ok(Type::Number),
+      "string" => Ok(Type::String),
+      "bool" => Ok(Type::Bool),
+      "table" => Ok(Type::Table),
+      "object" => Ok(Type::Object(None)),
+      "void" => Ok(Type::Void),
+      class if site::KNOWN_CLASSES.contains(&class) => Ok(Type::Object(Some(class.to_string()))),
+      others => Err(anyhow::anyhow!(
+        "Unknown type \"{}\", expected any, number, string, bool, table, object, void or a UI object class", others
+      )),
+    }
+  }
+
+  /// Whether a value of type `value` can be used where this type is expected
+  pub fn accepts(&self, value: &Type) -> bool {
+    match (self, value) {
+      (Type::Any, _) | (_, Type::Any) => true,
+      // Objects of unknown class are checked by CatWeb at runtime
+      (Type::Object(None), Type::Object(_)) | (Type::Object(Some(_)), Type::Object(None)) => true,
+      (Type::Function(_), Type::Function(_)) => true,
+      (expected, value) => expected == value,
+    }
+  }
+
+  /// The kind of value the compiler lowers operations on this type for, if it has one
+  pub fn value_kind(&self) -> Option<ValueKind> {
+    match self {
+      Type::Table => Some(ValueKind::Table),
+      Type::Object(Some(class)) => Some(ValueKind::Object(class.clone())),
+      _ => None,
+    }
+  }
+}
+
+impl std::fmt::Display for Type {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Type::Any => write!(f, "any"),
+      Type::Number => write!(f, "number"),
+      Type::String => write!(f, "string"),
+      Type::Bool => write!(f, "bool"),
+      Type::Table => write!(f, "table"),
+      Type::Object(None) => write!(f, "object"),
+      Type::Object(Some(class)) => write!(f, "{}", class),
+      Type::Function(name) => write!(f, "function \"{}\"", name),
+      Type::Void => write!(f, "void"),
+    }
+  }
+}
+
+/// Type of a UI object property. Properties whose values CatWeb doesn't restrict are `Any`.
+pub fn property_type(property: &str) -> Type {
+  match property {
+    "visible" | "rich_text" => Type::Bool,
+    "text" | "placeholder" | "font" | "image" => Type::String,
+    "rotation" | "z_index" | "layout_order" | "text_size"
+    | "background_transparency" | "text_transparency" | "image_transparency" => Type::Number,
+    _ => Type::Any,
+  }
+}
+
+/// Signature of a function declared in the program or the standard library
+#[derive(Debug, PartialEq, Clone)]
+pub struct FunctionSignature {
+  /// Name of the CatWeb function, which is the `export_as` name if given
+  pub name: String,
+  pub parameters: Vec<(String, Type)>,
+  /// Declared return type. Functions can't return values yet, so calls have no value whatever it is.
+  pub return_type: Type,
+}
+
+impl FunctionSignature {
+  pub fn from_declaration(func: &parser::FunctionDeclaration, export_as: Option<&str>) -> Result<Self, anyhow::Error> {
+    let annotated_type = |annotation: &Option<String>, default: Type| annotation.as_deref().map_or(Ok(default), Type::from_name);
+    Ok(Self {
+      name: export_as.unwrap_or(&func.name).to_string(),
+      parameters: func.parameters.iter()
+        .map(|param| Ok((param.name.name.clone(), annotated_type(&param.type_annotation, Type::Any)?)))
+        .collect::<Result<Vec<(String, Type)>, anyhow::Error>>()?,
+      return_type: annotated_type(&func.return_type, Type::Void)?,
+    })
+  }
+}
+
+/// Collects the signatures of the functions declared by the items, keyed by the names they can be called with.
+///
+/// Functions can be called by their declared name, and by their `export_as` name, e.g. `console.log`. Each of those
+/// names can only be declared once.
+pub fn collect_signatures(items: &[parser::Item], functions: &mut HashMap<String, FunctionSignature>) -> Result<(), anyhow::Error> {
+  let mut declared: HashMap<String, parser::Position> = HashMap::new();
+  let mut errors = Vec::new();
+  for item in items {
+    if let parser::Item::FunctionDeclaration(func) = item {
+      let signature = Attribute::from_attributes(&func.attributes, attributes::Target::Function)
+        .and_then(|attributes| {
+          let export_as = attributes.iter().find_map(|attribute| match attribute {
+            Attribute::ExportAs(name) => Some(name.as_str()),
+            _ => None,
+          });
+          FunctionSignature::from_declaration(func, export_as)
+        })
+        .map_err(|err| anyhow::anyhow!("{}: In function \"{}\": {}", func.position, func.name, err))?;
+      let mut names = vec![func.name.clone()];
+      if signature.name != func.name {
+        names.push(signature.name.clone());
+      }
+      for name in names {
+        match declared.get(&name) {
+          Some(first) => errors.push(format!(
+            "{}: In function \"{}\": \"{}\" is already declared at {}", func.position, func.name, name, first
+          )),
+          None => {
+            declared.insert(name.clone(), func.position);
+          },
+        }
+        functions.insert(name, signature.clone());
+      }
+    }
+  }
+  match errors.is_empty() {
+    true => Ok(()),
+    false => Err(anyhow::anyhow!("{}", errors.join("\n"))),
+  }
+}
+
+/// Returns the dotted name a call target spells out if it names a function, e.g. `f` or `console.log`
+pub fn callee_path(expr: &parser::Expression, resolution: &Resolution) -> Option<String> {
+  match expr {
+    parser::Expression::Identifier(iden) => match resolution.symbol(iden).symbol_type {
+      SymbolType::Function | SymbolType::Namespace => Some(iden.name.clone()),
+      _ => None,
+    },
+    parser::Expression::Member { object, property } => callee_path(object, resolution).map(|path| format!("{}.{}", path, property)),
+    _ => None,
+  }
+}
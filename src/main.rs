@@ -1,42 +1,724 @@
-use clap::Parser as ClapParser;
+use std::io::{IsTerminal, Read};
+use std::path::{Path, PathBuf};
+
+use clap::{Parser as ClapParser, Subcommand, ValueEnum};
 
 mod codegen;
 mod parser;
 mod compiler;
+mod manifest;
+mod simulator;
+mod watch;
+
+use codegen::source_map::SourceMap;
+use compiler::lints::{self, Level};
+use manifest::Manifest;
 
-use serde::Serialize;
-use serde_json;
+/// Exit code for errors in the program, e.g. syntax errors or denied lints
+const EXIT_PROGRAM_ERROR: i32 = 1;
+/// Exit code for invalid usage, e.g. unreadable files. Clap exits with it on invalid arguments too.
+const EXIT_USAGE_ERROR: i32 = 2;
 
 #[derive(ClapParser, Debug)]
 #[command(version, about, long_about = None)]
 pub struct Args {
-  /// Input file
-  #[arg(short, long)]
-  compile: String,
+  #[command(subcommand)]
+  command: Command,
+
+  /// Only print errors
+  #[arg(short, long, global = true, conflicts_with = "verbose")]
+  quiet: bool,
+
+  /// Print compilation details, such as the number of CatWeb variables used per function and the CatWeb names of locals
+  #[arg(short, long, global = true)]
+  verbose: bool,
 
-  /// Output file
-  #[arg(short, long, default_value = None)]
-  output: Option<String>,
+  /// When to color diagnostics
+  #[arg(long, value_enum, default_value_t = ColorChoice::Auto, global = true)]
+  color: ColorChoice,
+
+  /// Allow a lint
+  #[arg(short = 'A', long = "allow", value_name = "LINT", global = true)]
+  allow: Vec<String>,
+
+  /// Report a lint as a warning
+  #[arg(short = 'W', long = "warn", value_name = "LINT", global = true)]
+  warn: Vec<String>,
+
+  /// Report a lint as an error, which fails the compilation
+  #[arg(short = 'D', long = "deny", value_name = "LINT", global = true)]
+  deny: Vec<String>,
+}
+
+/// Commands which compile take a source file, or compile every script of the project the current directory belongs to
+#[derive(Subcommand, Debug)]
+enum Command {
+  /// Compile a source file to a CatWeb JSON
+  Build {
+    /// Source file, or - to read it from stdin. Files with the .ir extension are built from the lowered program.
+    input: Option<String>,
+
+    /// Output file. The JSON is printed if it isn't given.
+    #[arg(short, long, requires = "input")]
+    output: Option<String>,
+
+    /// Rebuild whenever a source file, linked site or the manifest changes
+    #[arg(short, long)]
+    watch: bool,
+
+    /// Output to produce instead of the JSON, as KIND or KIND=PATH: ast, ir, json, json-pretty or blocks.
+    /// Outputs without a path are printed, except json which is written to the output file if there is one.
+    #[arg(long, value_name = "KIND[=PATH]", value_parser = parse_emit)]
+    emit: Vec<Emit>,
+
+    /// Write a source map next to each JSON file, which maps its cards and actions to the source they were compiled
+    /// from. `lync decompile` reads it to show where actions come from.
+    #[arg(long)]
+    source_map: bool,
+
+    /// Put a comment with the source line each statement was compiled from in front of its actions
+    #[arg(long)]
+    annotate: bool,
+  },
+  /// Check a source file for errors and lints without generating code
+  #[command(alias = "lint")]
+  Check {
+    /// Source file, or - to read it from stdin
+    input: Option<String>,
+  },
+  /// Compile a source file, and simulate the event which runs when the website loads, printing what it logs
+  Run {
+    /// Source file, or - to read it from stdin
+    input: Option<String>,
+  },
+  /// Run the tests of the project. Every `tests/*.lxs` file must log the lines of the `.out` file next to it.
+  Test,
+  /// Create a project with a manifest, a main script and a sample test
+  Init {
+    /// Directory of the project
+    #[arg(default_value = ".")]
+    directory: String,
+
+    /// Name of the project. Defaults to the name of the directory.
+    #[arg(long)]
+    name: Option<String>,
+  },
+  /// Format a source file in place, or print it formatted if it's read from stdin
+  Fmt {
+    /// Source file, or - to read it from stdin
+    input: String,
+
+    /// Don't write anything, and fail if the file isn't formatted
+    #[arg(long)]
+    check: bool,
+  },
+  /// List the cards and actions of a CatWeb JSON
+  Decompile {
+    /// CatWeb JSON file, or - to read it from stdin
+    input: String,
+
+    /// Source map of the JSON, whose source locations are shown next to the actions. Defaults to the one `lync build
+    /// --source-map` writes next to the input file, if it exists.
+    #[arg(long, value_name = "PATH")]
+    source_map: Option<PathBuf>,
+  },
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+enum ColorChoice {
+  /// Color if stderr is a terminal, and NO_COLOR isn't set
+  Auto,
+  Always,
+  Never,
+}
+
+/// Output of `lync build --emit`
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+enum EmitKind {
+  /// Syntax tree of the source file, as S-expressions
+  Ast,
+  /// Lowered program, before it's converted to CatWeb JSON
+  Ir,
+  /// CatWeb JSON
+  Json,
+  /// Indented CatWeb JSON
+  JsonPretty,
+  /// Listing of the generated cards and actions, like `lync decompile` prints
+  Blocks,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Emit {
+  kind: EmitKind,
+  /// File to write the output to, or `-` to print it
+  path: Option<PathBuf>,
+}
+
+fn parse_emit(value: &str) -> Result<Emit, String> {
+  let (kind, path) = match value.split_once('=') {
+    Some((kind, path)) => (kind, Some(PathBuf::from(path))),
+    None => (value, None),
+  };
+  let kind = EmitKind::from_str(kind, false)
+    .map_err(|_| format!("unknown kind \"{}\", expected ast, ir, json, json-pretty or blocks", kind))?;
+  Ok(Emit { kind, path })
+}
+
+/// Reason `lync` failed, which decides its exit code
+enum Failure {
+  /// Errors in the program
+  Program(anyhow::Error),
+  /// Invalid usage, e.g. an unreadable file
+  Usage(anyhow::Error),
+}
+
+/// Script to compile, given on the command line or declared by the manifest
+struct Target {
+  name: String,
+  /// Source file, or `-` for stdin
+  input: String,
+  /// Source file as named by source maps
+  source: String,
+  output: Option<PathBuf>,
+}
+
+/// What `lync build` outputs besides the JSON
+struct BuildOptions<'a> {
+  emits: &'a [Emit],
+  /// Write the source map of each JSON written to a file next to it
+  source_map: bool,
+  annotate: bool,
+}
+
+/// Settings of every compilation, from the manifest and the command line
+struct Settings {
+  lint_levels: lints::LintLevels,
+  use_stdlib: bool,
+  /// Sites linked by every script
+  links: Vec<PathBuf>,
+}
+
+/// Prints diagnostics to stderr
+struct Reporter {
+  quiet: bool,
+  color: bool,
+}
+
+impl Reporter {
+  fn new(args: &Args) -> Self {
+    let color = match args.color {
+      ColorChoice::Auto => std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none(),
+      ColorChoice::Always => true,
+      ColorChoice::Never => false,
+    };
+    Self { quiet: args.quiet, color }
+  }
+
+  fn error(&self, error: &anyhow::Error) {
+    eprintln!("{}: {}", self.paint("error"), error);
+  }
+
+  fn warning(&self, message: &str) {
+    if !self.quiet {
+      eprintln!("{}: {}", self.paint("warning"), message);
+    }
+  }
+
+  /// Prints the lints and warnings of a compilation
+  fn diagnostics(&self, compiler: &compiler::Compiler) {
+    for diagnostic in compiler.lints() {
+      if diagnostic.level == Level::Deny || !self.quiet {
+        eprintln!("{}[{}]: {}", self.paint(diagnostic.severity()), diagnostic.lint, diagnostic.message);
+      }
+    }
+    for warning in compiler.warnings() {
+      self.warning(warning);
+    }
+  }
+
+  /// Colors "error" red and "warning" yellow, if coloring is enabled
+  fn paint(&self, severity: &str) -> String {
+    let color = match severity {
+      "error" => "31",
+      _ => "33",
+    };
+    match self.color {
+      true => format!("\x1b[1;{}m{}\x1b[0m", color, severity),
+      false => severity.to_string(),
+    }
+  }
 }
 
-// FIXME: Implement proper CLI
 fn main() {
   let args = Args::parse();
-  // Read file ./test.lxs
+  let reporter = Reporter::new(&args);
+  let exit_code = match run_command(&args, &reporter) {
+    Ok(()) => return,
+    Err(Failure::Program(err)) => {
+      reporter.error(&err);
+      EXIT_PROGRAM_ERROR
+    },
+    Err(Failure::Usage(err)) => {
+      reporter.error(&err);
+      EXIT_USAGE_ERROR
+    },
+  };
+  std::process::exit(exit_code);
+}
 
-  let input = std::fs::read_to_string(&args.compile).expect("File read error");
-  let mut parser = parser::Parser::new();
-  let syntax_tree = parser.parse_program_from_str(&input).unwrap();
-  let mut compiler = compiler::Compiler::new(syntax_tree.clone());
-  let program = compiler.compile();
-  let generator = codegen::CWBlockScriptGenerator::new();
-  let script = generator.generate(program);
-  match &args.output {
-    Some(output_path) => {
-      std::fs::write(output_path, script).expect("Failed to write output file");
+fn run_command(args: &Args, reporter: &Reporter) -> Result<(), Failure> {
+  match &args.command {
+    Command::Build { input, output, watch: true, emit, source_map, annotate } => {
+      let options = BuildOptions { emits: emit, source_map: *source_map, annotate: *annotate };
+      watch(args, reporter, input.as_deref(), output.as_deref(), &options)?;
+    },
+    Command::Build { input, output, watch: false, emit, source_map, annotate } => {
+      let options = BuildOptions { emits: emit, source_map: *source_map, annotate: *annotate };
+      let project = find_project()?;
+      let settings = settings(args, project.as_ref())?;
+      let targets = targets(input.as_deref(), output.as_deref(), project.as_ref())?;
+      check_emit_paths(options.emits, &targets)?;
+      for target in targets {
+        build(args, reporter, &settings, &target, &options)?;
+      }
+    },
+    Command::Check { input } => {
+      let project = find_project()?;
+      let settings = settings(args, project.as_ref())?;
+      for target in targets(input.as_deref(), None, project.as_ref())? {
+        let (mut compiler, _) = load_compiler(&settings, &target.input)?;
+        let analyzed = compiler.lint();
+        reporter.diagnostics(&compiler);
+        analyzed.and_then(|_| compiler.check_denied_lints()).map_err(Failure::Program)?;
+      }
+    },
+    Command::Run { input } => {
+      let project = find_project()?;
+      let settings = settings(args, project.as_ref())?;
+      for target in targets(input.as_deref(), None, project.as_ref())? {
+        let (output, simulated) = simulate(args, reporter, &settings, &target.input, &target.source)?;
+        for line in output {
+          println!("{}", line);
+        }
+        simulated.map_err(Failure::Program)?;
+      }
+    },
+    Command::Test => {
+      let Some((root, manifest)) = find_project()? else {
+        return Err(Failure::Usage(anyhow::anyhow!("No {} found in the current directory or its parents", manifest::MANIFEST_NAME)));
+      };
+      let settings = settings(args, Some(&(root.clone(), manifest)))?;
+      run_tests(args, reporter, &settings, &root.join("tests"))?;
+    },
+    Command::Init { directory, name } => {
+      let directory = Path::new(directory);
+      let name = match name {
+        Some(name) => name.clone(),
+        None => std::path::absolute(directory).ok()
+          .and_then(|path| path.file_name().map(|name| name.to_string_lossy().to_string()))
+          .unwrap_or_else(|| "lynx-project".to_string()),
+      };
+      let created = manifest::init(directory, &name).map_err(Failure::Usage)?;
+      if !args.quiet {
+        for path in created {
+          eprintln!("Created {}", path.display());
+        }
+      }
+    },
+    Command::Fmt { input, check } => {
+      let source = read_input(input)?;
+      let formatted = parser::format::format_source(&source).map_err(Failure::Program)?;
+      match (input.as_str(), *check) {
+        ("-", true) if formatted != source => return Err(Failure::Program(anyhow::anyhow!("The input is not formatted"))),
+        (path, true) if formatted != source => return Err(Failure::Program(anyhow::anyhow!("\"{}\" is not formatted", path))),
+        (_, true) => {},
+        ("-", false) => print!("{}", formatted),
+        (path, false) => {
+          if write_output(Path::new(path), &formatted)? && !args.quiet {
+            eprintln!("Formatted {}", path);
+          }
+        },
+      }
     },
-    None => {
-      println!("{}", script);
+    Command::Decompile { input, source_map } => {
+      let content = read_input(input)?;
+      let codegen::json::Wrapper::Script(scripts) = serde_json::from_str(&content)
+        .map_err(|err| Failure::Program(anyhow::anyhow!("Invalid CatWeb JSON: {}", err)))?;
+      let source_map = match source_map {
+        Some(path) => Some(load_source_map(path)?),
+        None if input != "-" && SourceMap::path_for(Path::new(input)).is_file() => {
+          Some(load_source_map(&SourceMap::path_for(Path::new(input)))?)
+        },
+        None => None,
+      };
+      // Source maps are written for JSONs of one script
+      for (index, script) in scripts.iter().enumerate() {
+        print!("{}", codegen::listing::list_script(script, source_map.as_ref().filter(|_| index == 0)));
+      }
+    },
+  }
+  Ok(())
+}
+
+/// Compiles a target, and writes its JSON to its output if it changed, or prints it. Emitted outputs replace the JSON
+/// with other outputs.
+fn build(args: &Args, reporter: &Reporter, settings: &Settings, target: &Target, options: &BuildOptions) -> Result<(), Failure> {
+  let default_emits = [Emit { kind: EmitKind::Json, path: None }];
+  let emits = match options.emits.is_empty() {
+    true => &default_emits[..],
+    false => options.emits,
+  };
+  let json_paths: Vec<&PathBuf> = emits.iter()
+    .filter(|emit| matches!(emit.kind, EmitKind::Json | EmitKind::JsonPretty))
+    .filter_map(|emit| emit_path(target, emit))
+    .collect();
+  if options.source_map && json_paths.is_empty() {
+    return Err(Failure::Usage(anyhow::anyhow!("--source-map requires an output file for the JSON")));
+  }
+  let mut generator = codegen::CWBlockScriptGenerator::new();
+  let (program, stdlib_functions) = match is_ir_file(&target.input) {
+    true if emits.iter().any(|emit| emit.kind == EmitKind::Ast) => {
+      return Err(Failure::Usage(anyhow::anyhow!("\"{}\" is an IR file, which has no syntax tree", target.input)));
+    },
+    true => (load_ir(&target.input)?, Vec::new()),
+    false => {
+      let (mut compiler, source) = load_compiler(settings, &target.input)?;
+      // The syntax tree is emitted before compiling, so it's available for programs which don't compile
+      for emit in emits.iter().filter(|emit| emit.kind == EmitKind::Ast) {
+        emit_output(args, target, emit, &parser::dump::dump_program(compiler.syntax_tree()))?;
+      }
+      if emits.iter().all(|emit| emit.kind == EmitKind::Ast) {
+        return Ok(());
+      }
+      let program = compile(args, reporter, &mut compiler)?;
+      if options.annotate {
+        generator = generator.with_annotations(&source, compiler.stdlib_functions());
+      }
+      (program, compiler.stdlib_functions().to_vec())
+    },
+  };
+  let ir = program.to_string();
+  let generated = generator.generate_program(program.clone());
+  let map = SourceMap::new(&generator, &program, &generated, &target.source, &stdlib_functions);
+  let script = codegen::json::Wrapper::Script(vec![generated]);
+  for emit in emits {
+    let content = match emit.kind {
+      EmitKind::Ast => continue,
+      EmitKind::Ir => ir.clone(),
+      EmitKind::Json => serde_json::to_string(&script).unwrap(),
+      EmitKind::JsonPretty => serde_json::to_string_pretty(&script).unwrap(),
+      EmitKind::Blocks => {
+        let codegen::json::Wrapper::Script(scripts) = &script;
+        scripts.iter().map(|script| codegen::listing::list_script(script, Some(&map))).collect()
+      },
+    };
+    emit_output(args, target, emit, &content)?;
+  }
+  if options.source_map {
+    let content = serde_json::to_string_pretty(&map).unwrap();
+    for path in json_paths {
+      write_output(&SourceMap::path_for(path), &content)?;
+    }
+  }
+  Ok(())
+}
+
+/// File an output of a build is written to. JSON without a path goes to the output of the target.
+fn emit_path<'a>(target: &'a Target, emit: &'a Emit) -> Option<&'a PathBuf> {
+  match (&emit.path, emit.kind) {
+    (Some(path), _) if path.as_os_str() != "-" => Some(path),
+    (None, EmitKind::Json) => target.output.as_ref(),
+    _ => None,
+  }
+}
+
+/// Writes an output of a build to its file, or prints it
+fn emit_output(args: &Args, target: &Target, emit: &Emit, content: &str) -> Result<(), Failure> {
+  match emit_path(target, emit) {
+    Some(path) => {
+      let written = write_output(path, content)?;
+      if !args.quiet {
+        match written {
+          true => eprintln!("Built {} to {}", target.name, path.display()),
+          false => eprintln!("Built {}, {} is unchanged", target.name, path.display()),
+        }
+      }
+    },
+    None if content.ends_with('\n') => print!("{}", content),
+    None => println!("{}", content),
+  }
+  Ok(())
+}
+
+/// Outputs of several targets can't be written to the same file
+fn check_emit_paths(emits: &[Emit], targets: &[Target]) -> Result<(), Failure> {
+  let has_path = emits.iter().any(|emit| emit.path.as_ref().is_some_and(|path| path.as_os_str() != "-"));
+  match has_path && targets.len() > 1 {
+    true => Err(Failure::Usage(anyhow::anyhow!("--emit with a path requires an input file"))),
+    false => Ok(()),
+  }
+}
+
+/// Builds the targets, then rebuilds those whose files change. Errors are reported and don't stop watching, and
+/// changes of the manifest reload the project.
+fn watch(args: &Args, reporter: &Reporter, input: Option<&str>, output: Option<&str>, options: &BuildOptions) -> Result<(), Failure> {
+  if input == Some("-") {
+    return Err(Failure::Usage(anyhow::anyhow!("Cannot watch stdin, pass a source file")));
+  }
+  let clear_screen = std::io::stderr().is_terminal();
+  let mut watcher = watch::FileWatcher::default();
+  loop {
+    let project = find_project()?;
+    let manifest_path = project.as_ref().map(|(root, _)| root.join(manifest::MANIFEST_NAME));
+    let loaded = settings(args, project.as_ref())
+      .and_then(|settings| {
+        let targets = targets(input, output, project.as_ref())?;
+        check_emit_paths(options.emits, &targets)?;
+        Ok((targets, settings))
+      });
+    let (targets, settings) = match loaded {
+      Ok(loaded) => loaded,
+      Err(Failure::Program(err) | Failure::Usage(err)) if manifest_path.is_some() => {
+        // The manifest may be fixed
+        reporter.error(&err);
+        watcher.watch(manifest_path);
+        while watcher.poll().is_empty() {
+          std::thread::sleep(watch::POLL_INTERVAL);
+        }
+        continue;
+      },
+      Err(failure) => return Err(failure),
+    };
+
+    let mut dependencies = vec![Vec::new(); targets.len()];
+    let mut changed: Option<Vec<PathBuf>> = None;
+    loop {
+      if clear_screen {
+        eprint!("\x1b[2J\x1b[H");
+      }
+      for (index, target) in targets.iter().enumerate() {
+        let is_affected = changed.as_ref().is_none_or(|changed| dependencies[index].iter().any(|file| changed.contains(file)));
+        if !is_affected {
+          continue;
+        }
+        if let Err(Failure::Program(err) | Failure::Usage(err)) = build(args, reporter, &settings, target, options) {
+          reporter.error(&err);
+        }
+        dependencies[index] = source_dependencies(&settings, &target.input);
+      }
+      if !args.quiet {
+        eprintln!("Watching for changes...");
+      }
+
+      watcher.watch(dependencies.iter().flatten().cloned().chain(manifest_path.clone()));
+      let files = loop {
+        std::thread::sleep(watch::POLL_INTERVAL);
+        let files = watcher.poll();
+        if !files.is_empty() {
+          break files;
+        }
+      };
+      if manifest_path.as_ref().is_some_and(|manifest_path| files.contains(manifest_path)) {
+        break;
+      }
+      changed = Some(files);
+    }
+  }
+}
+
+/// Returns the files a compilation depends on: the source file, and the sites it links. Only the source file is known
+/// if it doesn't parse.
+fn source_dependencies(settings: &Settings, input: &str) -> Vec<PathBuf> {
+  let path = std::path::absolute(input).unwrap_or_else(|_| PathBuf::from(input));
+  let base_path = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+  let link_paths = std::fs::read_to_string(&path).ok()
+    .and_then(|content| parser::Parser::new().parse_program_from_str(&content).ok())
+    .map(|syntax_tree| syntax_tree.link_statements.into_iter().filter_map(|statement| match statement {
+      parser::Statement::Link { path, .. } => Some(base_path.join(path)),
+      _ => None,
+    }).collect::<Vec<PathBuf>>())
+    .unwrap_or_default();
+  [path].into_iter()
+    .chain(link_paths)
+    .chain(settings.links.iter().map(|link| std::path::absolute(link).unwrap_or_else(|_| link.clone())))
+    .collect()
+}
+
+/// Finds the project the current directory belongs to
+fn find_project() -> Result<Option<(PathBuf, Manifest)>, Failure> {
+  let current_directory = std::env::current_dir()
+    .map_err(|err| Failure::Usage(anyhow::anyhow!("Failed to get the current directory: {}", err)))?;
+  Manifest::find(&current_directory).map_err(Failure::Usage)
+}
+
+/// Lint levels of the command line override those of the manifest
+fn settings(args: &Args, project: Option<&(PathBuf, Manifest)>) -> Result<Settings, Failure> {
+  let mut settings = Settings { lint_levels: lints::LintLevels::default(), use_stdlib: true, links: Vec::new() };
+  if let Some((root, manifest)) = project {
+    settings.lint_levels = manifest.lint_levels().map_err(Failure::Usage)?;
+    settings.use_stdlib = manifest.stdlib.enabled;
+    settings.links = manifest.links.iter().map(|link| root.join(link)).collect();
+  }
+  for (names, level) in [(&args.allow, Level::Allow), (&args.warn, Level::Warn), (&args.deny, Level::Deny)] {
+    for name in names {
+      settings.lint_levels.set(name, level).map_err(Failure::Usage)?;
+    }
+  }
+  Ok(settings)
+}
+
+/// Returns the input file, or the scripts of the project if there is none
+fn targets(input: Option<&str>, output: Option<&str>, project: Option<&(PathBuf, Manifest)>) -> Result<Vec<Target>, Failure> {
+  if let Some(input) = input {
+    let source = match input {
+      "-" => "<stdin>".to_string(),
+      path => path.to_string(),
+    };
+    return Ok(vec![Target { name: input.to_string(), input: input.to_string(), source, output: output.map(PathBuf::from) }]);
+  }
+  let Some((root, manifest)) = project else {
+    return Err(Failure::Usage(anyhow::anyhow!(
+      "No input file given, and no {} found in the current directory or its parents", manifest::MANIFEST_NAME
+    )));
+  };
+  if manifest.scripts.is_empty() {
+    return Err(Failure::Usage(anyhow::anyhow!("The manifest of project \"{}\" declares no scripts", manifest.project.name)));
+  }
+  Ok(manifest.scripts.iter().map(|script| Target {
+    name: script.name.clone(),
+    input: root.join(&script.entry).to_string_lossy().to_string(),
+    source: script.entry.to_string_lossy().to_string(),
+    output: Some(root.join(&script.output)),
+  }).collect())
+}
+
+/// Writes an output file, unless it already has the content. Returns whether it was written.
+fn write_output(path: &Path, content: &str) -> Result<bool, Failure> {
+  if std::fs::read_to_string(path).is_ok_and(|existing| existing == content) {
+    return Ok(false);
+  }
+  if let Some(parent) = path.parent() && !parent.as_os_str().is_empty() {
+    std::fs::create_dir_all(parent)
+      .map_err(|err| Failure::Usage(anyhow::anyhow!("Failed to create \"{}\": {}", parent.display(), err)))?;
+  }
+  std::fs::write(path, content).map_err(|err| Failure::Usage(anyhow::anyhow!("Failed to write \"{}\": {}", path.display(), err)))?;
+  Ok(true)
+}
+
+/// Compiles a source file and simulates it. Returns the logged lines, and whether the simulation succeeded. Errors
+/// name the location in `source` the failing action was compiled from.
+fn simulate(args: &Args, reporter: &Reporter, settings: &Settings, input: &str, source: &str) -> Result<(Vec<String>, Result<(), anyhow::Error>), Failure> {
+  let (program, stdlib_functions) = match is_ir_file(input) {
+    true => (load_ir(input)?, Vec::new()),
+    false => {
+      let (mut compiler, _) = load_compiler(settings, input)?;
+      (compile(args, reporter, &mut compiler)?, compiler.stdlib_functions().to_vec())
+    },
+  };
+  let generator = codegen::CWBlockScriptGenerator::new();
+  let script = generator.generate_program(program.clone());
+  let source_map = SourceMap::new(&generator, &program, &script, source, &stdlib_functions);
+  let mut simulator = simulator::Simulator::new(&script).with_source_map(&source_map);
+  let simulated = simulator.run_load_event();
+  Ok((simulator.output().to_vec(), simulated))
+}
+
+/// Runs every `.lxs` file of the tests directory, comparing what it logs with its `.out` file
+fn run_tests(args: &Args, reporter: &Reporter, settings: &Settings, directory: &Path) -> Result<(), Failure> {
+  let entries = std::fs::read_dir(directory)
+    .map_err(|err| Failure::Usage(anyhow::anyhow!("Failed to read \"{}\": {}", directory.display(), err)))?;
+  let mut tests = entries.filter_map(|entry| entry.ok().map(|entry| entry.path()))
+    .filter(|path| path.extension().is_some_and(|extension| extension == "lxs"))
+    .collect::<Vec<PathBuf>>();
+  tests.sort();
+
+  let mut failed = 0;
+  for test in &tests {
+    let name = test.file_stem().unwrap_or_default().to_string_lossy();
+    let expected = std::fs::read_to_string(test.with_extension("out")).unwrap_or_default();
+    let (output, simulated) = simulate(args, reporter, settings, &test.to_string_lossy(), &test.to_string_lossy())?;
+    let actual = output.iter().map(|line| format!("{}\n", line)).collect::<String>();
+    match simulated {
+      Ok(()) if actual == expected => eprintln!("test {} ... ok", name),
+      Ok(()) => {
+        failed += 1;
+        eprintln!("test {} ... FAILED\nexpected:\n{}actual:\n{}", name, expected, actual);
+      },
+      Err(err) => {
+        failed += 1;
+        eprintln!("test {} ... FAILED\n{}", name, err);
+      },
     }
+  }
+  match failed {
+    0 => Ok(()),
+    _ => Err(Failure::Program(anyhow::anyhow!("{} of {} tests failed", failed, tests.len()))),
+  }
+}
+
+/// Reads a file, or stdin for `-`
+fn read_input(input: &str) -> Result<String, Failure> {
+  let mut content = String::new();
+  let read = match input {
+    "-" => std::io::stdin().read_to_string(&mut content).map(|_| ()),
+    path => std::fs::read_to_string(path).map(|file_content| content = file_content),
   };
-}
\ No newline at end of file
+  read.map_err(|err| Failure::Usage(anyhow::anyhow!("Failed to read \"{}\": {}", input, err)))?;
+  Ok(content)
+}
+
+fn load_source_map(path: &Path) -> Result<SourceMap, Failure> {
+  let content = std::fs::read_to_string(path)
+    .map_err(|err| Failure::Usage(anyhow::anyhow!("Failed to read \"{}\": {}", path.display(), err)))?;
+  serde_json::from_str(&content)
+    .map_err(|err| Failure::Program(anyhow::anyhow!("Invalid source map \"{}\": {}", path.display(), err)))
+}
+
+/// Files with the `.ir` extension contain a lowered program, which is used instead of compiling a source file
+fn is_ir_file(input: &str) -> bool {
+  Path::new(input).extension().is_some_and(|extension| extension == "ir")
+}
+
+fn load_ir(input: &str) -> Result<codegen::Program, Failure> {
+  codegen::ir::parse_program(&read_input(input)?)
+    .map_err(|err| Failure::Program(anyhow::anyhow!("Invalid IR \"{}\": {}", input, err)))
+}
+
+/// Parses a source file, and loads the site data it links. Returns the compiler and the source.
+fn load_compiler(settings: &Settings, input: &str) -> Result<(compiler::Compiler, String), Failure> {
+  let content = read_input(input)?;
+  let syntax_tree = parser::Parser::new().parse_program_from_str(&content).map_err(Failure::Program)?;
+  let link_paths = syntax_tree.link_statements.iter().filter_map(|statement| match statement {
+    parser::Statement::Link { path, .. } => Some(path.clone()),
+    _ => None,
+  }).collect::<Vec<String>>();
+  // Linked sites are resolved relative to the source file, or the current directory for stdin
+  let base_path = match input {
+    "-" => Path::new("."),
+    path => Path::new(path).parent().unwrap_or(Path::new(".")),
+  };
+  let mut site = codegen::site::SiteData::load_links(&link_paths, base_path).map_err(Failure::Program)?;
+  site.add_links(&settings.links, Path::new(".")).map_err(Failure::Program)?;
+  let compiler = compiler::Compiler::new(syntax_tree)
+    .with_site_data(site)
+    .with_lint_levels(settings.lint_levels.clone())
+    .with_stdlib(settings.use_stdlib);
+  Ok((compiler, content))
+}
+
+/// Compiles the program, reporting its diagnostics
+fn compile(args: &Args, reporter: &Reporter, compiler: &mut compiler::Compiler) -> Result<codegen::Program, Failure> {
+  let compiled = compiler.compile();
+  reporter.diagnostics(compiler);
+  let program = compiled.map_err(Failure::Program)?;
+  if args.verbose {
+    for report in compiler.variable_reports() {
+      eprintln!("{}: {} variables ({} temporaries)", report.item, report.variables, report.temporaries);
+    }
+    // Locals are renamed in CatWeb, so the blocks can be mapped back to the source
+    for mapping in compiler.name_mapping() {
+      eprintln!("{}: local \"{}\" is CatWeb variable \"{}\"", mapping.function, mapping.name, mapping.mangled);
+    }
+  }
+  Ok(program)
+}
@@ -0,0 +1,158 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::codegen::json;
+use crate::compiler::lints::{self, LintLevels};
+
+mod test;
+
+/// File name of the manifest, which marks the root directory of a project
+pub const MANIFEST_NAME: &str = "lynx.toml";
+
+/// Manifest of a LynxScript project, describing its scripts and how they are compiled.
+///
+/// ```toml
+/// [project]
+/// name = "my-site"
+///
+/// [[script]]
+/// name = "main"
+/// entry = "src/main.lxs"
+/// output = "out/main.json"
+/// ```
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+  pub project: Project,
+  #[serde(default, rename = "script")]
+  pub scripts: Vec<Script>,
+  #[serde(default)]
+  pub stdlib: StdlibOptions,
+  /// Levels of lints by name, e.g. `unused_variables = "deny"`
+  #[serde(default)]
+  pub lints: BTreeMap<String, String>,
+  /// Site JSONs linked by every script, in addition to their `link` statements
+  #[serde(default)]
+  pub links: Vec<PathBuf>,
+}
+
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Project {
+  pub name: String,
+  /// Version of CatWeb the scripts are generated for, which must be one the generator supports
+  pub catweb_version: Option<String>,
+}
+
+/// Script of the project, which is compiled from its entry file to its own CatWeb JSON
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Script {
+  pub name: String,
+  pub entry: PathBuf,
+  pub output: PathBuf,
+}
+
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StdlibOptions {
+  /// Whether the standard library is linked. Without it, scripts can only use raw CatWeb block calls.
+  #[serde(default = "StdlibOptions::default_enabled")]
+  pub enabled: bool,
+}
+
+impl StdlibOptions {
+  fn default_enabled() -> bool {
+    true
+  }
+}
+
+impl Default for StdlibOptions {
+  fn default() -> Self {
+    Self { enabled: true }
+  }
+}
+
+impl Manifest {
+  /// Parses and validates a manifest
+  pub fn from_str(content: &str) -> Result<Self, anyhow::Error> {
+    let manifest: Manifest = toml::from_str(content)?;
+    if let Some(version) = &manifest.project.catweb_version
+      && !json::CATWEB_VERSIONS.contains(&version.as_str())
+    {
+      return Err(anyhow::anyhow!(
+        "Unsupported catweb_version \"{}\", expected {}", version, json::CATWEB_VERSIONS.join(" or ")
+      ));
+    }
+    manifest.lint_levels()?;
+    for (index, script) in manifest.scripts.iter().enumerate() {
+      if manifest.scripts[..index].iter().any(|other| other.name == script.name) {
+        return Err(anyhow::anyhow!("Script \"{}\" is declared more than once", script.name));
+      }
+    }
+    Ok(manifest)
+  }
+
+  /// Finds the manifest in `start` or the closest of its parent directories. Returns the directory of the manifest,
+  /// which paths in the manifest are relative to, and the manifest.
+  pub fn find(start: &Path) -> Result<Option<(PathBuf, Manifest)>, anyhow::Error> {
+    for directory in start.ancestors() {
+      let path = directory.join(MANIFEST_NAME);
+      if !path.is_file() {
+        continue;
+      }
+      let content = std::fs::read_to_string(&path)
+        .map_err(|err| anyhow::anyhow!("Failed to read \"{}\": {}", path.display(), err))?;
+      let manifest = Manifest::from_str(&content)
+        .map_err(|err| anyhow::anyhow!("Invalid manifest \"{}\": {}", path.display(), err))?;
+      return Ok(Some((directory.to_path_buf(), manifest)));
+    }
+    Ok(None)
+  }
+
+  pub fn lint_levels(&self) -> Result<LintLevels, anyhow::Error> {
+    let mut levels = LintLevels::default();
+    for (name, level_name) in &self.lints {
+      let level = lints::Level::from_name(level_name)
+        .ok_or_else(|| anyhow::anyhow!("Unknown level \"{}\" of lint \"{}\", expected allow, warn or deny", level_name, name))?;
+      levels.set(name, level)?;
+    }
+    Ok(levels)
+  }
+}
+
+/// Creates a project named `name` in `directory`, with a manifest, a main script and a sample test.
+/// Returns the paths of the created files.
+pub fn init(directory: &Path, name: &str) -> Result<Vec<PathBuf>, anyhow::Error> {
+  let manifest_path = directory.join(MANIFEST_NAME);
+  if manifest_path.exists() {
+    return Err(anyhow::anyhow!("\"{}\" already exists", manifest_path.display()));
+  }
+
+  let files = [
+    (manifest_path, format!(
+      "[project]\nname = \"{}\"\n\n[[script]]\nname = \"main\"\nentry = \"src/main.lxs\"\noutput = \"out/main.json\"\n\n[lints]\n",
+      name
+    )),
+    (directory.join("src").join("main.lxs"), "function greet(name: string) {\n  console.log(`Hello, ${name}!`);\n}\n\ngreet(\"world\");\n".to_string()),
+    (directory.join("tests").join("greet.lxs"), "console.log(`1 + 2 = ${1 + 2}`);\n".to_string()),
+    (directory.join("tests").join("greet.out"), "1 + 2 = 3\n".to_string()),
+  ];
+  for (path, _) in &files[1..] {
+    if path.exists() {
+      return Err(anyhow::anyhow!("\"{}\" already exists", path.display()));
+    }
+  }
+
+  let mut created = Vec::new();
+  for (path, content) in files {
+    if let Some(parent) = path.parent() {
+      std::fs::create_dir_all(parent).map_err(|err| anyhow::anyhow!("Failed to create \"{}\": {}", parent.display(), err))?;
+    }
+    std::fs::write(&path, content).map_err(|err| anyhow::anyhow!("Failed to write \"{}\": {}", path.display(), err))?;
+    created.push(path);
+  }
+  Ok(created)
+}
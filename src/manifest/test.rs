@@ -0,0 +1,81 @@
+#[test]
+fn test_manifest_parsing() {
+  use std::path::PathBuf;
+
+  use super::{Manifest, Script};
+  use crate::compiler::lints::{self, Level};
+
+  let manifest = Manifest::from_str(r#"
+  links = ["site.json"]
+
+  [project]
+  name = "cats"
+  catweb_version = "2.15"
+
+  [[script]]
+  name = "main"
+  entry = "src/main.lxs"
+  output = "out/main.json"
+
+  [stdlib]
+  enabled = false
+
+  [lints]
+  shadowing = "allow"
+  "#).unwrap();
+  assert_eq!(manifest.project.name, "cats");
+  assert_eq!(manifest.project.catweb_version.as_deref(), Some("2.15"));
+  assert_eq!(manifest.scripts, [Script {
+    name: "main".to_string(),
+    entry: PathBuf::from("src/main.lxs"),
+    output: PathBuf::from("out/main.json"),
+  }]);
+  assert!(!manifest.stdlib.enabled);
+  assert_eq!(manifest.links, [PathBuf::from("site.json")]);
+  let levels = manifest.lint_levels().unwrap();
+  assert_eq!(levels.level(lints::SHADOWING), Level::Allow);
+  assert_eq!(levels.level(lints::UNUSED_VARIABLES), Level::Warn);
+
+  // Only the project is required
+  let manifest = Manifest::from_str("[project]\nname = \"cats\"").unwrap();
+  assert!(manifest.scripts.is_empty() && manifest.stdlib.enabled);
+
+  for (input, message) in [
+    ("[project]\nname = \"cats\"\n[lints]\nshadowing = \"loud\"", "Unknown level \"loud\" of lint \"shadowing\""),
+    ("[project]\nname = \"cats\"\n[lints]\nshadowng = \"allow\"", "Unknown lint \"shadowng\", did you mean \"shadowing\"?"),
+    ("[project]\nname = \"cats\"\nversion = 1", "unknown field `version`"),
+    ("[project]\nname = \"cats\"\ncatweb_version = \"1.0\"", "Unsupported catweb_version \"1.0\", expected 2.15"),
+    (
+      "[project]\nname = \"cats\"\n[[script]]\nname = \"a\"\nentry = \"a.lxs\"\noutput = \"a.json\"\n[[script]]\nname = \"a\"\nentry = \"b.lxs\"\noutput = \"b.json\"",
+      "Script \"a\" is declared more than once",
+    ),
+  ] {
+    let error = Manifest::from_str(input).unwrap_err().to_string();
+    assert!(error.contains(message), "{}: {}", input, error);
+  }
+}
+
+#[test]
+fn test_project_init() {
+  use super::{init, Manifest};
+
+  let directory = std::env::temp_dir().join(format!("lync-init-test-{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&directory);
+  let created = init(&directory, "cats").unwrap();
+  assert_eq!(created, [
+    directory.join("lynx.toml"),
+    directory.join("src").join("main.lxs"),
+    directory.join("tests").join("greet.lxs"),
+    directory.join("tests").join("greet.out"),
+  ]);
+
+  // The manifest is found from subdirectories
+  let (root, manifest) = Manifest::find(&directory.join("src")).unwrap().unwrap();
+  assert_eq!(root, directory);
+  assert_eq!(manifest.project.name, "cats");
+  assert_eq!(manifest.scripts[0].entry.to_str(), Some("src/main.lxs"));
+
+  let error = init(&directory, "cats").unwrap_err().to_string();
+  assert!(error.ends_with("lynx.toml\" already exists"), "{}", error);
+  std::fs::remove_dir_all(&directory).unwrap();
+}
@@ -0,0 +1,175 @@
+//! Textual dump of the syntax tree, which is what `lync build --emit=ast` prints.
+//!
+//! Nodes are written as S-expressions, e.g. `(let x (+ (integer 1) y))`, and broken over several lines when they
+//! don't fit in [MAX_WIDTH] columns. Resolved symbols aren't included, so the dump only depends on the source.
+
+use super::*;
+
+/// Width after which nodes are broken over several lines
+pub const MAX_WIDTH: usize = 100;
+
+const INDENT: &str = "  ";
+
+enum Node {
+  Atom(String),
+  List(Vec<Node>),
+}
+
+/// Dumps the program, one top-level node per line
+pub fn dump_program(program: &Program) -> String {
+  let mut output = String::new();
+  let nodes = program.link_statements.iter().map(statement)
+    .chain(program.main_block.iter().map(item));
+  for node in nodes {
+    write_node(&mut output, &node, 0);
+    output.push('\n');
+  }
+  output
+}
+
+fn atom(text: impl Into<String>) -> Node {
+  Node::Atom(text.into())
+}
+
+fn quoted(text: &str) -> Node {
+  Node::Atom(serde_json::to_string(text).unwrap())
+}
+
+fn list(head: &str, children: impl IntoIterator<Item = Node>) -> Node {
+  Node::List(std::iter::once(atom(head)).chain(children).collect())
+}
+
+fn type_annotation(type_annotation: &Option<String>, head: &str) -> Option<Node> {
+  type_annotation.as_ref().map(|name| list(head, [atom(name)]))
+}
+
+fn attribute(attribute: &Attribute) -> Node {
+  let arguments = attribute.arguments.iter().map(|argument| {
+    let value = match &argument.value {
+      AttributeValue::String(text) => quoted(text),
+      AttributeValue::Number(number) => atom(number),
+      AttributeValue::Bool(value) => atom(value.to_string()),
+      AttributeValue::Identifier(name) => atom(name),
+    };
+    match &argument.name {
+      Some(name) => list("=", [atom(name), value]),
+      None => value,
+    }
+  });
+  list("attribute", std::iter::once(atom(&attribute.name)).chain(arguments))
+}
+
+fn item(item: &Item) -> Node {
+  match item {
+    Item::ConstDeclaration(declaration) => list("const", std::iter::once(atom(&declaration.name.name))
+      .chain(declaration.attributes.iter().map(attribute))
+      .chain(type_annotation(&declaration.type_annotation, "type"))
+      .chain([expression(&declaration.value)])),
+    Item::GlobalDeclaration(declaration) => list("global", std::iter::once(atom(&declaration.name.name))
+      .chain(declaration.attributes.iter().map(attribute))
+      .chain(type_annotation(&declaration.type_annotation, "type"))
+      .chain(declaration.value.iter().map(expression))),
+    Item::FunctionDeclaration(declaration) => {
+      let parameters = declaration.parameters.iter().map(|parameter| {
+        Node::List(std::iter::once(atom(&parameter.name.name))
+          .chain(parameter.type_annotation.as_ref().map(atom))
+          .collect())
+      });
+      list("function", std::iter::once(atom(&declaration.name))
+        .chain(declaration.attributes.iter().map(attribute))
+        .chain([list("parameters", parameters)])
+        .chain(type_annotation(&declaration.return_type, "returns"))
+        .chain([list("body", declaration.body.iter().map(statement))]))
+    },
+    Item::Statement(inner) => statement(inner),
+  }
+}
+
+fn statement(statement: &Statement) -> Node {
+  match statement {
+    Statement::Expression { attributes, expr, .. } => list("expression", attributes.iter().map(attribute)
+      .chain([expression(expr)])),
+    Statement::Let { attributes, name, type_annotation: annotation, value, .. } => list("let", std::iter::once(atom(&name.name))
+      .chain(attributes.iter().map(attribute))
+      .chain(type_annotation(annotation, "type"))
+      .chain([expression(value)])),
+    Statement::Assignment { attributes, lhs, rhs, .. } => list("assign", attributes.iter().map(attribute)
+      .chain([expression(lhs), expression(rhs)])),
+    Statement::Link { path, .. } => list("link", [quoted(path)]),
+    Statement::For { attributes, variable, iterable, body, .. } => list("for", std::iter::once(atom(&variable.name))
+      .chain(attributes.iter().map(attribute))
+      .chain([expression(iterable), list("body", body.iter().map(self::statement))])),
+  }
+}
+
+fn expression(expression: &Expression) -> Node {
+  match expression {
+    Expression::Literal(literal) => match literal {
+      Literal::Integer(number) => list("integer", [atom(number)]),
+      Literal::Float(number) => list("float", [atom(number)]),
+      Literal::String(text) => list("string", [quoted(text)]),
+      Literal::RawString(text) => list("raw-string", [quoted(text)]),
+      Literal::Bool(value) => list("bool", [atom(value.to_string())]),
+    },
+    Expression::CWScriptBlockID(id) => list("block", [atom(id)]),
+    Expression::Identifier(identifier) => atom(&identifier.name),
+    Expression::BinOperation { lhs, op, rhs } => {
+      let op = match op {
+        BinOperator::Addition => "+",
+        BinOperator::Subtraction => "-",
+        BinOperator::Multiplication => "*",
+        BinOperator::Division => "/",
+        BinOperator::Power => "**",
+      };
+      list(op, [self::expression(lhs), self::expression(rhs)])
+    },
+    Expression::UnaryOperation { op, expr } => {
+      let op = match op {
+        UnaryOperator::NumeralNegation => "neg",
+        UnaryOperator::LogicalNegation => "not",
+      };
+      list(op, [self::expression(expr)])
+    },
+    Expression::Call { function, arguments } => list("call", std::iter::once(self::expression(function))
+      .chain(arguments.iter().map(self::expression))),
+    Expression::Member { object, property } => list("member", [self::expression(object), atom(property)]),
+    Expression::Index { object, index } => list("index", [self::expression(object), self::expression(index)]),
+    Expression::Template(parts) => list("template", parts.iter().map(|part| match part {
+      TemplatePart::Text(text) => quoted(text),
+      TemplatePart::Expression(inner) => self::expression(inner),
+    })),
+    Expression::Array(elements) => list("array", elements.iter().map(self::expression)),
+    Expression::Table(entries) => list("table", entries.iter()
+      .map(|(key, value)| list("entry", [quoted(key), self::expression(value)]))),
+  }
+}
+
+/// Writes a node on one line, or with its children after the leading atoms on their own lines if it's too wide
+fn write_node(output: &mut String, node: &Node, depth: usize) {
+  let flat = flat(node);
+  let children = match node {
+    Node::List(children) if depth * INDENT.len() + flat.len() > MAX_WIDTH && children.len() > 1 => children,
+    _ => {
+      output.push_str(&flat);
+      return;
+    },
+  };
+  // Leading atoms, like the name of a declaration, stay on the first line
+  let head_len = children.iter().take_while(|child| matches!(child, Node::Atom(_))).count().max(1);
+  let head: Vec<String> = children[..head_len].iter().map(self::flat).collect();
+  output.push('(');
+  output.push_str(&head.join(" "));
+  for child in &children[head_len..] {
+    output.push('\n');
+    output.push_str(&INDENT.repeat(depth + 1));
+    write_node(output, child, depth + 1);
+  }
+  output.push(')');
+}
+
+fn flat(node: &Node) -> String {
+  match node {
+    Node::Atom(text) => text.clone(),
+    Node::List(children) => format!("({})", children.iter().map(flat).collect::<Vec<_>>().join(" ")),
+  }
+}
@@ -0,0 +1,531 @@
+//! Formatter of source files, which is what `lync fmt` runs.
+//!
+//! Programs are printed from the syntax tree in a fixed style: two-space indentation, one statement per line, and
+//! arguments, parameters, elements and entries on their own lines when a list doesn't fit in [MAX_WIDTH] columns.
+//! Comments aren't part of the tree, so they're taken from the source: they stay after the statement at the end of
+//! whose line they are, and go above the declaration or statement they're before or in otherwise. Blank lines between
+//! statements are kept, with several in a row merged into one.
+
+use super::*;
+use super::dump::MAX_WIDTH;
+
+const INDENT: &str = "  ";
+
+/// Formats a program. The formatted program parses to the same tree as `source`.
+pub fn format_source(source: &str) -> Result<String, anyhow::Error> {
+  let mut parser = Parser::new();
+  let pair = parser.parse_program_pair(source)?;
+  let program = parser.parse_program(pair.clone())?;
+
+  let mut formatter = Formatter { source, comments: comments(source, &pair), next_comment: 0, output: String::new() };
+  let mut pairs = pair.into_inner();
+  let link_pairs = pairs.next().expect("Program should have a link statement list").into_inner();
+  let nodes = link_pairs.zip(&program.link_statements).map(|(pair, statement)| (pair, Node::Statement(statement)))
+    .chain(pairs.filter(is_node).zip(&program.main_block).map(|(pair, item)| match item {
+      Item::Statement(statement) => (pair, Node::Statement(statement)),
+      item => (pair, Node::Item(item)),
+    }));
+  formatter.block(nodes, source.len(), 0);
+
+  let formatted = formatter.output;
+  let reparsed = Parser::new().parse_program_from_str(&formatted).ok().map(Program::without_positions);
+  if reparsed != Some(program.without_positions()) {
+    anyhow::bail!("Formatting changed the meaning of the program, which is a bug in the formatter");
+  }
+  Ok(formatted)
+}
+
+/// Declaration or statement to format
+enum Node<'a> {
+  Item(&'a Item),
+  Statement(&'a Statement),
+}
+
+/// Whether a pair in a block is a declaration or statement, rather than a doc comment which is formatted as a comment
+fn is_node(pair: &Pair<Rule>) -> bool {
+  pair.as_rule() != Rule::DanglingDocComment
+}
+
+/// Byte range of a comment in the source
+#[derive(Clone, Copy)]
+struct Comment {
+  start: usize,
+  end: usize,
+}
+
+/// Finds the comments in the source of a program, in order
+fn comments(source: &str, program: &Pair<Rule>) -> Vec<Comment> {
+  // Strings, template text and doc comments can contain `//` and `/*`, which don't start a comment there
+  let mut skipped = program.clone().into_inner().flatten()
+    .filter(|pair| matches!(pair.as_rule(), Rule::string_literal | Rule::raw_string_literal | Rule::template_text | Rule::DocComment))
+    .map(|pair| (pair.as_span().start(), pair.as_span().end()))
+    .peekable();
+  let bytes = source.as_bytes();
+  let mut comments = Vec::new();
+  let mut index = 0;
+  while index < bytes.len() {
+    if let Some(&(start, end)) = skipped.peek() && start <= index {
+      index = index.max(end);
+      skipped.next();
+      continue;
+    }
+    let end = if bytes[index..].starts_with(b"//") {
+      let line_end = source[index..].find('\n').map_or(source.len(), |offset| index + offset);
+      index + source[index..line_end].trim_end().len()
+    } else if bytes[index..].starts_with(b"/*") {
+      block_comment_end(bytes, index)
+    } else {
+      index += 1;
+      continue;
+    };
+    comments.push(Comment { start: index, end });
+    index = end;
+  }
+  comments
+}
+
+/// End of the block comment at `start`, after the `*/` closing it and the ones nested in it
+fn block_comment_end(bytes: &[u8], start: usize) -> usize {
+  let mut depth = 0;
+  let mut index = start;
+  while index < bytes.len() {
+    if bytes[index..].starts_with(b"/*") {
+      depth += 1;
+      index += 2;
+    } else if bytes[index..].starts_with(b"*/") {
+      depth -= 1;
+      index += 2;
+      if depth == 0 {
+        return index;
+      }
+    } else {
+      index += 1;
+    }
+  }
+  bytes.len()
+}
+
+struct Formatter<'a> {
+  source: &'a str,
+  comments: Vec<Comment>,
+  /// Index of the first comment which isn't written yet
+  next_comment: usize,
+  output: String,
+}
+
+impl<'a> Formatter<'a> {
+  /// Writes declarations or statements, one per line, and the comments before `end` which are left
+  fn block<'n>(&mut self, nodes: impl Iterator<Item = (Pair<'a, Rule>, Node<'n>)>, end: usize, depth: usize) {
+    let mut previous_end = None;
+    for (pair, node) in nodes {
+      let span = pair.as_span();
+      while let Some(comment) = self.take_comment_before(span.start()) {
+        self.separate(previous_end, comment.start);
+        self.comment_line(comment, depth);
+        previous_end = Some(comment.end);
+      }
+      self.separate(previous_end, span.start());
+      // Comments in the node go above it, except those in its body
+      let body = pair.clone().into_inner().last().filter(|pair| pair.as_rule() == Rule::Block);
+      let header_end = body.as_ref().map_or(span.end(), |body| body.as_span().start());
+      while let Some(comment) = self.take_comment_before(header_end) {
+        self.comment_line(comment, depth);
+      }
+
+      match node {
+        Node::Item(item) => self.item(item, body, depth),
+        Node::Statement(statement) => self.statement(statement, body, depth),
+      }
+      // Comments at the end of the line of the node stay there
+      let mut node_end = span.end();
+      while let Some(&comment) = self.comments.get(self.next_comment)
+        && self.source[node_end..comment.start].trim_matches([' ', '\t']).is_empty() {
+        self.output.push(' ');
+        self.output.push_str(&self.source[comment.start..comment.end]);
+        self.next_comment += 1;
+        node_end = comment.end;
+      }
+      self.output.push('\n');
+      previous_end = Some(node_end);
+    }
+
+    while let Some(comment) = self.take_comment_before(end) {
+      self.separate(previous_end, comment.start);
+      self.comment_line(comment, depth);
+      previous_end = Some(comment.end);
+    }
+  }
+
+  fn take_comment_before(&mut self, offset: usize) -> Option<Comment> {
+    let comment = self.comments.get(self.next_comment).filter(|comment| comment.start < offset).copied()?;
+    self.next_comment += 1;
+    Some(comment)
+  }
+
+  /// Writes a blank line if there's one between the previous node or comment and the next one
+  fn separate(&mut self, previous_end: Option<usize>, start: usize) {
+    if let Some(previous_end) = previous_end && self.source[previous_end..start].matches('\n').count() > 1 {
+      self.output.push('\n');
+    }
+  }
+
+  fn comment_line(&mut self, comment: Comment, depth: usize) {
+    self.output.push_str(&INDENT.repeat(depth));
+    self.output.push_str(&self.source[comment.start..comment.end]);
+    self.output.push('\n');
+  }
+
+  /// Writes the attributes of a node, one per line
+  fn attributes(&mut self, attributes: &[Attribute], depth: usize) {
+    for attribute in attributes {
+      self.output.push_str(&INDENT.repeat(depth));
+      self.output.push_str(&self::attribute(attribute));
+      self.output.push('\n');
+    }
+  }
+
+  /// Writes a block of statements, without the newline after its closing brace
+  fn body(&mut self, pair: Option<Pair<'a, Rule>>, statements: &[Statement], depth: usize) {
+    let pair = pair.expect("Node with a body should have a block");
+    let end = pair.as_span().end() - 1;
+    if statements.is_empty() && self.comments.get(self.next_comment).is_none_or(|comment| comment.start > end) {
+      self.output.push_str("{}");
+      return;
+    }
+    self.output.push_str("{\n");
+    let nodes = pair.into_inner().filter(is_node).zip(statements).map(|(pair, statement)| (pair, Node::Statement(statement)));
+    self.block(nodes, end, depth + 1);
+    self.output.push_str(&INDENT.repeat(depth));
+    self.output.push('}');
+  }
+
+  fn item(&mut self, item: &Item, body: Option<Pair<'a, Rule>>, depth: usize) {
+    let indent = INDENT.repeat(depth);
+    match item {
+      Item::ConstDeclaration(declaration) => {
+        self.attributes(&declaration.attributes, depth);
+        let head = format!("{}const {}{} = ", indent, declaration.name.name, type_annotation(&declaration.type_annotation));
+        let value = expression(&declaration.value, depth, width(&head), 1);
+        self.output.push_str(&format!("{}{};", head, value));
+      },
+      Item::GlobalDeclaration(declaration) => {
+        self.attributes(&declaration.attributes, depth);
+        let head = format!("{}global {}{}", indent, declaration.name.name, type_annotation(&declaration.type_annotation));
+        let line = match &declaration.value {
+          Some(value) => format!("{} = {};", head, expression(value, depth, width(&head) + 3, 1)),
+          None => format!("{};", head),
+        };
+        self.output.push_str(&line);
+      },
+      Item::FunctionDeclaration(declaration) => {
+        self.attributes(&declaration.attributes, depth);
+        let head = format!("{}function {}", indent, declaration.name);
+        let return_type = type_annotation(&declaration.return_type);
+        let parameters: Vec<String> = declaration.parameters.iter()
+          .map(|parameter| format!("{}{}", parameter.name.name, type_annotation(&parameter.type_annotation)))
+          .collect();
+        let flat = format!("({})", parameters.join(", "));
+        // The parameters are followed by the return type and ` {`
+        let parameters = match width(&head) + width(&flat) + width(&return_type) + 2 <= MAX_WIDTH {
+          true => flat,
+          false => wrapped_list("(", &parameters, ")", depth, |parameter, _, _| parameter.clone()),
+        };
+        self.output.push_str(&format!("{}{}{} ", head, parameters, return_type));
+        self.body(body, &declaration.body, depth);
+      },
+      Item::Statement(statement) => self.statement(statement, body, depth),
+    }
+  }
+
+  fn statement(&mut self, statement: &Statement, body: Option<Pair<'a, Rule>>, depth: usize) {
+    self.attributes(statement.attributes(), depth);
+    let indent = INDENT.repeat(depth);
+    let line = match statement {
+      Statement::Expression { expr, .. } => format!("{}{};", indent, expression(expr, depth, indent.len(), 1)),
+      Statement::Let { name, type_annotation: annotation, value, .. } => {
+        let head = format!("{}let {}{} = ", indent, name.name, type_annotation(annotation));
+        format!("{}{};", head, expression(value, depth, width(&head), 1))
+      },
+      Statement::Assignment { lhs, rhs, .. } => {
+        let head = format!("{}{} = ", indent, expression(lhs, depth, indent.len(), 2));
+        format!("{}{};", head, expression(rhs, depth, end_column(0, &head), 1))
+      },
+      Statement::Link { path, .. } => format!("{}link {};", indent, quoted(path)),
+      Statement::For { variable, iterable, body: statements, .. } => {
+        let head = format!("{}for ({} of ", indent, variable.name);
+        // The iterable is followed by `) {`
+        let line = format!("{}{}) ", head, expression(iterable, depth, end_column(0, &head), 3));
+        self.output.push_str(&line);
+        self.body(body, statements, depth);
+        return;
+      },
+    };
+    self.output.push_str(&line);
+  }
+}
+
+fn attribute(attribute: &Attribute) -> String {
+  // Doc attributes are written as the doc comments they usually come from
+  if attribute.name == "doc"
+    && let [AttributeArgument { name: None, value: AttributeValue::String(text) }] = attribute.arguments.as_slice()
+    && !text.contains(['\n', '\r']) {
+    return match text.is_empty() {
+      true => "///".to_string(),
+      false => format!("/// {}", text),
+    };
+  }
+  if attribute.arguments.is_empty() {
+    return format!("#[{}]", attribute.name);
+  }
+  let arguments = attribute.arguments.iter().map(|argument| {
+    let value = match &argument.value {
+      AttributeValue::String(text) => quoted(text),
+      AttributeValue::Number(number) | AttributeValue::Identifier(number) => number.clone(),
+      AttributeValue::Bool(value) => value.to_string(),
+    };
+    match &argument.name {
+      Some(name) => format!("{} = {}", name, value),
+      None => value,
+    }
+  }).collect::<Vec<String>>();
+  format!("#[{}({})]", attribute.name, arguments.join(", "))
+}
+
+fn type_annotation(type_annotation: &Option<String>) -> String {
+  type_annotation.as_ref().map_or(String::new(), |name| format!(": {}", name))
+}
+
+/// Number of columns of a single line
+fn width(text: &str) -> usize {
+  text.chars().count()
+}
+
+/// Column after a text written at `column`
+fn end_column(column: usize, text: &str) -> usize {
+  match text.rsplit_once('\n') {
+    Some((_, last_line)) => width(last_line),
+    None => column + width(text),
+  }
+}
+
+/// Writes an expression starting at `column` on one line, or with the lists in it broken over several lines if it
+/// doesn't fit with the `suffix` columns after it
+fn expression(expr: &Expression, depth: usize, column: usize, suffix: usize) -> String {
+  let flat = flat(expr);
+  let fits = match flat.split_once('\n') {
+    // Multi-line templates only need their first line to fit
+    Some((first_line, _)) => column + width(first_line) <= MAX_WIDTH,
+    None => column + width(&flat) + suffix <= MAX_WIDTH,
+  };
+  if fits {
+    return flat;
+  }
+  match expr {
+    Expression::BinOperation { lhs, op, rhs } => {
+      let (lhs_parentheses, rhs_parentheses) = operand_parentheses(op, lhs, rhs);
+      let lhs = operand(lhs, lhs_parentheses, depth, column, 0);
+      let head = format!("{} {} ", lhs, bin_operator(op));
+      let rhs = operand(rhs, rhs_parentheses, depth, end_column(column, &head), suffix);
+      format!("{}{}", head, rhs)
+    },
+    Expression::UnaryOperation { op, expr } => {
+      let operand = operand(expr, precedence(expr) < UNARY_PRECEDENCE, depth, column + 1, suffix);
+      format!("{}{}", unary_operator(op), operand)
+    },
+    Expression::Call { function, arguments } => {
+      let function = operand(function, precedence(function) < POSTFIX_PRECEDENCE, depth, column, 1);
+      let arguments = wrapped_list("(", arguments, ")", depth, |argument, depth, column| expression(argument, depth, column, 1));
+      format!("{}{}", function, arguments)
+    },
+    Expression::Member { object, property } => {
+      let object = operand(object, precedence(object) < POSTFIX_PRECEDENCE, depth, column, property.len() + 1 + suffix);
+      format!("{}.{}", object, property)
+    },
+    Expression::Index { object, index } => {
+      let object = operand(object, precedence(object) < POSTFIX_PRECEDENCE, depth, column, 1);
+      let index = expression(index, depth, end_column(column, &object) + 1, suffix + 1);
+      format!("{}[{}]", object, index)
+    },
+    Expression::Array(elements) => wrapped_list("[", elements, "]", depth, |element, depth, column| expression(element, depth, column, 1)),
+    Expression::Table(entries) => wrapped_list("{", entries, "}", depth, |(key, value), depth, column| {
+      let key = table_key(key);
+      let value = expression(value, depth, column + width(&key) + 2, 1);
+      format!("{}: {}", key, value)
+    }),
+    _ => flat,
+  }
+}
+
+/// `open`, then each item on its own line with a trailing comma, then `close` on a line of its own. Items are written
+/// by `write` with their depth and column.
+fn wrapped_list<T>(open: &str, items: &[T], close: &str, depth: usize, write: impl Fn(&T, usize, usize) -> String) -> String {
+  if items.is_empty() {
+    return format!("{}{}", open, close);
+  }
+  let indent = INDENT.repeat(depth + 1);
+  let mut output = open.to_string();
+  for item in items {
+    output.push('\n');
+    output.push_str(&indent);
+    output.push_str(&write(item, depth + 1, indent.len()));
+    output.push(',');
+  }
+  output.push('\n');
+  output.push_str(&INDENT.repeat(depth));
+  output.push_str(close);
+  output
+}
+
+/// Operand of an operator or postfix expression, in parentheses if it would parse differently without them
+fn operand(expr: &Expression, parentheses: bool, depth: usize, column: usize, suffix: usize) -> String {
+  match parentheses {
+    true => format!("({})", expression(expr, depth, column + 1, suffix + 1)),
+    false => expression(expr, depth, column, suffix),
+  }
+}
+
+fn flat_operand(expr: &Expression, parentheses: bool) -> String {
+  match parentheses {
+    true => format!("({})", flat(expr)),
+    false => flat(expr),
+  }
+}
+
+const UNARY_PRECEDENCE: u8 = 4;
+const POSTFIX_PRECEDENCE: u8 = 5;
+
+/// Precedence of the operator of an expression, as the parser defines it
+fn precedence(expr: &Expression) -> u8 {
+  match expr {
+    Expression::BinOperation { op, .. } => bin_precedence(op),
+    Expression::UnaryOperation { .. } => UNARY_PRECEDENCE,
+    _ => POSTFIX_PRECEDENCE,
+  }
+}
+
+fn bin_precedence(op: &BinOperator) -> u8 {
+  match op {
+    BinOperator::Addition | BinOperator::Subtraction => 1,
+    BinOperator::Multiplication | BinOperator::Division => 2,
+    BinOperator::Power => 3,
+  }
+}
+
+/// Whether the operands of a binary operation need parentheses. `**` is right-associative, the others left.
+fn operand_parentheses(op: &BinOperator, lhs: &Expression, rhs: &Expression) -> (bool, bool) {
+  let precedence = bin_precedence(op);
+  let right_associative = *op == BinOperator::Power;
+  (
+    self::precedence(lhs) < precedence || self::precedence(lhs) == precedence && right_associative,
+    self::precedence(rhs) < precedence || self::precedence(rhs) == precedence && !right_associative,
+  )
+}
+
+/// Symbol of a binary operator, e.g. `+`
+pub fn bin_operator(op: &BinOperator) -> &'static str {
+  match op {
+    BinOperator::Addition => "+",
+    BinOperator::Subtraction => "-",
+    BinOperator::Multiplication => "*",
+    BinOperator::Division => "/",
+    BinOperator::Power => "**",
+  }
+}
+
+fn unary_operator(op: &UnaryOperator) -> &'static str {
+  match op {
+    UnaryOperator::NumeralNegation => "-",
+    UnaryOperator::LogicalNegation => "!",
+  }
+}
+
+/// Source of an expression as the formatter writes it on one line, e.g. to show it in diagnostics
+pub fn expression_source(expr: &Expression) -> String {
+  flat(expr)
+}
+
+/// Writes an expression on one line, except for the line breaks in templates
+fn flat(expr: &Expression) -> String {
+  match expr {
+    Expression::Literal(literal) => match literal {
+      Literal::Integer(number) | Literal::Float(number) => number.clone(),
+      Literal::String(text) => quoted(text),
+      Literal::RawString(text) => format!("#\"{}\"", text),
+      Literal::Bool(value) => value.to_string(),
+    },
+    Expression::CWScriptBlockID(id) => format!("#{}", id),
+    Expression::Identifier(identifier) => identifier.name.clone(),
+    Expression::BinOperation { lhs, op, rhs } => {
+      let (lhs_parentheses, rhs_parentheses) = operand_parentheses(op, lhs, rhs);
+      format!("{} {} {}", flat_operand(lhs, lhs_parentheses), bin_operator(op), flat_operand(rhs, rhs_parentheses))
+    },
+    Expression::UnaryOperation { op, expr } => {
+      format!("{}{}", unary_operator(op), flat_operand(expr, precedence(expr) < UNARY_PRECEDENCE))
+    },
+    Expression::Call { function, arguments } => format!("{}({})",
+      flat_operand(function, precedence(function) < POSTFIX_PRECEDENCE),
+      arguments.iter().map(flat).collect::<Vec<String>>().join(", ")),
+    Expression::Member { object, property } => {
+      format!("{}.{}", flat_operand(object, precedence(object) < POSTFIX_PRECEDENCE), property)
+    },
+    Expression::Index { object, index } => {
+      format!("{}[{}]", flat_operand(object, precedence(object) < POSTFIX_PRECEDENCE), flat(index))
+    },
+    Expression::Template(parts) => {
+      let parts = parts.iter().map(|part| match part {
+        TemplatePart::Text(text) => template_text(text),
+        TemplatePart::Expression(inner) => format!("${{{}}}", flat(inner)),
+      });
+      format!("`{}`", parts.collect::<String>())
+    },
+    Expression::Array(elements) => format!("[{}]", elements.iter().map(flat).collect::<Vec<String>>().join(", ")),
+    Expression::Table(entries) if entries.is_empty() => "{}".to_string(),
+    Expression::Table(entries) => {
+      let entries = entries.iter().map(|(key, value)| format!("{}: {}", table_key(key), flat(value)));
+      format!("{{ {} }}", entries.collect::<Vec<String>>().join(", "))
+    },
+  }
+}
+
+/// Quotes a string with double quotes, escaping the characters which can't appear in it as they are
+fn quoted(text: &str) -> String {
+  let mut quoted = String::from('"');
+  for char in text.chars() {
+    match char {
+      '"' => quoted.push_str("\\\""),
+      '\\' => quoted.push_str("\\\\"),
+      '\n' => quoted.push_str("\\n"),
+      '\t' => quoted.push_str("\\t"),
+      char if char.is_control() => quoted.push_str(&format!("\\u{{{:x}}}", char as u32)),
+      char => quoted.push(char),
+    }
+  }
+  quoted.push('"');
+  quoted
+}
+
+/// Escapes the text of a template. Line breaks are kept.
+fn template_text(text: &str) -> String {
+  let mut escaped = String::new();
+  let mut chars = text.chars().peekable();
+  while let Some(char) = chars.next() {
+    match char {
+      '`' | '\\' => escaped.push('\\'),
+      '$' if chars.peek() == Some(&'{') => escaped.push('\\'),
+      _ => {},
+    }
+    escaped.push(char);
+  }
+  escaped
+}
+
+/// Keys which are identifiers aren't quoted
+fn table_key(key: &str) -> String {
+  let mut chars = key.chars();
+  let is_identifier = chars.next().is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+    && chars.all(|char| char.is_ascii_alphanumeric() || char == '_');
+  match is_identifier {
+    true => key.to_string(),
+    false => quoted(key),
+  }
+}
@@ -1,8 +1,10 @@
-use anyhow::{self, Error};
-
 use pest::{Parser as _Parser, Span, iterators::Pairs, iterators::Pair, pratt_parser::PrattParser};
 use pest_derive::Parser as _Parser;
 
+use crate::codegen::symbol_table::SymbolId;
+
+pub mod dump;
+pub mod format;
 mod test;
 
 // TODO: Carry source code span in the AST for better error reporting in the future.
@@ -13,40 +15,219 @@ pub struct Program {
   pub main_block: Vec<Item>,
 }
 
+impl Program {
+  /// Returns the program with the positions of its nodes unknown, to compare programs regardless of how their source
+  /// is laid out
+  pub fn without_positions(mut self) -> Program {
+    self.link_statements.iter_mut().for_each(Statement::clear_positions);
+    for item in &mut self.main_block {
+      match item {
+        Item::ConstDeclaration(constant) => {
+          constant.position = Position::default();
+          constant.name.position = Position::default();
+          constant.value.clear_positions();
+        },
+        Item::GlobalDeclaration(global) => {
+          global.position = Position::default();
+          global.name.position = Position::default();
+          global.value.iter_mut().for_each(Expression::clear_positions);
+        },
+        Item::FunctionDeclaration(func) => {
+          func.position = Position::default();
+          func.parameters.iter_mut().for_each(|param| param.name.position = Position::default());
+          func.body.iter_mut().for_each(Statement::clear_positions);
+        },
+        Item::Statement(statement) => statement.clear_positions(),
+      }
+    }
+    self
+  }
+}
+
 #[derive(Debug, PartialEq, Clone)]
-/// Enum currently only has function declaration. Might contain more item types in the future.
+/// Top-level part of a program
 pub enum Item {
-  Attribute(Attribute),
+  ConstDeclaration(ConstDeclaration),
+  GlobalDeclaration(GlobalDeclaration),
   FunctionDeclaration(FunctionDeclaration),
+  /// Statement outside of functions, which runs when the website loads
+  Statement(Statement),
 }
 
+/// `#[name(arguments)]`, which belongs to the item or statement after it. The compiler checks which attributes exist.
+///
+/// Doc comments are `doc` attributes, e.g. `/// Greets the user` is `#[doc("Greets the user")]`.
 #[derive(Debug, PartialEq, Clone)]
-pub enum Attribute {
-  Inline,
-  ExportAs(String),
+pub struct Attribute {
+  pub name: String,
+  pub arguments: Vec<AttributeArgument>,
+}
+
+/// Argument of an attribute, which is named in `name = value`
+#[derive(Debug, PartialEq, Clone)]
+pub struct AttributeArgument {
+  pub name: Option<String>,
+  pub value: AttributeValue,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum AttributeValue {
+  String(String),
+  Number(String),
+  Bool(bool),
+  Identifier(String),
+}
+
+/// `const NAME: type = literal;`
+#[derive(Debug, PartialEq, Clone)]
+pub struct ConstDeclaration {
+  pub attributes: Vec<Attribute>,
+  /// Position of the name
+  pub position: Position,
+  pub name: Identifier,
+  pub type_annotation: Option<String>,
+  pub value: Expression,
+}
+
+/// `global name: type = value;`
+#[derive(Debug, PartialEq, Clone)]
+pub struct GlobalDeclaration {
+  pub attributes: Vec<Attribute>,
+  /// Position of the name
+  pub position: Position,
+  pub name: Identifier,
+  pub type_annotation: Option<String>,
+  /// Initial value, assigned when the website loads
+  pub value: Option<Expression>,
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct FunctionDeclaration {
+  pub attributes: Vec<Attribute>,
+  /// Position of the name
+  pub position: Position,
   pub name: String,
-  pub parameters: Vec<Expression>,
+  pub parameters: Vec<Parameter>,
+  /// Name of the annotated return type, if any
+  pub return_type: Option<String>,
   pub body: Vec<Statement>,
 }
 
+#[derive(Debug, PartialEq, Clone)]
+pub struct Parameter {
+  pub name: Identifier,
+  /// Name of the annotated type, if any
+  pub type_annotation: Option<String>,
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Statement {
   Expression {
+    attributes: Vec<Attribute>,
     expr: Expression,
+    position: Position,
+  },
+  /// `let name: type = value;`
+  Let {
+    attributes: Vec<Attribute>,
+    name: Identifier,
+    type_annotation: Option<String>,
+    value: Expression,
+    position: Position,
   },
   Assignment {
+    attributes: Vec<Attribute>,
     lhs: Expression,
     rhs: Expression,
+    position: Position,
   },
   Link { 
-    path: String 
+    path: String,
+    position: Position,
+  },
+  /// `for (variable of iterable) { ... }`
+  For {
+    attributes: Vec<Attribute>,
+    variable: Identifier,
+    iterable: Expression,
+    body: Vec<Statement>,
+    position: Position,
   },
 }
 
+impl Statement {
+  pub fn position(&self) -> Position {
+    match self {
+      Statement::Expression { position, .. }
+      | Statement::Let { position, .. }
+      | Statement::Assignment { position, .. }
+      | Statement::Link { position, .. }
+      | Statement::For { position, .. } => *position,
+    }
+  }
+
+  pub fn attributes(&self) -> &[Attribute] {
+    match self {
+      Statement::Expression { attributes, .. }
+      | Statement::Let { attributes, .. }
+      | Statement::Assignment { attributes, .. }
+      | Statement::For { attributes, .. } => attributes,
+      Statement::Link { .. } => &[],
+    }
+  }
+
+  fn clear_positions(&mut self) {
+    match self {
+      Statement::Expression { position, expr, .. } => {
+        *position = Position::default();
+        expr.clear_positions();
+      },
+      Statement::Let { position, name, value, .. } => {
+        *position = Position::default();
+        name.position = Position::default();
+        value.clear_positions();
+      },
+      Statement::Assignment { position, lhs, rhs, .. } => {
+        *position = Position::default();
+        lhs.clear_positions();
+        rhs.clear_positions();
+      },
+      Statement::Link { position, .. } => *position = Position::default(),
+      Statement::For { position, variable, iterable, body, .. } => {
+        *position = Position::default();
+        variable.position = Position::default();
+        iterable.clear_positions();
+        body.iter_mut().for_each(Statement::clear_positions);
+      },
+    }
+  }
+}
+
+/// Line and column in the source a node starts at, counted from 1. The default position is unknown, e.g. for nodes
+/// created by the compiler.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Position {
+  pub line: usize,
+  pub column: usize,
+}
+
+impl Position {
+  fn of(span: Span) -> Self {
+    let (line, column) = span.start_pos().line_col();
+    Self { line, column }
+  }
+
+  pub fn is_known(&self) -> bool {
+    self.line > 0
+  }
+}
+
+impl std::fmt::Display for Position {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    write!(f, "{}:{}", self.line, self.column)
+  }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Literal {
   Integer(String),
@@ -61,7 +242,7 @@ pub enum Expression {
   // TODO: Implement other expression types
   Literal(Literal),
   CWScriptBlockID(String),
-  Identifier(String),
+  Identifier(Identifier),
   BinOperation {
     lhs: Box<Expression>,
     op: BinOperator,
@@ -76,6 +257,78 @@ pub enum Expression {
     function: Box<Expression>,
     arguments: Vec<Expression>,
   },
+  /// `object.property`
+  Member {
+    object: Box<Expression>,
+    property: String,
+  },
+  /// `object[index]`
+  Index {
+    object: Box<Expression>,
+    index: Box<Expression>,
+  },
+  /// Template literal with at least one embedded expression. Those without any are parsed as `Literal::String`.
+  Template(Vec<TemplatePart>),
+  /// `[a, b, c]`
+  Array(Vec<Expression>),
+  /// `{ key: value }`, with entries in source order
+  Table(Vec<(String, Expression)>),
+}
+
+impl Expression {
+  fn clear_positions(&mut self) {
+    match self {
+      Expression::Literal(_) | Expression::CWScriptBlockID(_) => {},
+      Expression::Identifier(iden) => iden.position = Position::default(),
+      Expression::BinOperation { lhs, rhs, .. } => {
+        lhs.clear_positions();
+        rhs.clear_positions();
+      },
+      Expression::UnaryOperation { expr, .. } => expr.clear_positions(),
+      Expression::Call { function, arguments } => {
+        function.clear_positions();
+        arguments.iter_mut().for_each(Expression::clear_positions);
+      },
+      Expression::Member { object, .. } => object.clear_positions(),
+      Expression::Index { object, index } => {
+        object.clear_positions();
+        index.clear_positions();
+      },
+      Expression::Template(parts) => parts.iter_mut().for_each(|part| match part {
+        TemplatePart::Text(_) => {},
+        TemplatePart::Expression(expr) => expr.clear_positions(),
+      }),
+      Expression::Array(elements) => elements.iter_mut().for_each(Expression::clear_positions),
+      Expression::Table(entries) => entries.iter_mut().for_each(|(_, value)| value.clear_positions()),
+    }
+  }
+}
+
+/// Name of a variable, function or UI object, and the symbol it refers to.
+///
+/// Symbols are assigned by the compiler's name resolution, so later passes don't need to look names up again.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Identifier {
+  pub name: String,
+  pub symbol: Option<SymbolId>,
+  pub position: Position,
+}
+
+impl Identifier {
+  /// Creates an identifier which isn't resolved yet, at an unknown position
+  pub fn new(name: &str) -> Self {
+    Self { name: name.to_string(), symbol: None, position: Position::default() }
+  }
+
+  fn of(pair: Pair<Rule>) -> Self {
+    Self { position: Position::of(pair.as_span()), ..Self::new(pair.as_str()) }
+  }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum TemplatePart {
+  Text(String),
+  Expression(Expression),
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -85,15 +338,12 @@ pub enum BinOperator {
   Multiplication,
   Division,
   Power,
-  Dot,
-  Comma
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum UnaryOperator {
   NumeralNegation,
   LogicalNegation,
-  CallExpression
 }
 
 // Defines the associativity and precedence of operators
@@ -105,18 +355,24 @@ lazy_static::lazy_static! {
     // Precedence is defined lowest to highest
     PrattParser::new()
       // Addition and subtract have equal precedence
-      .op(Op::infix(Comma, Left))
-      .op(Op::infix(Dot, Left))
       .op(Op::infix(Addition, Left) | Op::infix(Subtraction, Left))
       .op(Op::infix(Multiplication, Left) | Op::infix(Division, Left))
-      .op(Op::infix(Power, Left))
+      .op(Op::infix(Power, Right))
 
       .op(Op::prefix(NumeralNegation) | Op::prefix(LogicalNegation))
 
-      .op(Op::postfix(CallExpression))
+      // Calls, member accesses and indexing bind tightest, e.g. `-a.b(c)[d]` is `-(((a.b)(c))[d])`
+      .op(Op::postfix(CallExpression) | Op::postfix(MemberExpression) | Op::postfix(IndexExpression))
   };
 }
 
+/// Deepest nesting of brackets and block comments the parser accepts, which keeps it from overflowing the stack
+const MAX_NESTING: usize = 256;
+
+/// Deepest expression the parser accepts, counting its operations and brackets. Passes after parsing visit
+/// expressions recursively, so deeper ones would overflow the stack.
+const MAX_EXPRESSION_DEPTH: usize = 1000;
+
 #[derive(_Parser)]
 #[grammar = "grammar.pest"] // relative to src
 pub struct Parser;
@@ -132,7 +388,127 @@ impl Parser {
   }
 
   pub fn parse_program_from_str(self: &mut Parser, input: &str) -> Result<Program, anyhow::Error> {
-    Ok(self.parse_program(self.parse_rule(Rule::program, input)?.next().expect("Program should match once"))?)
+    let program = self.parse_program_pair(input)?;
+    self.parse_program(program)
+  }
+
+  /// Parses the source of a program to its `program` pair, failing if it's nested too deeply to be parsed further
+  pub fn parse_program_pair<'a>(self: &Parser, input: &'a str) -> Result<Pair<'a, Rule>, anyhow::Error> {
+    Parser::check_nesting(input)?;
+    let program = self.parse_rule(Rule::program, input)?.next().expect("Program should match once");
+    Parser::check_expression_depth(program.clone())?;
+    Ok(program)
+  }
+
+  /// Fails if brackets or block comments are nested more than `MAX_NESTING` levels deep, before the source is parsed
+  /// recursively. Strings and comments are skipped, so the brackets in them aren't counted.
+  fn check_nesting(input: &str) -> Result<(), anyhow::Error> {
+    let too_deep = |index: usize, what: &str| Parser::error_at(
+      Span::new(input, index, index + 1).expect("Index should be at a character"),
+      format!("{} are nested more than {} levels deep", what, MAX_NESTING),
+    );
+    let mut chars = input.char_indices().peekable();
+    let mut depth: usize = 0;
+    // Depths the substitutions of the template literals being scanned started at
+    let mut substitutions: Vec<usize> = Vec::new();
+    let mut in_template = false;
+    while let Some((index, c)) = chars.next() {
+      let next = chars.peek().map(|(_, c)| *c);
+      if in_template {
+        match (c, next) {
+          ('\\', _) => { chars.next(); },
+          ('`', _) => in_template = false,
+          ('$', Some('{')) => {
+            chars.next();
+            substitutions.push(depth);
+            depth += 1;
+            in_template = false;
+            if depth > MAX_NESTING {
+              return Err(too_deep(index, "Brackets"));
+            }
+          },
+          _ => {},
+        }
+        continue;
+      }
+      match (c, next) {
+        ('"' | '\'', _) => {
+          while let Some((_, string_char)) = chars.next() && string_char != c && string_char != '\n' {
+            if string_char == '\\' {
+              chars.next();
+            }
+          }
+        },
+        ('#', Some('"')) => {
+          chars.next();
+          chars.find(|(_, string_char)| *string_char == '"');
+        },
+        ('`', _) => in_template = true,
+        ('/', Some('/')) => {
+          chars.find(|(_, comment_char)| *comment_char == '\n');
+        },
+        ('/', Some('*')) => {
+          chars.next();
+          let mut comment_depth = 1;
+          while comment_depth > 0 && let Some((comment_index, comment_char)) = chars.next() {
+            match (comment_char, chars.peek().map(|(_, c)| *c)) {
+              ('/', Some('*')) => {
+                chars.next();
+                comment_depth += 1;
+                if comment_depth > MAX_NESTING {
+                  return Err(too_deep(comment_index, "Block comments"));
+                }
+              },
+              ('*', Some('/')) => {
+                chars.next();
+                comment_depth -= 1;
+              },
+              _ => {},
+            }
+          }
+        },
+        ('(' | '[' | '{', _) => {
+          depth += 1;
+          if depth > MAX_NESTING {
+            return Err(too_deep(index, "Brackets"));
+          }
+        },
+        (')' | ']', _) => depth = depth.saturating_sub(1),
+        ('}', _) => {
+          depth = depth.saturating_sub(1);
+          if substitutions.last() == Some(&depth) {
+            substitutions.pop();
+            in_template = true;
+          }
+        },
+        _ => {},
+      }
+    }
+    Ok(())
+  }
+
+  /// Fails if an expression is more than `MAX_EXPRESSION_DEPTH` levels deep. Every operation and nested expression
+  /// counts as a level, which overestimates the depth of the syntax tree the expression becomes.
+  fn check_expression_depth(program: Pair<Rule>) -> Result<(), anyhow::Error> {
+    // Ends and depths of the expressions around the current pair, innermost last
+    let mut enclosing: Vec<(usize, usize)> = Vec::new();
+    for pair in program.into_inner().flatten().filter(|pair| pair.as_rule() == Rule::Expression) {
+      let span = pair.as_span();
+      while enclosing.last().is_some_and(|(end, _)| *end <= span.start()) {
+        enclosing.pop();
+      }
+      let operations = pair.clone().into_inner().filter(|part| matches!(part.as_rule(),
+        Rule::Addition | Rule::Subtraction | Rule::Multiplication | Rule::Division | Rule::Power
+        | Rule::NumeralNegation | Rule::LogicalNegation
+        | Rule::CallExpression | Rule::MemberExpression | Rule::IndexExpression
+      )).count();
+      let depth = enclosing.last().map_or(0, |(_, depth)| *depth) + 1 + operations;
+      if depth > MAX_EXPRESSION_DEPTH {
+        return Err(Parser::error_at(span, format!("Expression is nested more than {} levels deep", MAX_EXPRESSION_DEPTH)));
+      }
+      enclosing.push((span.end(), depth));
+    }
+    Ok(())
   }
 
   // Parses the entire program. Only accept a single `program` pair.
@@ -143,42 +519,105 @@ impl Parser {
     program_body.pop().expect("EOF at the end of program body should not be empty"); // Remove EOF
     Ok(
       Program {
-        // TODO: Implement link logic
-        link_statements: vec![],
+        link_statements: program_header.into_iter()
+          .map(|pair| self.parse_statement(pair))
+          .collect::<Result<Vec<Statement>, anyhow::Error>>()?,
         main_block: program_body.into_iter()
-          .filter_map(|pair| self.parse_item(pair).ok().flatten())
-          .collect::<Vec<Item>>(),
+          .filter(|pair| pair.as_rule() != Rule::DanglingDocComment)
+          .map(|pair| self.parse_item(pair))
+          .collect::<Result<Vec<Item>, anyhow::Error>>()?,
       }
     )
   }
 
-  /// Parses an item, which can be either an attribute or a function declaration.
-  /// 
-  /// - If it's an attribute, we push it to the state and return None
-  /// - If it's a function declaration, we return the parsed item
-  fn parse_item(self: &mut Parser, input: Pair<Rule>) -> Result<Option<Item>, anyhow::Error> {
+  /// Parses an item, which can be a constant or global declaration, a function declaration or a statement.
+  fn parse_item(self: &mut Parser, input: Pair<Rule>) -> Result<Item, anyhow::Error> {
     match input.as_rule() {
-      Rule::FunctionDeclaration => Ok(Some(Item::FunctionDeclaration(self.parse_function_declaration(input)?))),
-      Rule::Attribute => Ok(Some(Item::Attribute(self.parse_attribute(input)?))),
+      Rule::FunctionDeclaration => Ok(Item::FunctionDeclaration(self.parse_function_declaration(input)?)),
+      Rule::ConstDeclaration => {
+        // Constants are declared like globals, but always have a value
+        let GlobalDeclaration { attributes, position, name, type_annotation, value } = self.parse_declaration(input)?;
+        Ok(Item::ConstDeclaration(ConstDeclaration {
+          attributes,
+          position,
+          name,
+          type_annotation,
+          value: value.expect("Constant declaration should have a value"),
+        }))
+      },
+      Rule::GlobalDeclaration => Ok(Item::GlobalDeclaration(self.parse_declaration(input)?)),
+      Rule::ExpressionStatement | Rule::AssignmentStatement | Rule::ForStatement => Ok(Item::Statement(self.parse_statement(input)?)),
+      // Attributes are only matched on their own if no declaration or statement follows them
+      Rule::Attribute => {
+        let span = input.as_span();
+        let attribute = self.parse_attribute(input)?;
+        Err(Parser::error_at(span, format!(
+          "Attribute \"{}\" must be followed by a declaration or statement", attribute.name
+        )))
+      },
+      Rule::UnclosedComment => {
+        let start = input.as_span().start_pos();
+        Err(Parser::error_at(start.span(&start), "Block comment is never closed by \"*/\"".to_string()))
+      },
       rule => unreachable!("Expected item, found {:?}", rule),
     }
   }
 
+  /// Parses the attributes, name, optional type annotation and optional value of a `const` or `global` declaration
+  fn parse_declaration(self: &Parser, input: Pair<Rule>) -> Result<GlobalDeclaration, anyhow::Error> {
+    let mut input_iter = input.into_inner();
+    let attributes = self.parse_attributes(&mut input_iter)?;
+    let name_pair = input_iter.next().expect("Declaration should have a name");
+    let position = Position::of(name_pair.as_span());
+    let name = Identifier::of(name_pair);
+    let mut type_annotation = None;
+    let mut value = None;
+    for pair in input_iter {
+      match pair.as_rule() {
+        Rule::Type => type_annotation = Some(pair.as_str().to_string()),
+        _ => value = Some(self.parse_expression(pair)?),
+      }
+    }
+    Ok(GlobalDeclaration { attributes, position, name, type_annotation, value })
+  }
+
+  /// Parses the attributes and doc comments at the start of an item or statement
+  fn parse_attributes(self: &Parser, input: &mut Pairs<Rule>) -> Result<Vec<Attribute>, anyhow::Error> {
+    let mut attributes = Vec::new();
+    while input.peek().is_some_and(|pair| matches!(pair.as_rule(), Rule::Attribute | Rule::DocComment)) {
+      attributes.push(self.parse_attribute(input.next().expect("Attribute should be present"))?);
+    }
+    Ok(attributes)
+  }
+
   fn parse_attribute(self: &Parser, input: Pair<Rule>) -> Result<Attribute, anyhow::Error> {
     match input.as_rule() {
+      Rule::DocComment => {
+        let text = input.into_inner().next().expect("Doc comment should have text").as_str();
+        // The space after `///` isn't part of the text
+        let text = text.strip_prefix(' ').unwrap_or(text);
+        Ok(Attribute {
+          name: "doc".to_string(),
+          arguments: vec![AttributeArgument { name: None, value: AttributeValue::String(text.to_string()) }],
+        })
+      },
       Rule::Attribute => {
         let mut input_iter = input.into_inner();
-        let attribute_name = input_iter.find_first_tagged("attr_name").expect("There should be attribute name in attribute").as_str().to_string();
-        let attribute_content = if let Some(content_pair) = input_iter.find_first_tagged("attr_content") {
-          Some(content_pair.as_str().trim_matches('"').to_string())
-        } else {
-          None
-        };
-        match attribute_name.as_str() {
-          "inline" => Ok(Attribute::Inline),
-          "export_as" => Ok(Attribute::ExportAs(attribute_content.unwrap_or_else(|| attribute_name))),
-          attr_str => Err(anyhow::anyhow!("Unknown attribute: {}", attr_str)),
-        }
+        let name = input_iter.next().expect("Attribute should have a name").as_str().to_string();
+        let arguments = input_iter.map(|argument_pair| {
+          let mut argument_iter = argument_pair.into_inner();
+          // The value is always last, with the optional name before it
+          let value_pair = argument_iter.next_back().expect("Attribute argument should have a value");
+          let value = match value_pair.as_rule() {
+            Rule::string_literal => AttributeValue::String(self.parse_string_literal(value_pair)?),
+            Rule::float_literal | Rule::number_literal => AttributeValue::Number(value_pair.as_str().to_string()),
+            Rule::boolean_literal => AttributeValue::Bool(self.parse_boolean_literal(value_pair)?),
+            Rule::Identifier => AttributeValue::Identifier(value_pair.as_str().to_string()),
+            rule => unreachable!("Expected attribute value, found {:?}", rule),
+          };
+          Ok(AttributeArgument { name: argument_iter.next().map(|name_pair| name_pair.as_str().to_string()), value })
+        }).collect::<Result<Vec<AttributeArgument>, anyhow::Error>>()?;
+        Ok(Attribute { name, arguments })
       },
       rule => unreachable!("Expected attribute, found {:?}", rule),
     }
@@ -188,24 +627,35 @@ impl Parser {
     match input.as_rule() {
       Rule::FunctionDeclaration => {
         let mut input_iter: Pairs<Rule> = input.into_inner();
-        let function_name = input_iter.next().expect("Function name should not be empty").as_str().to_string();
+        let attributes = self.parse_attributes(&mut input_iter)?;
+        let name_pair = input_iter.next().expect("Function name should not be empty");
+        let position = Position::of(name_pair.as_span());
+        let function_name = name_pair.as_str().to_string();
         
         // The last pair is the block. We pop it from the back.
         let body_pair = input_iter.next_back().expect("Function body should not be empty");
-        let parsed_body = self.parse_block(body_pair.into_inner());
-
-        // The remaining pair in the middle (if any) is the parameters expression
-        let expanded_parameters = if let Some(params_pair) = input_iter.next() {
-           let param_expr = self.parse_expression(params_pair);
-           self.expand_comma_expression(param_expr)?
-        } else {
-           vec![]
-        };
+        let parsed_body = self.parse_block(body_pair.into_inner())?;
+
+        // The remaining pairs in the middle are the parameter list and the optional return type
+        let parameters = input_iter.next().expect("Function should have a parameter list").into_inner()
+          .map(|param_pair| {
+            let mut param_iter = param_pair.into_inner();
+            Parameter {
+              name: Identifier::of(param_iter.next().expect("Parameter should have a name")),
+              type_annotation: param_iter.next().map(|type_pair| type_pair.as_str().to_string()),
+            }
+          })
+          .collect::<Vec<Parameter>>();
+        let return_type = input_iter.next()
+          .map(|return_type_pair| return_type_pair.into_inner().next().expect("Return type should have a type").as_str().to_string());
 
         Ok(
           FunctionDeclaration {
+            attributes,
+            position,
             name: function_name,
-            parameters: expanded_parameters,
+            parameters,
+            return_type,
             body: parsed_body,
           }
         )
@@ -214,62 +664,106 @@ impl Parser {
     }
   }
 
-  fn parse_block(self: &Parser, input: Pairs<Rule>) -> Vec<Statement> {
-    input.map(|pair| self.parse_statement(pair)).collect()
+  fn parse_block(self: &Parser, input: Pairs<Rule>) -> Result<Vec<Statement>, anyhow::Error> {
+    input.filter(|pair| pair.as_rule() != Rule::DanglingDocComment).map(|pair| self.parse_statement(pair)).collect()
   }
 
-  fn parse_statement(self: &Parser, input: Pair<Rule>) -> Statement {
+  fn parse_statement(self: &Parser, input: Pair<Rule>) -> Result<Statement, anyhow::Error> {
     let statement = input;
-    match statement.as_rule() {
+    if statement.as_rule() == Rule::LinkStatement {
+      let position = Position::of(statement.as_span());
+      return Ok(Statement::Link {
+        path: self.parse_string_literal(statement.into_inner().next().expect("Link statement should have a path"))?,
+        position,
+      });
+    }
+    let rule = statement.as_rule();
+    let mut statement_iter = statement.into_inner();
+    let attributes = self.parse_attributes(&mut statement_iter)?;
+    let position = Position::of(statement_iter.next().expect("Statement should have a start").as_span());
+    match rule {
       Rule::ExpressionStatement => {
-        Statement::Expression {
-          expr: self.parse_expression(statement.into_inner().next().unwrap())
-        }
+        Ok(Statement::Expression {
+          attributes,
+          expr: self.parse_expression(statement_iter.next().unwrap())?,
+          position,
+        })
       }
       
       Rule::AssignmentStatement => {
-        let inner_statement = statement.into_inner().next().unwrap();
+        let inner_statement = statement_iter.next().unwrap();
         match inner_statement.as_rule() {
-          Rule::LetStatement | Rule::ReassignmentStatement => {
+          Rule::LetStatement => {
+            let mut inner_statement_iter = inner_statement.into_inner();
+            let name = Identifier::of(inner_statement_iter.next().expect("Let statement should have a name"));
+            // The value is always last, with the optional type annotation before it
+            let value = self.parse_expression(inner_statement_iter.next_back().expect("Let statement should have a value"))?;
+            Ok(Statement::Let {
+              attributes,
+              name,
+              type_annotation: inner_statement_iter.next().map(|type_pair| type_pair.as_str().to_string()),
+              value,
+              position,
+            })
+          },
+          Rule::ReassignmentStatement => {
             let mut inner_statement_iter = inner_statement.into_inner();
             let lhs_expr = inner_statement_iter.next().expect("LHS of assignment should not be empty");
             let rhs_expr = inner_statement_iter.next().expect("RHS of assignment should not be empty");
-            Statement::Assignment {
-              lhs: self.parse_singlet(lhs_expr),
-              rhs: self.parse_expression(rhs_expr),
-            }
+            Ok(Statement::Assignment {
+              attributes,
+              lhs: self.parse_singlet(lhs_expr)?,
+              rhs: self.parse_expression(rhs_expr)?,
+              position,
+            })
           },
           rule => unreachable!("Expected assignment statement, found {:?}", rule),
         }
       },
-      // TODO: Implement link statement parsing
-      Rule::LinkStatement => todo!(),
+      Rule::ForStatement => {
+        let variable = Identifier::of(statement_iter.next().expect("For statement should have a variable"));
+        let iterable = self.parse_expression(statement_iter.next().expect("For statement should have an iterable"))?;
+        let body = self.parse_block(statement_iter.next().expect("For statement should have a body").into_inner())?;
+        Ok(Statement::For { attributes, variable, iterable, body, position })
+      },
       rule => unreachable!("Expected statement, found {:?}", rule),
     }
   }
 
-  fn parse_singlet(self: &Parser, input: Pair<Rule>) -> Expression {
+  fn parse_singlet(self: &Parser, input: Pair<Rule>) -> Result<Expression, anyhow::Error> {
     match input.as_rule() {
       Rule::Expression => self.parse_expression(input),
-      Rule::CWScriptBlockID => Expression::CWScriptBlockID(input.as_str().strip_prefix("#").expect("Expected # prefix before numerals for raw ID calls").to_string()),
-      Rule::Identifier => Expression::Identifier(input.as_str().to_string()),
-      Rule::raw_string_literal => Expression::Literal(Literal::RawString(input.as_str()
+      Rule::CWScriptBlockID => Ok(Expression::CWScriptBlockID(input.as_str().strip_prefix("#").expect("Expected # prefix before numerals for raw ID calls").to_string())),
+      Rule::Identifier => Ok(Expression::Identifier(Identifier::of(input))),
+      Rule::raw_string_literal => Ok(Expression::Literal(Literal::RawString(input.as_str()
         .strip_prefix("#").expect("Expected # prefix before raw string literal")
         .strip_prefix('"').expect("Expected quotation around raw string literal")
         .strip_suffix('"').expect("Expected quotation around raw string literal")
-        .to_string())),
-      Rule::string_literal => Expression::Literal(Literal::String(input.as_str()
-        .strip_prefix('"').expect("Expected quotation around string literal")
-        .strip_suffix('"').expect("Expected quotation around string literal")
-        .to_string())),
-      Rule::float_literal => Expression::Literal(Literal::Float(input.as_str().to_string())),
-      Rule::number_literal => Expression::Literal(Literal::Integer(input.as_str().to_string())),
-      Rule::boolean_literal => Expression::Literal(Literal::Bool(self.parse_boolean_literal(input).unwrap())),
+        .to_string()))),
+      Rule::string_literal => Ok(Expression::Literal(Literal::String(self.parse_string_literal(input)?))),
+      Rule::template_literal => self.parse_template_literal(input),
+      Rule::ArrayExpression => Ok(Expression::Array(input.into_inner()
+        .map(|element| self.parse_expression(element))
+        .collect::<Result<Vec<Expression>, anyhow::Error>>()?)),
+      Rule::TableExpression => Ok(Expression::Table(input.into_inner()
+        .map(|entry| {
+          let mut entry_iter = entry.into_inner();
+          let key_pair = entry_iter.next().expect("Table entry should have a key");
+          let key = match key_pair.as_rule() {
+            Rule::string_literal => self.parse_string_literal(key_pair)?,
+            _ => key_pair.as_str().to_string(),
+          };
+          Ok((key, self.parse_expression(entry_iter.next().expect("Table entry should have a value"))?))
+        })
+        .collect::<Result<Vec<(String, Expression)>, anyhow::Error>>()?)),
+      Rule::float_literal => Ok(Expression::Literal(Literal::Float(input.as_str().to_string()))),
+      Rule::number_literal => Ok(Expression::Literal(Literal::Integer(input.as_str().to_string()))),
+      Rule::boolean_literal => Ok(Expression::Literal(Literal::Bool(self.parse_boolean_literal(input)?))),
       rule => unreachable!("Expected singlet expression, found {:?}", rule),
     }
   }
 
-  fn parse_expression(self: &Parser, input: Pair<Rule>) -> Expression {
+  fn parse_expression(self: &Parser, input: Pair<Rule>) -> Result<Expression, anyhow::Error> {
     PRATT_PARSER
       .map_primary(|primary| self.parse_singlet(primary))
       .map_infix(|lhs, op, rhs| {
@@ -279,15 +773,13 @@ impl Parser {
           Rule::Multiplication => BinOperator::Multiplication,
           Rule::Division => BinOperator::Division,
           Rule::Power => BinOperator::Power,
-          Rule::Dot => BinOperator::Dot,
-          Rule::Comma => BinOperator::Comma,
           rule => unreachable!("Expr::parse expected infix operation, found {:?}", rule),
         };
-        Expression::BinOperation {
-          lhs: Box::new(lhs),
+        Ok(Expression::BinOperation {
+          lhs: Box::new(lhs?),
           op,
-          rhs: Box::new(rhs),
-        }
+          rhs: Box::new(rhs?),
+        })
       })
       .map_prefix(|prefix, expr| {
         let op = match prefix.as_rule() {
@@ -295,21 +787,32 @@ impl Parser {
           Rule::LogicalNegation => UnaryOperator::LogicalNegation,
           rule => unreachable!("Expr::parse expected prefix operation, found {:?}", rule),
         };
-        Expression::UnaryOperation {
+        Ok(Expression::UnaryOperation {
           op,
-          expr: Box::new(expr),
-        }
+          expr: Box::new(expr?),
+        })
       })
       .map_postfix(|expr, postfix| {
         match postfix.as_rule() {
           Rule::CallExpression => {
-            let postfix_inner_iter = postfix.clone().into_inner();
-            Expression::Call {
-              function: Box::new(expr), 
-              arguments: if postfix_inner_iter.len() > 0 {
-                postfix_inner_iter.map(|arg_pair| self.expand_comma_expression(self.parse_expression(arg_pair)).unwrap()).flatten().collect::<Vec<Expression>>() 
-              } else { vec![] }
-            }
+            Ok(Expression::Call {
+              function: Box::new(expr?), 
+              arguments: postfix.into_inner()
+                .map(|arg_pair| self.parse_expression(arg_pair))
+                .collect::<Result<Vec<Expression>, anyhow::Error>>()?,
+            })
+          },
+          Rule::MemberExpression => {
+            Ok(Expression::Member {
+              object: Box::new(expr?),
+              property: postfix.into_inner().next().expect("Member expression should have a property").as_str().to_string(),
+            })
+          },
+          Rule::IndexExpression => {
+            Ok(Expression::Index {
+              object: Box::new(expr?),
+              index: Box::new(self.parse_expression(postfix.into_inner().next().expect("Index expression should have an index"))?),
+            })
           },
           rule => unreachable!("Expr::parse expected postfix operation, found {:?}", rule),
         }
@@ -317,6 +820,90 @@ impl Parser {
       .parse(input.into_inner())
   }
 
+  /// Parses a string literal and decodes its escape sequences
+  fn parse_string_literal(self: &Parser, input: Pair<Rule>) -> Result<String, anyhow::Error> {
+    let content = input.into_inner().find_first_tagged("string_content").expect("String literal should have content");
+    Parser::decode_escape_sequences(content.as_span())
+  }
+
+  /// Parses a template literal. Adjacent text is merged, and templates without embedded expressions become strings.
+  fn parse_template_literal(self: &Parser, input: Pair<Rule>) -> Result<Expression, anyhow::Error> {
+    let mut parts: Vec<TemplatePart> = Vec::new();
+    for part in input.into_inner() {
+      match part.as_rule() {
+        Rule::template_text => {
+          let text = Parser::decode_escape_sequences(part.as_span())?;
+          match parts.last_mut() {
+            Some(TemplatePart::Text(previous)) => previous.push_str(&text),
+            _ => parts.push(TemplatePart::Text(text)),
+          }
+        },
+        Rule::template_substitution => {
+          let expr_pair = part.into_inner().next().expect("Template substitution should contain an expression");
+          parts.push(TemplatePart::Expression(self.parse_expression(expr_pair)?));
+        },
+        rule => unreachable!("Expected template part, found {:?}", rule),
+      }
+    }
+
+    match parts.as_slice() {
+      [] => Ok(Expression::Literal(Literal::String(String::new()))),
+      [TemplatePart::Text(text)] => Ok(Expression::Literal(Literal::String(text.clone()))),
+      _ => Ok(Expression::Template(parts)),
+    }
+  }
+
+  /// Decodes the escape sequences in the string content of a span
+  fn decode_escape_sequences(span: Span) -> Result<String, anyhow::Error> {
+    let mut decoded = String::new();
+    let mut chars = span.as_str().char_indices();
+
+    while let Some((start, char)) = chars.next() {
+      if char != '\\' {
+        decoded.push(char);
+        continue;
+      }
+      // The grammar guarantees a character after the backslash
+      let (_, escaped) = chars.next().expect("Escape sequence should not be empty");
+      match escaped {
+        'n' => decoded.push('\n'),
+        't' => decoded.push('\t'),
+        '\\' | '"' | '\'' | '`' | '$' => decoded.push(escaped),
+        'u' => {
+          // `\u{...}` with 1 to 6 hexadecimal digits
+          let code = span.as_str()[start + 2..].strip_prefix('{')
+            .and_then(|rest| rest.split_once('}'))
+            .map(|(code, _)| code);
+          let unicode_char = code
+            .filter(|code| (1..=6).contains(&code.len()))
+            .and_then(|code| u32::from_str_radix(code, 16).ok())
+            .and_then(char::from_u32);
+          match (code, unicode_char) {
+            (Some(code), Some(unicode_char)) => {
+              decoded.push(unicode_char);
+              // Skip the braces and the code
+              chars.nth(code.chars().count() + 1);
+            },
+            (code, _) => return Err(Parser::error_at(
+              span.get(start..start + 2 + code.map_or(0, |code| code.len() + 2)).unwrap_or(span),
+              "Invalid unicode escape sequence, expected \\u{...} with 1 to 6 hexadecimal digits".to_string(),
+            )),
+          }
+        },
+        others => return Err(Parser::error_at(
+          span.get(start..start + 1 + others.len_utf8()).unwrap_or(span),
+          format!("Unknown escape sequence \\{}", others),
+        )),
+      }
+    }
+    Ok(decoded)
+  }
+
+  /// Creates a parse error pointing to the given source code span
+  fn error_at(span: Span, message: String) -> anyhow::Error {
+    pest::error::Error::<Rule>::new_from_span(pest::error::ErrorVariant::CustomError { message }, span).into()
+  }
+
   pub fn parse_boolean_literal(self: &Parser, input: Pair<Rule>) -> Result<bool, anyhow::Error> {
     let pair = input.into_inner().next().unwrap();
     match pair.as_rule() {
@@ -325,15 +912,4 @@ impl Parser {
       _ => Err(anyhow::anyhow!("Expected boolean literal, found {:?}", pair.as_rule())),
     }
   }
-
-  fn expand_comma_expression(self: &Parser, input: Expression) -> Result<Vec<Expression>, anyhow::Error> {
-    match input {
-      Expression::BinOperation { lhs, op: BinOperator::Comma, rhs } => {
-        let mut result = self.expand_comma_expression(*lhs)?;
-        result.push(*rhs);
-        Ok(result)
-      },
-      expr => Ok(vec![expr]),
-    }
-  }
 }
\ No newline at end of file
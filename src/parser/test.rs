@@ -3,7 +3,7 @@ fn test_simple_function_parsing() {
   use crate::parser::{self};
 
   let input = r#"
-  #[export_as("c")]
+  #[export_as("add")]
   function add(a, b) {
     #0(#"", "Hello, World!");
   }"#;
@@ -12,21 +12,30 @@ fn test_simple_function_parsing() {
 
   dbg!(syntax_tree.clone());
 
+  let identifier = |name: &str, line: usize, column: usize| parser::Identifier {
+    position: parser::Position { line, column },
+    ..parser::Identifier::new(name)
+  };
   let expected_syntax_tree = parser::Program {
     link_statements: vec![],
     main_block: vec![
-      parser::Item::Attribute(
-        parser::Attribute::ExportAs("add".to_string())
-      ),
       parser::Item::FunctionDeclaration(
         parser::FunctionDeclaration {         
+          position: parser::Position { line: 3, column: 12 },
+          attributes: vec![parser::Attribute {
+            name: "export_as".to_string(),
+            arguments: vec![parser::AttributeArgument { name: None, value: parser::AttributeValue::String("add".to_string()) }],
+          }],
           name: "add".to_string(),
           parameters: vec![
-            parser::Expression::Identifier("a".to_string()),
-            parser::Expression::Identifier("b".to_string()),
+            parser::Parameter { name: identifier("a", 3, 16), type_annotation: None },
+            parser::Parameter { name: identifier("b", 3, 19), type_annotation: None },
           ],
+          return_type: None,
           body: vec![
             parser::Statement::Expression {
+              attributes: vec![],
+              position: parser::Position { line: 4, column: 5 },
               expr: parser::Expression::Call {
                 function: Box::new(parser::Expression::CWScriptBlockID("0".to_string())),
                 arguments: vec![
@@ -42,4 +51,505 @@ fn test_simple_function_parsing() {
   };
 
   assert_eq!(expected_syntax_tree, syntax_tree);
-}
\ No newline at end of file
+}
+#[test]
+fn test_string_literal_parsing() {
+  use crate::parser::{self};
+
+  let input = r#"
+  function f() {
+    #0("quote \" backslash \\ newline \n tab \t", 'single "quoted"', `multi
+line`, "\u{1F431}\u{41}");
+  }"#;
+
+  let syntax_tree = super::Parser.parse_program_from_str(input).unwrap();
+
+  let expected_arguments = vec![
+    parser::Expression::Literal(parser::Literal::String("quote \" backslash \\ newline \n tab \t".to_string())),
+    parser::Expression::Literal(parser::Literal::String("single \"quoted\"".to_string())),
+    parser::Expression::Literal(parser::Literal::String("multi\nline".to_string())),
+    parser::Expression::Literal(parser::Literal::String("🐱A".to_string())),
+  ];
+
+  let parser::Item::FunctionDeclaration(function) = &syntax_tree.main_block[0] else {
+    panic!("Expected function declaration");
+  };
+  let parser::Statement::Expression { expr: parser::Expression::Call { arguments, .. }, .. } = &function.body[0] else {
+    panic!("Expected call statement");
+  };
+  assert_eq!(&expected_arguments, arguments);
+}
+
+#[test]
+fn test_invalid_escape_sequences() {
+  let error = super::Parser.parse_program_from_str("function f() {\n  #0(\"a \\q\");\n}").unwrap_err().to_string();
+  assert!(error.contains("2:9"), "Expected location of the escape in: {}", error);
+  assert!(error.contains("Unknown escape sequence \\q"), "{}", error);
+
+  let error = super::Parser.parse_program_from_str("function f() {\n  #0(\"\\u{110000}\");\n}").unwrap_err().to_string();
+  assert!(error.contains("2:7"), "Expected location of the escape in: {}", error);
+  assert!(error.contains("Invalid unicode escape sequence"), "{}", error);
+
+  // Quoted strings cannot span multiple lines
+  assert!(super::Parser.parse_program_from_str("function f() {\n  #0(\"a\nb\");\n}").is_err());
+}
+
+#[test]
+fn test_template_literal_parsing() {
+  use crate::parser::{self};
+
+  let input = r#"
+  function f() {
+    #0(`Hello ${user}! \${not} ${a + 1}`, `plain
+text`);
+  }"#;
+
+  let syntax_tree = super::Parser.parse_program_from_str(input).unwrap();
+
+  // Identifiers in templates have their position in the source
+  let identifier = |name: &str, line: usize, column: usize| parser::Expression::Identifier(parser::Identifier {
+    position: parser::Position { line, column },
+    ..parser::Identifier::new(name)
+  });
+  let expected_arguments = vec![
+    parser::Expression::Template(vec![
+      parser::TemplatePart::Text("Hello ".to_string()),
+      parser::TemplatePart::Expression(identifier("user", 3, 17)),
+      parser::TemplatePart::Text("! ${not} ".to_string()),
+      parser::TemplatePart::Expression(parser::Expression::BinOperation {
+        lhs: Box::new(identifier("a", 3, 34)),
+        op: parser::BinOperator::Addition,
+        rhs: Box::new(parser::Expression::Literal(parser::Literal::Integer("1".to_string()))),
+      }),
+    ]),
+    parser::Expression::Literal(parser::Literal::String("plain\ntext".to_string())),
+  ];
+
+  let parser::Item::FunctionDeclaration(function) = &syntax_tree.main_block[0] else {
+    panic!("Expected function declaration");
+  };
+  let parser::Statement::Expression { expr: parser::Expression::Call { arguments, .. }, .. } = &function.body[0] else {
+    panic!("Expected call statement");
+  };
+  assert_eq!(&expected_arguments, arguments);
+}
+
+/// Fully parenthesizes an expression, to make its structure easy to compare
+#[cfg(test)]
+fn parenthesize(expr: &crate::parser::Expression) -> String {
+  use crate::parser::{BinOperator, Expression, Literal, UnaryOperator};
+
+  match expr {
+    Expression::Literal(Literal::Integer(value) | Literal::Float(value)) => value.clone(),
+    Expression::Literal(literal) => format!("{:?}", literal),
+    Expression::Identifier(iden) => iden.name.clone(),
+    Expression::CWScriptBlockID(id) => format!("#{}", id),
+    Expression::BinOperation { lhs, op, rhs } => {
+      let op = match op {
+        BinOperator::Addition => "+",
+        BinOperator::Subtraction => "-",
+        BinOperator::Multiplication => "*",
+        BinOperator::Division => "/",
+        BinOperator::Power => "**",
+      };
+      format!("({} {} {})", parenthesize(lhs), op, parenthesize(rhs))
+    },
+    Expression::UnaryOperation { op, expr } => {
+      let op = match op {
+        UnaryOperator::NumeralNegation => "-",
+        UnaryOperator::LogicalNegation => "!",
+      };
+      format!("({}{})", op, parenthesize(expr))
+    },
+    Expression::Call { function, arguments } => {
+      format!("{}({})", parenthesize(function), arguments.iter().map(parenthesize).collect::<Vec<String>>().join(", "))
+    },
+    Expression::Member { object, property } => format!("({}.{})", parenthesize(object), property),
+    Expression::Index { object, index } => format!("({}[{}])", parenthesize(object), parenthesize(index)),
+    Expression::Array(elements) => format!("[{}]", elements.iter().map(parenthesize).collect::<Vec<String>>().join(", ")),
+    Expression::Table(entries) => format!(
+      "{{{}}}",
+      entries.iter().map(|(key, value)| format!("{}: {}", key, parenthesize(value))).collect::<Vec<String>>().join(", ")
+    ),
+    Expression::Template(_) => format!("{:?}", expr),
+  }
+}
+
+#[test]
+fn test_operator_precedence() {
+  let cases = [
+    // Binary operators and associativity
+    ("a + b - c", "((a + b) - c)"),
+    ("a - b + c", "((a - b) + c)"),
+    ("a * b / c", "((a * b) / c)"),
+    ("a / b * c", "((a / b) * c)"),
+    ("a + b * c", "(a + (b * c))"),
+    ("a - b / c", "(a - (b / c))"),
+    ("a * b ** c", "(a * (b ** c))"),
+    ("a ** b ** c", "(a ** (b ** c))"),
+    ("(a + b) * c", "((a + b) * c)"),
+    // Unary operators bind tighter than binary operators
+    ("-a + b", "((-a) + b)"),
+    ("!a * b", "((!a) * b)"),
+    ("-a ** b", "((-a) ** b)"),
+    ("--a", "(-(-a))"),
+    ("a - -b", "(a - (-b))"),
+    // Postfix operators bind tightest
+    ("a.b + c", "((a.b) + c)"),
+    ("a + b.c", "(a + (b.c))"),
+    ("-a.b", "(-(a.b))"),
+    ("!f(a)", "(!f(a))"),
+    ("a.b.c", "((a.b).c)"),
+    ("a.b(c)", "(a.b)(c)"),
+    ("a[i].b", "((a[i]).b)"),
+    ("a[i + 1] * 2", "((a[(i + 1)]) * 2)"),
+    ("f(a, b + c)[0]", "(f(a, (b + c))[0])"),
+    ("f()(a)", "f()(a)"),
+    ("#0(a, b,)", "#0(a, b)"),
+    // Collections
+    ("[1, a + 1][0]", "([1, (a + 1)][0])"),
+    ("{ name: a, \"my key\": [b], }.name", "({name: a, my key: [b]}.name)"),
+  ];
+
+  for (input, expected) in cases {
+    let program = format!("function f() {{ {}; }}", input);
+    let syntax_tree = super::Parser.parse_program_from_str(&program).unwrap();
+    let crate::parser::Item::FunctionDeclaration(function) = &syntax_tree.main_block[0] else {
+      panic!("Expected function declaration");
+    };
+    let crate::parser::Statement::Expression { expr, .. } = &function.body[0] else {
+      panic!("Expected expression statement");
+    };
+    assert_eq!(parenthesize(expr), expected, "while parsing {}", input);
+  }
+}
+
+#[test]
+fn test_argument_lists() {
+  use crate::parser::{self};
+
+  // Parenthesized commas are not argument separators
+  assert!(super::Parser.parse_program_from_str("function f() { g((a, b)); }").is_err());
+  assert!(super::Parser.parse_program_from_str("function f(a b) {}").is_err());
+  // Empty parentheses are only an argument list
+  for input in ["let x = ();", "let x = () + 1;", "f(());"] {
+    assert!(super::Parser.parse_program_from_str(input).is_err(), "{}", input);
+  }
+
+  let syntax_tree = super::Parser.parse_program_from_str("function f(a, b,) { g(); }").unwrap().without_positions();
+  let parser::Item::FunctionDeclaration(function) = &syntax_tree.main_block[0] else {
+    panic!("Expected function declaration");
+  };
+  assert_eq!(function.parameters, vec![
+    parser::Parameter { name: parser::Identifier::new("a"), type_annotation: None },
+    parser::Parameter { name: parser::Identifier::new("b"), type_annotation: None },
+  ]);
+  assert_eq!(function.body[0], parser::Statement::Expression {
+    attributes: vec![],
+    expr: parser::Expression::Call {
+      function: Box::new(parser::Expression::Identifier(parser::Identifier::new("g"))),
+      arguments: vec![],
+    },
+    position: Default::default(),
+  });
+}
+
+#[test]
+fn test_type_annotation_parsing() {
+  use crate::parser::{self};
+
+  let input = r#"
+  function f(a: string, b): bool {
+    let n: number = 0;
+    let m = n;
+  }"#;
+
+  let syntax_tree = super::Parser.parse_program_from_str(input).unwrap().without_positions();
+  let parser::Item::FunctionDeclaration(func) = &syntax_tree.main_block[0] else {
+    panic!("Expected function declaration");
+  };
+
+  assert_eq!(func.parameters, vec![
+    parser::Parameter { name: parser::Identifier::new("a"), type_annotation: Some("string".to_string()) },
+    parser::Parameter { name: parser::Identifier::new("b"), type_annotation: None },
+  ]);
+  assert_eq!(func.return_type, Some("bool".to_string()));
+  assert_eq!(func.body, vec![
+    parser::Statement::Let {
+      attributes: vec![],
+      name: parser::Identifier::new("n"),
+      type_annotation: Some("number".to_string()),
+      value: parser::Expression::Literal(parser::Literal::Integer("0".to_string())),
+      position: Default::default(),
+    },
+    parser::Statement::Let {
+      attributes: vec![],
+      name: parser::Identifier::new("m"),
+      type_annotation: None,
+      value: parser::Expression::Identifier(parser::Identifier::new("n")),
+      position: Default::default(),
+    },
+  ]);
+}
+
+#[test]
+fn test_declaration_parsing() {
+  use crate::parser::{self};
+
+  let input = r#"
+  const MAX: number = 10;
+  global count = MAX;
+  global name: string;"#;
+
+  let syntax_tree = super::Parser.parse_program_from_str(input).unwrap().without_positions();
+  assert_eq!(syntax_tree.main_block, vec![
+    parser::Item::ConstDeclaration(parser::ConstDeclaration {
+      attributes: vec![],
+      position: Default::default(),
+      name: parser::Identifier::new("MAX"),
+      type_annotation: Some("number".to_string()),
+      value: parser::Expression::Literal(parser::Literal::Integer("10".to_string())),
+    }),
+    parser::Item::GlobalDeclaration(parser::GlobalDeclaration {
+      attributes: vec![],
+      position: Default::default(),
+      name: parser::Identifier::new("count"),
+      type_annotation: None,
+      value: Some(parser::Expression::Identifier(parser::Identifier::new("MAX"))),
+    }),
+    parser::Item::GlobalDeclaration(parser::GlobalDeclaration {
+      attributes: vec![],
+      position: Default::default(),
+      name: parser::Identifier::new("name"),
+      type_annotation: Some("string".to_string()),
+      value: None,
+    }),
+  ]);
+
+  // Constants always have a value
+  assert!(super::Parser.parse_program_from_str("const MAX;").is_err());
+}
+
+#[test]
+fn test_top_level_statement_parsing() {
+  use crate::parser::{self};
+
+  let input = r#"
+  console.log("Hi");
+  function f() {}
+  constant = 1;
+  for (letter of letters) {}"#;
+
+  let syntax_tree = super::Parser.parse_program_from_str(input).unwrap();
+  let kinds = syntax_tree.main_block.iter().map(|item| match item {
+    parser::Item::Statement(parser::Statement::Expression { .. }) => "expression",
+    parser::Item::Statement(parser::Statement::Assignment { .. }) => "assignment",
+    parser::Item::Statement(parser::Statement::For { .. }) => "for",
+    parser::Item::FunctionDeclaration(_) => "function",
+    others => panic!("Unexpected item {:?}", others),
+  }).collect::<Vec<&str>>();
+  assert_eq!(kinds, vec!["expression", "function", "assignment", "for"]);
+
+  // Keywords don't run into identifiers
+  let parser::Item::Statement(parser::Statement::For { variable, .. }) = &syntax_tree.main_block[3] else {
+    panic!("Expected for statement");
+  };
+  assert_eq!(variable.name, "letter");
+}
+
+#[test]
+fn test_attribute_parsing() {
+  use crate::parser::{self};
+
+  let input = r#"
+  #[inline]
+  #[lint(unused, level = "deny", 2, strict = true,)]
+  function f() {}"#;
+
+  let syntax_tree = super::Parser.parse_program_from_str(input).unwrap();
+  let parser::Item::FunctionDeclaration(func) = &syntax_tree.main_block[0] else {
+    panic!("Expected function declaration");
+  };
+  let argument = |name: Option<&str>, value: parser::AttributeValue| parser::AttributeArgument { name: name.map(str::to_string), value };
+  assert_eq!(func.attributes, vec![
+    parser::Attribute { name: "inline".to_string(), arguments: vec![] },
+    parser::Attribute {
+      name: "lint".to_string(),
+      arguments: vec![
+        argument(None, parser::AttributeValue::Identifier("unused".to_string())),
+        argument(Some("level"), parser::AttributeValue::String("deny".to_string())),
+        argument(None, parser::AttributeValue::Number("2".to_string())),
+        argument(Some("strict"), parser::AttributeValue::Bool(true)),
+      ],
+    },
+  ]);
+
+  // Doc comments are doc attributes, and statements have attributes too
+  let input = "/// Adds one\n///\n//// Not documentation\nfunction f() {\n  #[comment(\"call\")]\n  /// text\n  g();\n}";
+  let syntax_tree = super::Parser.parse_program_from_str(input).unwrap();
+  let parser::Item::FunctionDeclaration(func) = &syntax_tree.main_block[0] else {
+    panic!("Expected function declaration");
+  };
+  let attribute = |name: &str, text: &str| parser::Attribute {
+    name: name.to_string(),
+    arguments: vec![argument(None, parser::AttributeValue::String(text.to_string()))],
+  };
+  assert_eq!(func.attributes, vec![attribute("doc", "Adds one"), attribute("doc", "")]);
+  assert_eq!(func.body[0].attributes(), [attribute("comment", "call"), attribute("doc", "text")]);
+  // Statements start after their attributes
+  assert_eq!((func.body[0].position().line, func.body[0].position().column), (7, 3));
+
+  // Attributes must belong to a declaration or statement
+  let error = super::Parser.parse_program_from_str("function f() {}\n#[inline]").unwrap_err().to_string();
+  assert!(error.contains("Attribute \"inline\" must be followed by a declaration or statement"), "{}", error);
+}
+
+#[test]
+fn test_ast_dump() {
+  use crate::parser::dump::dump_program;
+
+  let input = r#"
+  link "site.json";
+  #[export_as("console.warn")]
+  function warn(message: string): string { return_value(message); }
+  const LIMIT: number = 2 ** -1.5;
+  global count;
+  for (x of [1, "a\"b", true]) { console.log(`${x}!`, t.a[#1()]); }
+  "#;
+  let syntax_tree = super::Parser.parse_program_from_str(input).unwrap();
+  assert_eq!(dump_program(&syntax_tree), [
+    "(link \"site.json\")",
+    "(function warn",
+    "  (attribute export_as \"console.warn\")",
+    "  (parameters (message string))",
+    "  (returns string)",
+    "  (body (expression (call return_value message))))",
+    "(const LIMIT (type number) (** (integer 2) (neg (float 1.5))))",
+    "(global count)",
+    "(for x",
+    "  (array (integer 1) (string \"a\\\"b\") (bool true))",
+    "  (body",
+    "    (expression (call (member console log) (template x \"!\") (index (member t a) (call (block 1)))))))",
+    "",
+  ].join("\n"));
+}
+
+#[test]
+fn test_comment_parsing() {
+  use crate::parser::{self};
+
+  let parse = |input: &str| super::Parser.parse_program_from_str(input);
+  let expected = parse("function f() { g(1 + 2); }").unwrap().without_positions();
+  for input in [
+    "function f() { g(1 + 2); } // at the end of the file",
+    "function f() { g(1 + 2); }\n//",
+    "/* block */ function f() { g(1 /* inline */ + 2); }",
+    "/* nested /* block */ comments\n */ function f() { g(1 + 2); }",
+    "/**/ function f() { /* */ g(1 + 2); /***/ }",
+    // Doc comments which don't document anything are plain comments
+    "function f() { g(1 + 2);\n  /// before the brace\n}",
+    "function f() {\n  //// not documentation\n  g(1 + 2);\n  /// first\n  /// second\n}",
+    "function f() { g(1 + 2); }\n/// at the end of the file",
+    "function f() { g(1 + 2); }\n/// at the end\n/// of the file\n",
+  ] {
+    assert_eq!(parse(input).unwrap().without_positions(), expected, "{}", input);
+  }
+
+  // Doc comments belong to the function after them
+  let syntax_tree = parse("/// Adds\n/* not documentation */\n/// numbers\nfunction add() {}").unwrap();
+  let parser::Item::FunctionDeclaration(func) = &syntax_tree.main_block[0] else {
+    panic!("Expected function declaration");
+  };
+  let doc = func.attributes.iter().map(|attribute| match &attribute.arguments[0].value {
+    parser::AttributeValue::String(text) => text.as_str(),
+    _ => panic!("Expected doc comment text"),
+  }).collect::<Vec<&str>>();
+  assert_eq!(doc, ["Adds", "numbers"]);
+
+  let error = parse("g(); /* open /* nested */").unwrap_err().to_string();
+  assert!(error.contains("Block comment is never closed by \"*/\""), "{}", error);
+}
+
+#[test]
+fn test_nesting_limits() {
+  let parse = |input: &str| super::Parser.parse_program_from_str(input);
+  let nested = |open: &str, inner: &str, close: &str, depth: usize| format!("{}{}{}", open.repeat(depth), inner, close.repeat(depth));
+
+  assert!(parse(&format!("let x = {};", nested("(", "1", ")", 256))).is_ok());
+  // Brackets in strings and comments don't count
+  assert!(parse(&format!("let x = \"{}\"; // {}\n/* {} */", "(".repeat(300), "[".repeat(300), "{".repeat(300))).is_ok());
+
+  for (input, message) in [
+    (format!("let x = {};", nested("(", "1", ")", 257)), "Brackets are nested more than 256 levels deep"),
+    (format!("let x = {};", nested("[", "1", "]", 10000)), "Brackets are nested more than 256 levels deep"),
+    (nested("for (x of []) {", "", "}", 300), "Brackets are nested more than 256 levels deep"),
+    (format!("let x = `{}`;", nested("${`", "", "`}", 300)), "Brackets are nested more than 256 levels deep"),
+    (nested("/*", "", "*/", 300), "Block comments are nested more than 256 levels deep"),
+    (format!("let x = 1{};", "+1".repeat(1000)), "Expression is nested more than 1000 levels deep"),
+    (format!("let x = {}1;", "-".repeat(10000)), "Expression is nested more than 1000 levels deep"),
+  ] {
+    let error = parse(&input).unwrap_err().to_string();
+    assert!(error.contains(message), "{}", error);
+  }
+}
+
+#[test]
+fn test_formatting() {
+  use crate::parser::format::format_source;
+
+  let input = r#"link "site.json";
+// Adds numbers
+/// Adds
+#[export_as("add")] function add(a:number,b) : number { return_value(a+b); // sum
+}
+
+
+const LIMIT: number = 2 ** -1.5; /* trailing */
+let x = (1 + 2) * 3 - (4 - 5) - -(2 ** 3) ** 2 - (-a).b;
+for (let y of [1, 'a\n']) { console.log(`${y}!\` \${`, {a: 1, "b c": 2}, f(1 /* inline */ + 2));
+
+  z = very_long_function_name_to_wrap(first_argument_value, second_argument_value, [third_argument_value]);
+  // last
+}
+function empty() {}"#;
+  let expected = r#"link "site.json";
+// Adds numbers
+/// Adds
+#[export_as("add")]
+function add(a: number, b): number {
+  return_value(a + b); // sum
+}
+
+const LIMIT: number = 2 ** -1.5; /* trailing */
+let x = (1 + 2) * 3 - (4 - 5) - -(2 ** 3) ** 2 - (-a).b;
+for (y of [1, "a\n"]) {
+  /* inline */
+  console.log(`${y}!\` \${`, { a: 1, "b c": 2 }, f(1 + 2));
+
+  z = very_long_function_name_to_wrap(
+    first_argument_value,
+    second_argument_value,
+    [third_argument_value],
+  );
+  // last
+}
+function empty() {}
+"#;
+  let formatted = format_source(input).unwrap();
+  assert_eq!(formatted, expected);
+  assert_eq!(format_source(&formatted).unwrap(), formatted);
+
+  // Formatting keeps the syntax tree of every fixture, and formatted fixtures stay the same
+  let mut directories = vec![std::path::PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures"))];
+  while let Some(directory) = directories.pop() {
+    for entry in std::fs::read_dir(directory).unwrap() {
+      let path = entry.unwrap().path();
+      if path.is_dir() {
+        directories.push(path);
+      } else if path.extension().is_some_and(|extension| extension == "lxs")
+        && let Ok(formatted) = format_source(&std::fs::read_to_string(&path).unwrap()) {
+        assert_eq!(format_source(&formatted).unwrap(), formatted, "{}", path.display());
+      }
+    }
+  }
+}
@@ -0,0 +1,356 @@
+//! Simulator of CatWeb scripts, which runs generated scripts outside of CatWeb for `lync run` and tests.
+//!
+//! Only the actions emitted by the compiler are supported. UI objects are simulated as the properties written to them,
+//! and waits don't pause the simulation.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::codegen::{blocks, json};
+use crate::codegen::source_map::SourceMap;
+
+mod test;
+
+/// Depth of nested function calls at which the simulation stops, since the script likely never returns
+const MAX_CALL_DEPTH: usize = 200;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Value {
+  Text(String),
+  /// Tables are shared by the variables holding them
+  Table(Rc<RefCell<Table>>),
+}
+
+impl Value {
+  /// Text of the value, as CatWeb interpolates it
+  pub fn text(&self) -> String {
+    match self {
+      Value::Text(text) => text.clone(),
+      Value::Table(_) => "table".to_string(),
+    }
+  }
+}
+
+/// CatWeb table, with its entries in insertion order. Arrays have the keys "1", "2", ...
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Table {
+  pub entries: Vec<(String, Value)>,
+}
+
+/// Card whose actions run, which errors name
+struct Card {
+  index: usize,
+  description: String,
+}
+
+pub struct Simulator<'a> {
+  script: &'a json::Script,
+  source_map: Option<&'a SourceMap>,
+  variables: HashMap<String, Value>,
+  /// Properties of UI objects, by globalid and property
+  properties: HashMap<(String, String), String>,
+  output: Vec<String>,
+  call_depth: usize,
+}
+
+impl<'a> Simulator<'a> {
+  pub fn new(script: &'a json::Script) -> Self {
+    Self { script, source_map: None, variables: HashMap::new(), properties: HashMap::new(), output: Vec::new(), call_depth: 0 }
+  }
+
+  /// Errors start with the source location of the failing action if the source map has one
+  pub fn with_source_map(mut self, source_map: &'a SourceMap) -> Self {
+    self.source_map = Some(source_map);
+    self
+  }
+
+  /// Runs the event which runs when the website loads, if the script has one
+  pub fn run_load_event(&mut self) -> Result<(), anyhow::Error> {
+    let script = self.script;
+    for (index, card) in script.content.iter().enumerate() {
+      if let json::CodeCard::Event(event) = card && event.id == blocks::WEBSITE_LOADED {
+        self.run_actions(&event.actions, &Card { index, description: format!("event {}", event.id) }, 0)?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Lines logged by the script
+  pub fn output(&self) -> &[String] {
+    &self.output
+  }
+
+  pub fn property(&self, globalid: &str, property: &str) -> Option<&str> {
+    self.properties.get(&(globalid.to_string(), property.to_string())).map(|value| value.as_str())
+  }
+
+  /// Runs the actions of a card, of which `offset` actions precede them. Errors name the card and the position of the
+  /// failing action in it.
+  fn run_actions(&mut self, actions: &[json::Action], card: &Card, offset: usize) -> Result<(), anyhow::Error> {
+    let mut index = 0;
+    while index < actions.len() {
+      let action = &actions[index];
+      let position = offset + index;
+      if blocks::BLOCK_OPENERS.contains(&action.id.as_str()) {
+        let end = matching_end(actions, index)
+          .ok_or_else(|| locate_error(self.source_map, card, position, anyhow::anyhow!("Block is never closed by an \"end\" action")))?;
+        self.run_block(action, &actions[index + 1..end], card, position)?;
+        index = end + 1;
+        continue;
+      }
+
+      let source_map = self.source_map;
+      let located = |err: anyhow::Error| locate_error(source_map, card, position, err);
+      match action.id.as_str() {
+        // Errors in the called function name it instead
+        blocks::RUN_FUNCTION => {
+          let (index, func, values) = self.called_function(action).map_err(located)?;
+          self.call(index, func, values)?;
+        },
+        _ => self.run_action(action).map_err(located)?,
+      }
+      index += 1;
+    }
+    Ok(())
+  }
+
+  fn run_block(&mut self, action: &json::Action, body: &[json::Action], card: &Card, position: usize) -> Result<(), anyhow::Error> {
+    let source_map = self.source_map;
+    let located = |err: anyhow::Error| locate_error(source_map, card, position, err);
+    match action.id.as_str() {
+      blocks::IF_EQUAL => {
+        let [lhs, rhs] = arguments(action).map_err(located)?;
+        if self.value(lhs).text() == self.value(rhs).text() {
+          self.run_actions(body, card, position + 1)?;
+        }
+      },
+      blocks::ITERATE_TABLE => {
+        let [table, index, value] = arguments(action).map_err(located)?;
+        let entries = self.table(table).map_err(&located)?.borrow().entries.clone();
+        for (key, entry) in entries {
+          self.variables.insert(name(index), Value::Text(key));
+          self.variables.insert(name(value), entry);
+          self.run_actions(body, card, position + 1)?;
+        }
+      },
+      others => return Err(located(anyhow::anyhow!("Unsupported block \"{}\"", others))),
+    }
+    Ok(())
+  }
+
+  fn run_action(&mut self, action: &json::Action) -> Result<(), anyhow::Error> {
+    match action.id.as_str() {
+      blocks::LOG => {
+        let text = action.text.last().map(|field| self.value(field).text()).unwrap_or_default();
+        self.output.push(text);
+      },
+      blocks::SET_VARIABLE => {
+        let [variable, value] = arguments(action)?;
+        let value = self.value(value);
+        self.variables.insert(name(variable), value);
+      },
+      id @ (blocks::INCREASE_VARIABLE | blocks::SUBTRACT_VARIABLE | blocks::MULTIPLY_VARIABLE | blocks::DIVIDE_VARIABLE | blocks::POWER_VARIABLE) => {
+        let [variable, operand] = arguments(action)?;
+        let current = self.variables.get(&name(variable)).map(|value| value.text()).unwrap_or_default();
+        let lhs = number(&current)?;
+        let rhs = number(&self.value(operand).text())?;
+        let result = match id {
+          blocks::INCREASE_VARIABLE => lhs + rhs,
+          blocks::SUBTRACT_VARIABLE => lhs - rhs,
+          blocks::MULTIPLY_VARIABLE => lhs * rhs,
+          blocks::DIVIDE_VARIABLE => lhs / rhs,
+          _ => lhs.powf(rhs),
+        };
+        self.variables.insert(name(variable), Value::Text(format_number(result)));
+      },
+      blocks::WAIT => {
+        let [seconds] = arguments(action)?;
+        number(&self.value(seconds).text())?;
+      },
+      blocks::SET_PROPERTY => {
+        let [property, object, value] = arguments(action)?;
+        let value = self.value(value).text();
+        self.properties.insert((self.value(object).text(), self.value(property).text()), value);
+      },
+      blocks::GET_PROPERTY => {
+        let [property, object, result] = arguments(action)?;
+        let value = self.property(&self.value(object).text(), &self.value(property).text()).unwrap_or_default().to_string();
+        self.variables.insert(name(result), Value::Text(value));
+      },
+      blocks::CREATE_TABLE => {
+        let [result] = arguments(action)?;
+        self.variables.insert(name(result), Value::Table(Rc::default()));
+      },
+      blocks::SET_ENTRY => {
+        let [table, key, value] = arguments(action)?;
+        let (key, value) = (self.value(key).text(), self.value(value));
+        let mut table = self.table(table)?.borrow_mut();
+        match table.entries.iter_mut().find(|(other, _)| *other == key) {
+          Some((_, entry)) => *entry = value,
+          None => table.entries.push((key, value)),
+        }
+      },
+      blocks::GET_ENTRY => {
+        let [table, key, result] = arguments(action)?;
+        let key = self.value(key).text();
+        let value = self.table(table)?.borrow().entries.iter()
+          .find(|(other, _)| *other == key)
+          .map_or_else(|| Value::Text(String::new()), |(_, value)| value.clone());
+        self.variables.insert(name(result), value);
+      },
+      blocks::TABLE_LENGTH => {
+        let [table, result] = arguments(action)?;
+        let length = self.table(table)?.borrow().entries.len();
+        self.variables.insert(name(result), Value::Text(length.to_string()));
+      },
+      blocks::INSERT_ENTRY => {
+        let [table, value] = arguments(action)?;
+        let value = self.value(value);
+        let mut table = self.table(table)?.borrow_mut();
+        let key = (table.entries.len() + 1).to_string();
+        table.entries.push((key, value));
+      },
+      blocks::REMOVE_ENTRY => {
+        let [table, key] = arguments(action)?;
+        let key = self.value(key).text();
+        let mut table = self.table(table)?.borrow_mut();
+        table.entries.retain(|(other, _)| *other != key);
+        // Later entries of arrays move down, like in CatWeb
+        if let Ok(removed) = key.parse::<usize>() {
+          for (other, _) in table.entries.iter_mut() {
+            if let Ok(index) = other.parse::<usize>() && index > removed {
+              *other = (index - 1).to_string();
+            }
+          }
+        }
+      },
+      blocks::COMMENT => {},
+      blocks::END => return Err(anyhow::anyhow!("\"end\" action without a block to close")),
+      others => return Err(anyhow::anyhow!("Unsupported action \"{}\"", others)),
+    }
+    Ok(())
+  }
+
+  /// Returns the index of the card of the function a "run function" action calls, the function and the values of its
+  /// arguments
+  fn called_function(&self, action: &json::Action) -> Result<(usize, &'a json::FunctionDeclaration, Vec<Value>), anyhow::Error> {
+    let [function_name, json::TextFieldValue::Tuple(arguments), ..] = action.text.as_slice() else {
+      return Err(anyhow::anyhow!("Malformed \"run function\" action"));
+    };
+    let function_name = name(function_name);
+    let (index, func) = self.script.content.iter().enumerate().find_map(|(index, card)| match card {
+      json::CodeCard::FunctionDeclaration(func) if func.name() == function_name => Some((index, func)),
+      _ => None,
+    }).ok_or_else(|| anyhow::anyhow!("Undefined function \"{}\"", function_name))?;
+    if self.call_depth >= MAX_CALL_DEPTH {
+      return Err(anyhow::anyhow!("Calls are nested more than {} levels deep", MAX_CALL_DEPTH));
+    }
+    Ok((index, func, arguments.value.iter().map(|argument| self.value(argument)).collect()))
+  }
+
+  fn call(&mut self, index: usize, func: &json::FunctionDeclaration, values: Vec<Value>) -> Result<(), anyhow::Error> {
+    for (parameter, value) in func.variable_overrides.iter().zip(values) {
+      self.variables.insert(parameter.value.clone(), value);
+    }
+    self.call_depth += 1;
+    let card = Card { index, description: format!("function \"{}\"", func.name()) };
+    let result = self.run_actions(&func.actions, &card, 0);
+    self.call_depth -= 1;
+    result
+  }
+
+  /// Value of a text field, with the variables it mentions interpolated. A field naming just a variable holding a
+  /// table refers to the table.
+  fn value(&self, field: &json::TextFieldValue) -> Value {
+    let text = match field {
+      json::TextFieldValue::PlainText(text) => return Value::Text(text.clone()),
+      json::TextFieldValue::Parameter(parameter) => &parameter.value,
+      json::TextFieldValue::Tuple(tuple) => return Value::Text(tuple.value.iter().map(|field| self.value(field).text()).collect()),
+    };
+    if let Some(variable) = text.strip_prefix('{').and_then(|text| text.strip_suffix('}'))
+      && !variable.contains(['{', '}'])
+      && let Some(value @ Value::Table(_)) = self.variables.get(variable)
+    {
+      return value.clone();
+    }
+
+    let mut interpolated = String::new();
+    let mut chars = text.chars();
+    while let Some(char) = chars.next() {
+      match char {
+        '\\' => interpolated.extend(chars.next()),
+        '{' => {
+          let variable = chars.by_ref().take_while(|char| *char != '}').collect::<String>();
+          // Variables which are never set interpolate as nothing
+          interpolated.push_str(&self.variables.get(&variable).map(|value| value.text()).unwrap_or_default());
+        },
+        others => interpolated.push(others),
+      }
+    }
+    Value::Text(interpolated)
+  }
+
+  /// Table held by the variable a text field names
+  fn table(&self, field: &json::TextFieldValue) -> Result<&Rc<RefCell<Table>>, anyhow::Error> {
+    let variable = name(field);
+    match self.variables.get(&variable) {
+      Some(Value::Table(table)) => Ok(table),
+      _ => Err(anyhow::anyhow!("Variable \"{}\" doesn't hold a table", variable)),
+    }
+  }
+}
+
+/// Returns the index of the `end` action closing the block opened at `start`
+/// Names the card and position of the action an error happened at, after its source location if it's known
+fn locate_error(source_map: Option<&SourceMap>, card: &Card, position: usize, err: anyhow::Error) -> anyhow::Error {
+  match source_map.and_then(|source_map| source_map.find(card.index, Some(position))) {
+    Some(mapping) => anyhow::anyhow!("{}: In {}, action {}: {}", mapping.location(), card.description, position, err),
+    None => anyhow::anyhow!("In {}, action {}: {}", card.description, position, err),
+  }
+}
+
+fn matching_end(actions: &[json::Action], start: usize) -> Option<usize> {
+  let mut depth = 0;
+  for (index, action) in actions.iter().enumerate().skip(start) {
+    if blocks::BLOCK_OPENERS.contains(&action.id.as_str()) {
+      depth += 1;
+    } else if action.id == blocks::END {
+      depth -= 1;
+      if depth == 0 {
+        return Some(index);
+      }
+    }
+  }
+  None
+}
+
+fn arguments<const N: usize>(action: &json::Action) -> Result<&[json::TextFieldValue; N], anyhow::Error> {
+  <&[json::TextFieldValue; N]>::try_from(action.text.as_slice())
+    .map_err(|_| anyhow::anyhow!("Action \"{}\" expects {} fields, found {}", action.id, N, action.text.len()))
+}
+
+/// Text of a field naming a variable or function, without interpolation
+fn name(field: &json::TextFieldValue) -> String {
+  match field {
+    json::TextFieldValue::PlainText(text) => text.clone(),
+    json::TextFieldValue::Parameter(parameter) => parameter.value.clone(),
+    json::TextFieldValue::Tuple(_) => String::new(),
+  }
+}
+
+fn number(text: &str) -> Result<f64, anyhow::Error> {
+  match text.trim() {
+    // Variables which are never set count as zero
+    "" => Ok(0.0),
+    text => text.parse::<f64>().map_err(|_| anyhow::anyhow!("Expected a number, found \"{}\"", text)),
+  }
+}
+
+/// Formats numbers like CatWeb, without a fraction for whole numbers
+fn format_number(number: f64) -> String {
+  match number.fract() == 0.0 && number.abs() < 1e15 {
+    true => format!("{}", number as i64),
+    false => number.to_string(),
+  }
+}
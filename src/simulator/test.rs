@@ -0,0 +1,93 @@
+#[cfg(test)]
+fn simulate(input: &str) -> Result<Vec<String>, anyhow::Error> {
+  use crate::{codegen, compiler, parser};
+
+  let syntax_tree = parser::Parser::new().parse_program_from_str(input)?;
+  let program = compiler::Compiler::new(syntax_tree).compile()?;
+  let script = codegen::CWBlockScriptGenerator::new().generate_program(program);
+  let mut simulator = super::Simulator::new(&script);
+  simulator.run_load_event()?;
+  Ok(simulator.output().to_vec())
+}
+
+#[test]
+fn test_simulating_programs() {
+  assert_eq!(simulate(r#"
+  function greet(name: string) {
+    console.log(`Hi {${name}}!`);
+  }
+  let x = 2;
+  x = x * 3 + 1;
+  console.log(x);
+  greet("cat");
+  console.log(!false);
+  "#).unwrap(), ["7", "Hi {cat}!", "true"]);
+
+  // Tables are shared by the variables holding them
+  assert_eq!(simulate(r#"
+  let names = ["cat", "lynx"];
+  let same = names;
+  same.push("tiger");
+  names.remove(1);
+  for (name of names) {
+    console.log(name);
+    wait(0);
+  }
+  let counts = { cat: 1 };
+  counts.cat = counts.cat + names.length;
+  console.log(counts.cat);
+  "#).unwrap(), ["lynx", "tiger", "3"]);
+
+  // Recursive calls restore the variables of their callers from the call stack
+  assert_eq!(simulate(r#"
+  function countdown(n: number) {
+    let more = true;
+    #18(n, 0);
+    more = false;
+    #25();
+    #18(more, true);
+    countdown(n - 1);
+    #25();
+    console.log(n);
+  }
+  countdown(2);
+  "#).unwrap(), ["0", "1", "2"]);
+  assert_eq!(
+    simulate("function forever() { forever(); } forever();").unwrap_err().to_string(),
+    "In function \"forever\", action 0: Calls are nested more than 200 levels deep",
+  );
+}
+
+#[test]
+fn test_simulation_errors() {
+  use crate::codegen::json;
+  use crate::codegen::source_map::{Mapping, SourceMap};
+
+  let action = |id: &str, text: Vec<&str>| json::Action {
+    id: id.to_string(),
+    text: text.into_iter().map(|value| json::TextFieldValue::Parameter(json::Parameter { value: value.to_string(), ..Default::default() })).collect(),
+    ..Default::default()
+  };
+  let script = |actions: Vec<json::Action>| json::Script {
+    alias: String::new(),
+    class: "script".to_string(),
+    content: vec![json::CodeCard::Event(json::Event { id: "0".to_string(), actions, ..Default::default() })],
+  };
+
+  for (actions, message) in [
+    (vec![action("11", vec!["x", "a"]), action("12", vec!["x", "1"])], "In event 0, action 1: Expected a number, found \"a\""),
+    (vec![action("18", vec!["1", "1"])], "In event 0, action 0: Block is never closed by an \"end\" action"),
+    (vec![action("102", vec!["t", "1", "x"])], "In event 0, action 0: Variable \"t\" doesn't hold a table"),
+    (vec![action("999", vec![])], "In event 0, action 0: Unsupported action \"999\""),
+  ] {
+    let script = script(actions);
+    let error = super::Simulator::new(&script).run_load_event().unwrap_err();
+    assert_eq!(error.to_string(), message);
+  }
+
+  let script = script(vec![action("11", vec!["x", "a"]), action("12", vec!["x", "1"])]);
+  let mapping = Mapping { card: 0, action: Some(1), file: "main.lxs".to_string(), line: 2, column: 3 };
+  let source_map = SourceMap { mappings: vec![mapping] };
+  let error = super::Simulator::new(&script).with_source_map(&source_map).run_load_event().unwrap_err();
+  assert_eq!(error.to_string(), "main.lxs:2:3: In event 0, action 1: Expected a number, found \"a\"");
+}
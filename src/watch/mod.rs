@@ -0,0 +1,52 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+mod test;
+
+/// How often watched files are checked for changes
+pub const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(250);
+
+/// Watches files for changes by polling their modification times and sizes, which works on every platform without
+/// extra dependencies. Missing files are watched too, and count as changed when they are created.
+#[derive(Debug, Default)]
+pub struct FileWatcher {
+  states: HashMap<PathBuf, Option<FileState>>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct FileState {
+  modified: SystemTime,
+  len: u64,
+}
+
+impl FileWatcher {
+  /// Sets the watched files. Files which weren't watched before count as unchanged until they change.
+  pub fn watch(&mut self, files: impl IntoIterator<Item = PathBuf>) {
+    let mut states = HashMap::new();
+    for file in files {
+      let state = self.states.remove(&file).unwrap_or_else(|| file_state(&file));
+      states.insert(file, state);
+    }
+    self.states = states;
+  }
+
+  /// Returns the watched files which were modified, created or removed since the last poll
+  pub fn poll(&mut self) -> Vec<PathBuf> {
+    let mut changed = Vec::new();
+    for (file, state) in self.states.iter_mut() {
+      let current = file_state(file);
+      if current != *state {
+        *state = current;
+        changed.push(file.clone());
+      }
+    }
+    changed.sort();
+    changed
+  }
+}
+
+fn file_state(path: &Path) -> Option<FileState> {
+  let metadata = std::fs::metadata(path).ok()?;
+  Some(FileState { modified: metadata.modified().ok()?, len: metadata.len() })
+}
@@ -0,0 +1,31 @@
+#[test]
+fn test_file_watching() {
+  use super::FileWatcher;
+
+  let directory = std::env::temp_dir().join(format!("lync-watch-test-{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&directory);
+  std::fs::create_dir_all(&directory).unwrap();
+  let source = directory.join("main.lxs");
+  let site = directory.join("site.json");
+  std::fs::write(&source, "let x = 1;").unwrap();
+
+  let mut watcher = FileWatcher::default();
+  watcher.watch([source.clone(), site.clone()]);
+  assert!(watcher.poll().is_empty());
+
+  // Files are compared by size too, since modification times may be coarse
+  std::fs::write(&source, "let x = 12;").unwrap();
+  assert_eq!(watcher.poll(), std::slice::from_ref(&source));
+  assert!(watcher.poll().is_empty());
+
+  // Missing files change when they are created, and watched files when they are removed
+  std::fs::write(&site, "[]").unwrap();
+  std::fs::remove_file(&source).unwrap();
+  assert_eq!(watcher.poll(), [source.clone(), site.clone()]);
+
+  // Files which stay watched keep their state
+  std::fs::write(&site, "[{}]").unwrap();
+  watcher.watch([site.clone()]);
+  assert_eq!(watcher.poll(), [site]);
+  std::fs::remove_dir_all(&directory).unwrap();
+}
@@ -0,0 +1,102 @@
+//! Feeds malformed and unusual sources through the `lync` binary, and checks it exits with the documented codes: 1 for
+//! errors in the program and 2 for incorrect usage. A panic would exit with 101, and a stack overflow would abort.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Runs `lync` with the arguments and the input on stdin, from a directory without a manifest
+fn lync(args: &[&str], input: &[u8]) -> Option<i32> {
+  let directory = std::env::temp_dir().join("lync-exit-codes");
+  std::fs::create_dir_all(&directory).unwrap();
+  let mut child = Command::new(env!("CARGO_BIN_EXE_lync"))
+    .current_dir(&directory)
+    .args(args)
+    .stdin(Stdio::piped())
+    .stdout(Stdio::null())
+    .stderr(Stdio::null())
+    .spawn()
+    .unwrap();
+  // The command may exit before reading its input
+  let _ = child.stdin.take().unwrap().write_all(input);
+  child.wait().unwrap().code()
+}
+
+#[test]
+fn test_syntax_errors() {
+  let deep = |open: &str, close: &str, depth: usize| format!("let x = {}1{};", open.repeat(depth), close.repeat(depth));
+  for input in [
+    "()".to_string(),
+    "let x = ();".to_string(),
+    "/* unclosed".to_string(),
+    "\"unterminated".to_string(),
+    "let x = \"\\q\";".to_string(),
+    "console.log(\"\\u{110000}\");".to_string(),
+    "`${`".to_string(),
+    "let x = 1".to_string(),
+    "function f() {".to_string(),
+    "#[".to_string(),
+    deep("(", ")", 10000),
+    deep("[", "]", 100000),
+    deep("-", "", 20000),
+    format!("let x = 1{};", "+1".repeat(20000)),
+    format!("{}{}", "/*".repeat(10000), "*/".repeat(10000)),
+  ] {
+    for command in ["build", "run", "check", "fmt"] {
+      let start = input.chars().take(40).collect::<String>();
+      assert_eq!(lync(&[command, "-"], input.as_bytes()), Some(1), "lync {} - <<< {}", command, start);
+    }
+  }
+}
+
+#[test]
+fn test_program_errors() {
+  for input in [
+    "f();",
+    "for (x of 1) {}",
+    "let x = -\"a\";",
+    "function f(a: number) {} f(\"a\");",
+    "function f(): bool {} let x = f();",
+    "function f() {} function f() {}",
+    "#[inlien] function f() {}",
+    "#[deny(unused_variables)] let x = 1;",
+    "link \"missing.json\";",
+  ] {
+    for command in ["build", "run", "check"] {
+      assert_eq!(lync(&[command, "-"], input.as_bytes()), Some(1), "lync {} - <<< {}", command, input);
+    }
+  }
+}
+
+#[test]
+fn test_edge_case_sources() {
+  // Sources which build, with the exit code of running them
+  for (input, run_code) in [
+    ("", 0),
+    ("\n\n// Only a comment", 0),
+    ("/// Only a doc comment", 0),
+    ("let x = [1, 2][5]; console.log(x);", 0),
+    ("let x = 1 / 0; console.log(x);", 0),
+    ("let x = 99999999999999999999999999999; console.log(x);", 0),
+    ("console.log(`${1}${`${2}`}`);", 0),
+    ("let a = []; a[0] = a; console.log(a);", 0),
+    ("#0();", 0),
+    ("#99999(1);", 1),
+    ("let t = {}; console.log(t.a.b);", 1),
+    ("#[inline] function f() { f(); } f();", 1),
+  ] {
+    assert_eq!(lync(&["build", "-"], input.as_bytes()), Some(0), "lync build - <<< {}", input);
+    assert_eq!(lync(&["run", "-"], input.as_bytes()), Some(run_code), "lync run - <<< {}", input);
+  }
+}
+
+#[test]
+fn test_usage_errors() {
+  for (args, input) in [
+    (&["build", "-"][..], &b"\x00\xff"[..]),
+    (&["build", "missing.lxs"][..], &b""[..]),
+    (&["build", "--emit", "pdf", "-"][..], &b""[..]),
+    (&["fmt"][..], &b""[..]),
+  ] {
+    assert_eq!(lync(args, input), Some(2), "lync {}", args.join(" "));
+  }
+}
@@ -0,0 +1,106 @@
+//! Golden tests, which build every `.lxs` file of `tests/fixtures` and compare the outputs with the files next to it:
+//! the indented JSON with `.json`, the lowered program with `.ir` and the diagnostics with `.stderr`. Outputs which
+//! are empty, like the JSON of a program which doesn't compile, have no file.
+//!
+//! Run `cargo test --test golden -- --bless` to write the current outputs to the expectation files. Other arguments
+//! filter the fixtures by path.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+
+/// Output of a fixture, compared with the file of the extension
+struct Output {
+  extension: &'static str,
+  content: String,
+}
+
+fn main() {
+  let args: Vec<String> = std::env::args().skip(1).collect();
+  let bless = args.iter().any(|arg| arg == "--bless");
+  let filters: Vec<&String> = args.iter().filter(|arg| !arg.starts_with('-')).collect();
+
+  let mut fixtures = Vec::new();
+  find_fixtures(Path::new(FIXTURES), &mut fixtures);
+  fixtures.sort();
+  fixtures.retain(|fixture| filters.is_empty() || filters.iter().any(|filter| fixture.to_string_lossy().contains(filter.as_str())));
+
+  println!("\nrunning {} golden tests", fixtures.len());
+  let mut failed = Vec::new();
+  for fixture in &fixtures {
+    let name = fixture.strip_prefix(FIXTURES).unwrap_or(fixture).display().to_string();
+    let mut mismatches = Vec::new();
+    for output in outputs(fixture) {
+      let path = fixture.with_extension(output.extension);
+      let expected = std::fs::read_to_string(&path).unwrap_or_default();
+      if output.content == expected {
+        continue;
+      }
+      if bless {
+        match output.content.is_empty() {
+          true => std::fs::remove_file(&path).unwrap(),
+          false => std::fs::write(&path, &output.content).unwrap(),
+        }
+      } else {
+        mismatches.push(format!("{}\nexpected:\n{}\nactual:\n{}", path.display(), expected, output.content));
+      }
+    }
+    match mismatches.is_empty() {
+      true => println!("test {} ... ok", name),
+      false => {
+        println!("test {} ... FAILED", name);
+        failed.push((name, mismatches));
+      },
+    }
+  }
+
+  for (name, mismatches) in &failed {
+    println!("\n---- {} ----", name);
+    for mismatch in mismatches {
+      println!("{}", mismatch);
+    }
+  }
+  let result = match failed.is_empty() {
+    true => "ok",
+    false => "FAILED",
+  };
+  println!("\ntest result: {}. {} passed; {} failed\n", result, fixtures.len() - failed.len(), failed.len());
+  if !failed.is_empty() {
+    println!("Run `cargo test --test golden -- --bless` to update the expectations if the changes are intended");
+    std::process::exit(1);
+  }
+}
+
+fn find_fixtures(directory: &Path, fixtures: &mut Vec<PathBuf>) {
+  let entries = std::fs::read_dir(directory).unwrap_or_else(|err| panic!("Failed to read {}: {}", directory.display(), err));
+  for entry in entries {
+    let path = entry.unwrap().path();
+    if path.is_dir() {
+      find_fixtures(&path, fixtures);
+    } else if path.extension().is_some_and(|extension| extension == "lxs") {
+      fixtures.push(path);
+    }
+  }
+}
+
+/// Builds the fixture from its directory, so paths in diagnostics don't depend on where the repository is
+fn outputs(fixture: &Path) -> [Output; 3] {
+  let directory = fixture.parent().unwrap();
+  let file_name = fixture.file_name().unwrap();
+  let build = |emit: &str| {
+    Command::new(env!("CARGO_BIN_EXE_lync"))
+      .current_dir(directory)
+      .args(["build", "--color", "never", "--emit", emit])
+      .arg(file_name)
+      .output()
+      .unwrap()
+  };
+  let json = build("json-pretty");
+  let ir = build("ir");
+  [
+    Output { extension: "json", content: String::from_utf8(json.stdout).unwrap() },
+    Output { extension: "ir", content: String::from_utf8(ir.stdout).unwrap() },
+    Output { extension: "stderr", content: String::from_utf8(json.stderr).unwrap() },
+  ]
+}